@@ -0,0 +1,153 @@
+use anyhow::Result;
+
+use super::{Command, CommandStage, ParsedLine, ShellCtx, Word, WordPart};
+
+/// What a simple command starting with an alias expanded to.
+pub enum Aliased {
+    /// Another simple command, which takes the place of the original.
+    Simple(CommandStage),
+    /// Anything else, as for `alias x='cd ~; ls'`, with the aliases it took
+    /// to get there, which are not expanded again while it runs.
+    Line(ParsedLine, Vec<String>),
+}
+
+/// Expands the alias the first word of `stage` names, repeatedly while the
+/// result starts with another one. `None` when it is not an alias.
+///
+/// An alias is not expanded again inside its own value, so `alias ls='ls -a'`
+/// runs `ls -a`, and `alias a=b b=a` stops at `a`.
+pub fn expand(stage: &CommandStage, ctx: &ShellCtx) -> Result<Option<Aliased>> {
+    let mut used = ctx.active_aliases().to_vec();
+    let Some(mut text) = expand_words(&stage.words, ctx, &mut used) else {
+        return Ok(None);
+    };
+    let mut current = stage.clone();
+
+    loop {
+        let expanded = match single_stage(crate::parse(&text)?) {
+            Ok(expanded) => expanded,
+            Err(line) => return Ok(Some(Aliased::Line(line, used))),
+        };
+
+        // The redirects and assignments around the alias still apply.
+        current = CommandStage {
+            assignments: current
+                .assignments
+                .into_iter()
+                .chain(expanded.assignments)
+                .collect(),
+            words: expanded.words,
+            redirects: expanded
+                .redirects
+                .into_iter()
+                .chain(current.redirects)
+                .collect(),
+        };
+
+        match expand_words(&current.words, ctx, &mut used) {
+            Some(next) => text = next,
+            None => return Ok(Some(Aliased::Simple(current))),
+        }
+    }
+}
+
+/// Replaces the first of `words` with the value of the alias it names,
+/// giving the text of the resulting command. When the value ends in a blank
+/// the word after it is expanded as well, so that `alias sudo='sudo '`
+/// makes `sudo ll` work.
+fn expand_words(words: &[Word], ctx: &ShellCtx, used: &mut Vec<String>) -> Option<String> {
+    let (first, rest) = words.split_first()?;
+    let name = alias_name(first)?;
+    if used.iter().any(|used| used == name) {
+        return None;
+    }
+    let value = ctx.alias(name)?;
+    used.push(name.to_string());
+
+    let rest = match value.ends_with([' ', '\t']) {
+        true => expand_words(rest, ctx, used),
+        false => None,
+    }
+    .unwrap_or_else(|| join(rest));
+
+    Some(format!("{value} {rest}"))
+}
+
+/// The name in a word that may be an alias, which must be unquoted.
+fn alias_name(word: &Word) -> Option<&str> {
+    match word.0.as_slice() {
+        [WordPart::Literal(name)] => Some(name),
+        _ => None,
+    }
+}
+
+fn join(words: &[Word]) -> String {
+    words
+        .iter()
+        .map(Word::to_string)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn single_stage(line: ParsedLine) -> Result<CommandStage, ParsedLine> {
+    match line {
+        ParsedLine::Pipeline(mut stages) if stages.len() == 1 => match stages.remove(0) {
+            Command::Simple(stage) => Ok(stage),
+            stage => Err(ParsedLine::Pipeline(vec![stage])),
+        },
+        line => Err(line),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Expands the aliases at the start of `line`, giving the simple
+    /// command it becomes.
+    fn expand_line(line: &str, ctx: &ShellCtx) -> Option<String> {
+        let Ok(stage) = single_stage(crate::parse(line).unwrap()) else {
+            panic!("{line} is not a simple command");
+        };
+        match expand(&stage, ctx).unwrap()? {
+            Aliased::Simple(stage) => Some(stage.to_string()),
+            Aliased::Line(line, _) => panic!("{line:?} is not a simple command"),
+        }
+    }
+
+    fn ctx_with(aliases: &[(&str, &str)]) -> ShellCtx {
+        let mut ctx = ShellCtx::build().unwrap();
+        for (name, value) in aliases {
+            ctx.set_alias(name, value.to_string());
+        }
+        ctx
+    }
+
+    #[test]
+    fn recursion_stops() {
+        let ctx = ctx_with(&[("ls", "ls -a"), ("a", "b"), ("b", "a"), ("ll", "ls -l")]);
+        assert_eq!(expand_line("ls x", &ctx).unwrap(), "ls -a x");
+        assert_eq!(expand_line("a", &ctx).unwrap(), "a");
+        assert_eq!(expand_line("b 1", &ctx).unwrap(), "b 1");
+        assert_eq!(expand_line("ll", &ctx).unwrap(), "ls -a -l");
+        assert_eq!(expand_line("cat ls", &ctx), None);
+        assert_eq!(expand_line("'ls'", &ctx), None);
+    }
+
+    #[test]
+    fn trailing_blank() {
+        let ctx = ctx_with(&[("sudo", "sudo "), ("ll", "ls -l"), ("e", "echo")]);
+        assert_eq!(expand_line("sudo ll /", &ctx).unwrap(), "sudo ls -l /");
+        assert_eq!(expand_line("e ll", &ctx).unwrap(), "echo ll");
+    }
+
+    #[test]
+    fn redirects_and_assignments_kept() {
+        let ctx = ctx_with(&[("say", "X=2 echo >out"), ("e", "echo")]);
+        assert_eq!(
+            expand_line("Y=1 say hi 2>err", &ctx).unwrap(),
+            "Y=1 X=2 echo hi > out 2> err"
+        );
+        assert_eq!(expand_line("e a >f", &ctx).unwrap(), "echo a > f");
+    }
+}
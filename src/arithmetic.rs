@@ -0,0 +1,508 @@
+use anyhow::Result;
+
+use super::{ShellCtx, ShellError};
+
+/// How deeply variables holding expressions may refer to one another.
+const MAX_DEPTH: usize = 1024;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(i64),
+    Name(String),
+    Op(&'static str),
+}
+
+/// Operators, longest first so that `<<=` is not read as `<<` and `=`.
+const OPERATORS: [&str; 39] = [
+    "<<=", ">>=", "**", "++", "--", "<<", ">>", "<=", ">=", "==", "!=", "&&", "||", "+=", "-=",
+    "*=", "/=", "%=", "&=", "^=", "|=", "+", "-", "*", "/", "%", "<", ">", "&", "|", "^", "!", "~",
+    "?", ":", "=", ",", "(", ")",
+];
+
+#[derive(Debug)]
+enum Expr {
+    Number(i64),
+    Variable(String),
+    Unary(&'static str, Box<Expr>),
+    Binary(&'static str, Box<Expr>, Box<Expr>),
+    /// `name = value`, or `name op= value` with the operator.
+    Assign(String, Option<&'static str>, Box<Expr>),
+    /// `++name`, `name--` and so on.
+    Step {
+        name: String,
+        delta: i64,
+        prefix: bool,
+    },
+    Ternary(Box<Expr>, Box<Expr>, Box<Expr>),
+}
+
+/// Evaluates an integer expression as in `$((...))`, assigning to shell
+/// variables as it goes.
+pub fn evaluate(expression: &str, ctx: &mut ShellCtx) -> Result<i64> {
+    eval_text(expression, ctx, 0)
+}
+
+fn eval_text(expression: &str, ctx: &mut ShellCtx, depth: usize) -> Result<i64> {
+    let error = |reason: &str| ShellError::Execution(format!("{expression}: {reason}"));
+
+    let tokens = tokenize(expression).map_err(|(reason, at)| {
+        error(&format!(
+            "{reason} (error token is \"{}\")",
+            &expression[at..]
+        ))
+    })?;
+    if tokens.is_empty() {
+        return Ok(0);
+    }
+
+    let mut parser = Parser { tokens, pos: 0 };
+    let expr = parser
+        .comma()
+        .and_then(|expr| match parser.tokens.get(parser.pos) {
+            None => Ok(expr),
+            Some(_) => Err("syntax error in expression"),
+        });
+    let expr = expr.map_err(|reason| {
+        let token = parser.error_token(expression);
+        error(&format!("{reason} (error token is \"{token}\")"))
+    })?;
+
+    // Errors from variables holding expressions already name their own.
+    eval(&expr, ctx, depth).map_err(|e| match e.downcast::<EvalError>() {
+        Ok(EvalError(reason)) => error(reason).into(),
+        Err(e) => e,
+    })
+}
+
+/// A failure while evaluating, before it is tied to the expression.
+#[derive(Debug, thiserror::Error)]
+#[error("{0}")]
+struct EvalError(&'static str);
+
+/// Splits an expression into tokens, returning an error with the offset it
+/// was found at.
+fn tokenize(input: &str) -> Result<Vec<(Token, usize)>, (&'static str, usize)> {
+    let mut tokens = Vec::new();
+    let mut idx = 0;
+
+    while idx < input.len() {
+        let rest = &input[idx..];
+        let c = rest.chars().next().expect("not at the end of the input");
+
+        if c.is_whitespace() {
+            idx += c.len_utf8();
+            continue;
+        }
+
+        if c.is_ascii_digit() {
+            let len = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || matches!(c, '#' | '_' | '@')))
+                .unwrap_or(rest.len());
+            let value = parse_number(&rest[..len]).ok_or(("value too great for base", idx))?;
+            tokens.push((Token::Number(value), idx));
+            idx += len;
+            continue;
+        }
+
+        if c.is_ascii_alphabetic() || c == '_' {
+            let len = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len());
+            tokens.push((Token::Name(rest[..len].to_string()), idx));
+            idx += len;
+            continue;
+        }
+
+        let op = OPERATORS
+            .iter()
+            .find(|op| rest.starts_with(*op))
+            .ok_or(("syntax error: invalid arithmetic operator", idx))?;
+        tokens.push((Token::Op(op), idx));
+        idx += op.len();
+    }
+
+    Ok(tokens)
+}
+
+/// Parses an integer constant: decimal, octal with a leading `0`, hex with
+/// `0x`, or `base#digits` for bases 2 to 64.
+pub fn parse_number(text: &str) -> Option<i64> {
+    let (base, digits) = if let Some((base, digits)) = text.split_once('#') {
+        (
+            base.parse::<u32>()
+                .ok()
+                .filter(|base| (2..=64).contains(base))?,
+            digits,
+        )
+    } else if let Some(hex) = text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        (16, hex)
+    } else if text.len() > 1 && text.starts_with('0') {
+        (8, &text[1..])
+    } else {
+        (10, text)
+    };
+
+    if digits.is_empty() {
+        return None;
+    }
+
+    digits.chars().try_fold(0i64, |value, c| {
+        let digit = match c {
+            '0'..='9' => c as u32 - '0' as u32,
+            'a'..='z' => c as u32 - 'a' as u32 + 10,
+            'A'..='Z' if base <= 36 => c as u32 - 'A' as u32 + 10,
+            'A'..='Z' => c as u32 - 'A' as u32 + 36,
+            '@' => 62,
+            '_' => 63,
+            _ => return None,
+        };
+        (digit < base).then(|| value.wrapping_mul(base as i64).wrapping_add(digit as i64))
+    })
+}
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    pos: usize,
+}
+
+type ParseResult = Result<Expr, &'static str>;
+
+/// Binary operators from the loosest binding to the tightest, below the
+/// ternary operator.
+const BINARY: [&[&str]; 10] = [
+    &["||"],
+    &["&&"],
+    &["|"],
+    &["^"],
+    &["&"],
+    &["==", "!="],
+    &["<", ">", "<=", ">="],
+    &["<<", ">>"],
+    &["+", "-"],
+    &["*", "/", "%"],
+];
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(token, _)| token)
+    }
+
+    fn next_op(&mut self, ops: &[&str]) -> Option<&'static str> {
+        match self.peek() {
+            Some(Token::Op(op)) if ops.contains(op) => {
+                let op = *op;
+                self.pos += 1;
+                Some(op)
+            }
+            _ => None,
+        }
+    }
+
+    /// The rest of the expression from where parsing failed, or the last
+    /// token when it ended too early.
+    fn error_token<'a>(&self, expression: &'a str) -> &'a str {
+        match self.tokens.get(self.pos).or(self.tokens.last()) {
+            Some((_, at)) => expression[*at..].trim_end(),
+            None => "",
+        }
+    }
+
+    fn comma(&mut self) -> ParseResult {
+        let mut expr = self.assignment()?;
+        while self.next_op(&[","]).is_some() {
+            expr = Expr::Binary(",", Box::new(expr), Box::new(self.assignment()?));
+        }
+        Ok(expr)
+    }
+
+    fn assignment(&mut self) -> ParseResult {
+        const ASSIGNMENTS: [&str; 11] = [
+            "=", "*=", "/=", "%=", "+=", "-=", "<<=", ">>=", "&=", "^=", "|=",
+        ];
+
+        if let Some(Token::Name(name)) = self.peek()
+            && let Some((Token::Op(op), _)) = self.tokens.get(self.pos + 1)
+            && ASSIGNMENTS.contains(op)
+        {
+            let name = name.clone();
+            let op = op.strip_suffix('=').filter(|op| !op.is_empty());
+            let op = op.map(|op| {
+                *OPERATORS
+                    .iter()
+                    .find(|known| **known == op)
+                    .expect("every compound assignment has a binary operator")
+            });
+            self.pos += 2;
+            return Ok(Expr::Assign(name, op, Box::new(self.assignment()?)));
+        }
+
+        let expr = self.ternary()?;
+        match self.peek() {
+            Some(Token::Op(op)) if ASSIGNMENTS.contains(op) => {
+                Err("attempted assignment to non-variable")
+            }
+            _ => Ok(expr),
+        }
+    }
+
+    fn ternary(&mut self) -> ParseResult {
+        let condition = self.binary(0)?;
+        if self.next_op(&["?"]).is_none() {
+            return Ok(condition);
+        }
+
+        let then = self.comma()?;
+        if self.next_op(&[":"]).is_none() {
+            return Err("`:' expected for conditional expression");
+        }
+        let otherwise = self.assignment()?;
+
+        Ok(Expr::Ternary(
+            Box::new(condition),
+            Box::new(then),
+            Box::new(otherwise),
+        ))
+    }
+
+    fn binary(&mut self, level: usize) -> ParseResult {
+        let Some(ops) = BINARY.get(level) else {
+            return self.power();
+        };
+
+        let mut expr = self.binary(level + 1)?;
+        while let Some(op) = self.next_op(ops) {
+            expr = Expr::Binary(op, Box::new(expr), Box::new(self.binary(level + 1)?));
+        }
+        Ok(expr)
+    }
+
+    fn power(&mut self) -> ParseResult {
+        let base = self.unary()?;
+        match self.next_op(&["**"]) {
+            Some(op) => Ok(Expr::Binary(op, Box::new(base), Box::new(self.power()?))),
+            None => Ok(base),
+        }
+    }
+
+    fn unary(&mut self) -> ParseResult {
+        if let Some(op) = self.next_op(&["++", "--"]) {
+            let Some(Token::Name(name)) = self.peek() else {
+                return Err("syntax error: operand expected");
+            };
+            let name = name.clone();
+            self.pos += 1;
+            return Ok(Expr::Step {
+                name,
+                delta: if op == "++" { 1 } else { -1 },
+                prefix: true,
+            });
+        }
+
+        match self.next_op(&["-", "+", "!", "~"]) {
+            Some(op) => Ok(Expr::Unary(op, Box::new(self.unary()?))),
+            None => self.postfix(),
+        }
+    }
+
+    fn postfix(&mut self) -> ParseResult {
+        let expr = self.primary()?;
+        if let Expr::Variable(name) = &expr
+            && let Some(op) = self.next_op(&["++", "--"])
+        {
+            return Ok(Expr::Step {
+                name: name.clone(),
+                delta: if op == "++" { 1 } else { -1 },
+                prefix: false,
+            });
+        }
+        Ok(expr)
+    }
+
+    fn primary(&mut self) -> ParseResult {
+        let token = self.peek().cloned();
+        match token {
+            Some(Token::Number(value)) => {
+                self.pos += 1;
+                Ok(Expr::Number(value))
+            }
+            Some(Token::Name(name)) => {
+                self.pos += 1;
+                Ok(Expr::Variable(name))
+            }
+            Some(Token::Op("(")) => {
+                self.pos += 1;
+                let expr = self.comma()?;
+                match self.next_op(&[")"]) {
+                    Some(_) => Ok(expr),
+                    None => Err("missing `)'"),
+                }
+            }
+            _ => Err("syntax error: operand expected"),
+        }
+    }
+}
+
+fn eval(expr: &Expr, ctx: &mut ShellCtx, depth: usize) -> Result<i64> {
+    let value = match expr {
+        Expr::Number(value) => *value,
+        Expr::Variable(name) => variable(name, ctx, depth)?,
+        Expr::Unary(op, operand) => {
+            let operand = eval(operand, ctx, depth)?;
+            match *op {
+                "-" => operand.wrapping_neg(),
+                "!" => (operand == 0) as i64,
+                "~" => !operand,
+                _ => operand,
+            }
+        }
+        Expr::Binary("&&", left, right) => {
+            (eval(left, ctx, depth)? != 0 && eval(right, ctx, depth)? != 0) as i64
+        }
+        Expr::Binary("||", left, right) => {
+            (eval(left, ctx, depth)? != 0 || eval(right, ctx, depth)? != 0) as i64
+        }
+        Expr::Binary(op, left, right) => {
+            let left = eval(left, ctx, depth)?;
+            let right = eval(right, ctx, depth)?;
+            apply(op, left, right)?
+        }
+        Expr::Assign(name, op, value) => {
+            let mut value = eval(value, ctx, depth)?;
+            if let Some(op) = op {
+                value = apply(op, variable(name, ctx, depth)?, value)?;
+            }
+            ctx.set_var(name, value.to_string())?;
+            value
+        }
+        Expr::Step {
+            name,
+            delta,
+            prefix,
+        } => {
+            let old = variable(name, ctx, depth)?;
+            let new = old.wrapping_add(*delta);
+            ctx.set_var(name, new.to_string())?;
+            if *prefix { new } else { old }
+        }
+        Expr::Ternary(condition, then, otherwise) => match eval(condition, ctx, depth)? {
+            0 => eval(otherwise, ctx, depth)?,
+            _ => eval(then, ctx, depth)?,
+        },
+    };
+
+    Ok(value)
+}
+
+fn apply(op: &str, left: i64, right: i64) -> Result<i64> {
+    let value = match op {
+        "," => right,
+        "|" => left | right,
+        "^" => left ^ right,
+        "&" => left & right,
+        "==" => (left == right) as i64,
+        "!=" => (left != right) as i64,
+        "<" => (left < right) as i64,
+        ">" => (left > right) as i64,
+        "<=" => (left <= right) as i64,
+        ">=" => (left >= right) as i64,
+        "<<" => left.wrapping_shl(right as u32),
+        ">>" => left.wrapping_shr(right as u32),
+        "+" => left.wrapping_add(right),
+        "-" => left.wrapping_sub(right),
+        "*" => left.wrapping_mul(right),
+        "/" | "%" if right == 0 => return Err(EvalError("division by 0").into()),
+        "/" => left.wrapping_div(right),
+        "%" => left.wrapping_rem(right),
+        "**" if right < 0 => return Err(EvalError("exponent less than 0").into()),
+        "**" => (0..right).fold(1i64, |value, _| value.wrapping_mul(left)),
+        _ => unreachable!("unknown binary operator {op}"),
+    };
+
+    Ok(value)
+}
+
+/// The value of a variable, where an unset or empty one counts as 0 and one
+/// holding an expression is evaluated in turn.
+fn variable(name: &str, ctx: &mut ShellCtx, depth: usize) -> Result<i64> {
+    let value = ctx.get_var(name).unwrap_or_default();
+    let value = value.trim();
+    if value.is_empty() {
+        return Ok(0);
+    }
+    if let Some(value) = parse_number(value) {
+        return Ok(value);
+    }
+    if depth >= MAX_DEPTH {
+        return Err(EvalError("expression recursion level exceeded").into());
+    }
+
+    eval_text(value, ctx, depth + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval_str(expression: &str) -> Result<i64> {
+        let mut ctx = ShellCtx::build().unwrap();
+        evaluate(expression, &mut ctx)
+    }
+
+    #[test]
+    fn precedence() {
+        assert_eq!(eval_str("1 + 2 * 3").unwrap(), 7);
+        assert_eq!(eval_str("(1 + 2) * 3").unwrap(), 9);
+        assert_eq!(eval_str("2 ** 3 ** 2").unwrap(), 512);
+        assert_eq!(eval_str("-2 ** 2").unwrap(), 4);
+        assert_eq!(eval_str("1 < 2 && 3 == 3 || 0").unwrap(), 1);
+        assert_eq!(eval_str("6 & 3 | 8 ^ 1").unwrap(), 11);
+        assert_eq!(eval_str("1 << 4 >> 2").unwrap(), 4);
+        assert_eq!(eval_str("0 ? 1 : 2 ? 3 : 4").unwrap(), 3);
+        assert_eq!(eval_str("!0 + ~0").unwrap(), 0);
+        assert_eq!(eval_str("7 / 2, 7 % 2").unwrap(), 1);
+        assert_eq!(eval_str("").unwrap(), 0);
+    }
+
+    #[test]
+    fn numbers() {
+        assert_eq!(parse_number("0x1f"), Some(31));
+        assert_eq!(parse_number("017"), Some(15));
+        assert_eq!(parse_number("2#101"), Some(5));
+        assert_eq!(parse_number("64#_"), Some(63));
+        assert_eq!(parse_number("09"), None);
+        assert_eq!(parse_number("1#1"), None);
+    }
+
+    #[test]
+    fn variables() {
+        let mut ctx = ShellCtx::build().unwrap();
+        assert_eq!(evaluate("x = 5, y = x++ + ++x", &mut ctx).unwrap(), 12);
+        assert_eq!(ctx.get_var("x").as_deref(), Some("7"));
+        assert_eq!(evaluate("x *= 2, x -= 4", &mut ctx).unwrap(), 10);
+        assert_eq!(evaluate("unset_var + 1", &mut ctx).unwrap(), 1);
+
+        ctx.set_var("e", "x + 1".to_string()).unwrap();
+        assert_eq!(evaluate("e * 2", &mut ctx).unwrap(), 22);
+        assert_eq!(evaluate("0 && (z = 1)", &mut ctx).unwrap(), 0);
+        assert_eq!(ctx.get_var("z"), None);
+    }
+
+    #[test]
+    fn errors() {
+        let message = |expression| eval_str(expression).unwrap_err().to_string();
+
+        assert_eq!(message("1 / 0"), "1 / 0: division by 0");
+        assert_eq!(
+            message("1 +"),
+            "1 +: syntax error: operand expected (error token is \"+\")"
+        );
+        assert_eq!(
+            message("2 = 3"),
+            "2 = 3: attempted assignment to non-variable (error token is \"= 3\")"
+        );
+        assert_eq!(
+            message("1 2"),
+            "1 2: syntax error in expression (error token is \"2\")"
+        );
+    }
+}
@@ -0,0 +1,341 @@
+use std::{fmt, rc::Rc};
+
+use super::{Redirect, Redirection};
+
+/// A line of input as produced by the parser. Words are kept unexpanded so
+/// that each command sees the effects of the ones that ran before it.
+#[derive(Debug)]
+pub enum ParsedLine {
+    Pipeline(Vec<Command>),
+    And(Box<ParsedLine>, Box<ParsedLine>),
+    Or(Box<ParsedLine>, Box<ParsedLine>),
+    Background(Box<ParsedLine>),
+    Sequence(Vec<ParsedLine>),
+}
+
+/// A single stage of a pipeline.
+#[derive(Debug)]
+pub enum Command {
+    Simple(CommandStage),
+    /// A compound command with the redirects that follow it, as in
+    /// `while read line; do ...; done < file`.
+    Compound(Compound, Vec<Redirection>),
+    /// A function definition, which only takes effect when it runs.
+    Function(Rc<Function>),
+}
+
+/// A function, `name() { ...; }` or `function name { ...; }`.
+#[derive(Debug)]
+pub struct Function {
+    pub name: String,
+    /// A compound command with its redirects.
+    pub body: Command,
+}
+
+#[derive(Debug, Clone)]
+pub struct CommandStage {
+    pub assignments: Vec<(String, Word)>,
+    pub words: Vec<Word>,
+    pub redirects: Vec<Redirection>,
+}
+
+#[derive(Debug)]
+pub enum Compound {
+    /// `{ ...; }`, run in the current shell.
+    Group(Box<ParsedLine>),
+    /// `( ... )`, run in a forked copy of the shell so that `cd` and
+    /// variable changes do not outlive it.
+    Subshell(Box<ParsedLine>),
+    /// `if` with its `elif` branches as condition and body pairs.
+    If {
+        branches: Vec<(ParsedLine, ParsedLine)>,
+        otherwise: Option<Box<ParsedLine>>,
+    },
+    /// `while`, or `until` when the condition is negated.
+    Loop {
+        until: bool,
+        condition: Box<ParsedLine>,
+        body: Box<ParsedLine>,
+    },
+    /// `for name in words`, where leaving out `in` loops over the
+    /// positional parameters.
+    For {
+        name: String,
+        words: Option<Vec<Word>>,
+        body: Box<ParsedLine>,
+    },
+    /// `for ((init; condition; step))`.
+    ArithmeticFor {
+        init: Word,
+        condition: Word,
+        step: Word,
+        body: Box<ParsedLine>,
+    },
+    Case {
+        word: Word,
+        items: Vec<CaseItem>,
+    },
+    /// A `((...))` command, true when the expression is non-zero.
+    Arithmetic(Word),
+}
+
+/// The patterns of a `case` item and the commands run when one matches.
+#[derive(Debug)]
+pub struct CaseItem {
+    pub patterns: Vec<Word>,
+    pub body: ParsedLine,
+}
+
+/// A shell word made up of adjacent, differently quoted parts.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Word(pub Vec<WordPart>);
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum WordPart {
+    /// Unquoted text.
+    Literal(String),
+    /// Single-quoted or escaped text, taken as is.
+    Quoted(String),
+    /// An unquoted `$NAME`.
+    Variable(String),
+    /// The contents of a double-quoted string.
+    DoubleQuoted(Vec<WordPart>),
+    /// A command substitution, `$(...)` or backticks, holding the command.
+    Command(String),
+    /// The expression of a `$((...))`, expanded like a double-quoted string.
+    Arithmetic(Vec<WordPart>),
+}
+
+impl ParsedLine {
+    /// Whether the line is empty, as the body of a `case` item may be.
+    pub fn is_empty(&self) -> bool {
+        matches!(self, ParsedLine::Sequence(lines) if lines.is_empty())
+    }
+
+    fn ends_in_background(&self) -> bool {
+        match self {
+            ParsedLine::Background(_) => true,
+            ParsedLine::Sequence(lines) => lines.last().is_some_and(Self::ends_in_background),
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for ParsedLine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParsedLine::Pipeline(stages) => {
+                for (i, stage) in stages.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " | ")?;
+                    }
+                    write!(f, "{stage}")?;
+                }
+                Ok(())
+            }
+            ParsedLine::And(left, right) => write!(f, "{left} && {right}"),
+            ParsedLine::Or(left, right) => write!(f, "{left} || {right}"),
+            ParsedLine::Background(line) => write!(f, "{line} &"),
+            ParsedLine::Sequence(lines) => {
+                for (i, line) in lines.iter().enumerate() {
+                    match i {
+                        0 => write!(f, "{line}")?,
+                        _ if lines[i - 1].ends_in_background() => write!(f, " {line}")?,
+                        _ => write!(f, "; {line}")?,
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// A list followed by the `;` that ends it before a keyword, which a
+/// trailing `&` already does.
+struct Terminated<'a>(&'a ParsedLine);
+
+impl fmt::Display for Terminated<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0.ends_in_background() {
+            true => write!(f, "{}", self.0),
+            false => write!(f, "{};", self.0),
+        }
+    }
+}
+
+impl fmt::Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Command::Simple(stage) => write!(f, "{stage}"),
+            Command::Compound(compound, redirects) => {
+                write!(f, "{compound}")?;
+                for redirect in redirects {
+                    write!(f, " {}", format_redirect(redirect))?;
+                }
+                Ok(())
+            }
+            Command::Function(function) => write!(f, "{function}"),
+        }
+    }
+}
+
+impl fmt::Display for Function {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} () {}", self.name, self.body)
+    }
+}
+
+impl fmt::Display for Compound {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Compound::Group(body) => write!(f, "{{ {} }}", Terminated(body)),
+            Compound::Subshell(body) => write!(f, "( {body} )"),
+            Compound::If {
+                branches,
+                otherwise,
+            } => {
+                for (i, (condition, body)) in branches.iter().enumerate() {
+                    let keyword = if i == 0 { "if" } else { "elif" };
+                    write!(
+                        f,
+                        "{keyword} {} then {} ",
+                        Terminated(condition),
+                        Terminated(body)
+                    )?;
+                }
+                if let Some(otherwise) = otherwise {
+                    write!(f, "else {} ", Terminated(otherwise))?;
+                }
+                write!(f, "fi")
+            }
+            Compound::Loop {
+                until,
+                condition,
+                body,
+            } => {
+                let keyword = if *until { "until" } else { "while" };
+                write!(
+                    f,
+                    "{keyword} {} do {} done",
+                    Terminated(condition),
+                    Terminated(body)
+                )
+            }
+            Compound::For { name, words, body } => {
+                write!(f, "for {name}")?;
+                if let Some(words) = words {
+                    write!(f, " in")?;
+                    for word in words {
+                        write!(f, " {word}")?;
+                    }
+                }
+                write!(f, "; do {} done", Terminated(body))
+            }
+            Compound::ArithmeticFor {
+                init,
+                condition,
+                step,
+                body,
+            } => {
+                write!(f, "for ((")?;
+                write_parts(f, &init.0, true)?;
+                write!(f, "; ")?;
+                write_parts(f, &condition.0, true)?;
+                write!(f, "; ")?;
+                write_parts(f, &step.0, true)?;
+                write!(f, ")); do {} done", Terminated(body))
+            }
+            Compound::Case { word, items } => {
+                write!(f, "case {word} in")?;
+                for item in items {
+                    let patterns: Vec<String> = item.patterns.iter().map(Word::to_string).collect();
+                    write!(f, " {})", patterns.join("|"))?;
+                    if !item.body.is_empty() {
+                        write!(f, " {}", item.body)?;
+                    }
+                    write!(f, ";;")?;
+                }
+                write!(f, " esac")
+            }
+            Compound::Arithmetic(expression) => {
+                write!(f, "((")?;
+                write_parts(f, &expression.0, true)?;
+                write!(f, "))")
+            }
+        }
+    }
+}
+
+impl fmt::Display for CommandStage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let assignments = self
+            .assignments
+            .iter()
+            .map(|(name, value)| format!("{name}={value}"));
+        let words = self.words.iter().map(Word::to_string);
+        let redirects = self.redirects.iter().map(format_redirect);
+
+        let parts: Vec<String> = assignments.chain(words).chain(redirects).collect();
+        write!(f, "{}", parts.join(" "))
+    }
+}
+
+fn format_redirect(redirect: &Redirection) -> String {
+    match redirect.redirect {
+        // The body follows the command line, so it is left out.
+        Redirect::HereDoc { .. } => redirect.redirect.to_string(),
+        Redirect::Duplicate { .. } => format!("{}{}", redirect.redirect, redirect.target),
+        _ => format!("{} {}", redirect.redirect, redirect.target),
+    }
+}
+
+impl fmt::Display for Word {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_parts(f, &self.0, false)
+    }
+}
+
+/// Writes word parts back out in a form the parser reads as the same word.
+fn write_parts(f: &mut fmt::Formatter<'_>, parts: &[WordPart], in_quotes: bool) -> fmt::Result {
+    for (i, part) in parts.iter().enumerate() {
+        match part {
+            WordPart::Literal(text) => write!(f, "{text}")?,
+            WordPart::Quoted(text) if in_quotes => {
+                for c in text.chars() {
+                    if matches!(c, '\\' | '"' | '$' | '`') {
+                        write!(f, "\\")?;
+                    }
+                    write!(f, "{c}")?;
+                }
+            }
+            WordPart::Quoted(text) => write!(f, "'{}'", text.replace('\'', "'\\''"))?,
+            WordPart::Variable(name) => {
+                let next = match parts.get(i + 1) {
+                    Some(WordPart::Literal(next)) => Some(next),
+                    Some(WordPart::Quoted(next)) if in_quotes => Some(next),
+                    _ => None,
+                };
+                let needs_braces = next.is_some_and(|next| {
+                    next.starts_with(|c: char| c.is_ascii_alphanumeric() || c == '_')
+                });
+                match needs_braces {
+                    true => write!(f, "${{{name}}}")?,
+                    false => write!(f, "${name}")?,
+                }
+            }
+            WordPart::Command(command) => write!(f, "$({command})")?,
+            WordPart::Arithmetic(parts) => {
+                write!(f, "$((")?;
+                write_parts(f, parts, true)?;
+                write!(f, "))")?;
+            }
+            WordPart::DoubleQuoted(parts) => {
+                write!(f, "\"")?;
+                write_parts(f, parts, true)?;
+                write!(f, "\"")?;
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -1,26 +1,73 @@
+mod alias;
+mod bg;
+mod bind;
+mod break_;
 mod cd;
+mod continue_;
 mod describe;
+mod disown;
 mod echo;
+mod env;
 mod exit;
+mod export;
+mod fg;
 mod history;
+mod jobs;
+mod let_;
+mod local;
 mod pwd;
+mod readonly;
+mod return_;
+mod set;
+mod shift;
+mod shopt;
+mod source;
+mod unalias;
+mod unset;
+mod wait;
 
-use std::fmt::Debug;
+use std::{
+    fmt::{self, Debug},
+    io::{self, BufWriter, Read, Write},
+    process::Command,
+};
 
+use alias::Alias;
+use bg::Bg;
+use bind::Bind;
+use break_::Break;
 use cd::Cd;
+use continue_::Continue;
 use describe::Describe;
+use disown::Disown;
 use echo::Echo;
+use env::Env;
 use exit::Exit;
+use export::Export;
+use fg::Fg;
 use history::History;
+use jobs::Jobs;
+use let_::Let;
+use local::Local;
 use pwd::Pwd;
+use readonly::Readonly;
+use return_::Return;
+use set::Set;
+use shift::Shift;
+use shopt::Shopt;
+use source::Source;
+use unalias::Unalias;
+use unset::Unset;
+use wait::Wait;
 
-use super::{ShellCtx, ShellError};
+use super::{FdTable, ShellCtx, ShellError};
 use anyhow::Result;
 
 #[derive(Debug)]
 pub struct Builtins;
 
 impl Builtins {
+    #[allow(clippy::new_ret_no_self)]
     pub fn new(cmd: &str) -> Option<Box<dyn ShellCommand>> {
         match cmd {
             "exit" => Some(Box::new(Exit)),
@@ -29,19 +76,64 @@ impl Builtins {
             "type" => Some(Box::new(Describe)),
             "cd" => Some(Box::new(Cd)),
             "history" => Some(Box::new(History)),
+            "export" => Some(Box::new(Export)),
+            "unset" => Some(Box::new(Unset)),
+            "set" => Some(Box::new(Set)),
+            "readonly" => Some(Box::new(Readonly)),
+            "env" => Some(Box::new(Env)),
+            "jobs" => Some(Box::new(Jobs)),
+            "fg" => Some(Box::new(Fg)),
+            "bg" => Some(Box::new(Bg)),
+            "wait" => Some(Box::new(Wait)),
+            "disown" => Some(Box::new(Disown)),
+            "shopt" => Some(Box::new(Shopt)),
+            "let" => Some(Box::new(Let)),
+            "break" => Some(Box::new(Break)),
+            "continue" => Some(Box::new(Continue)),
+            "local" => Some(Box::new(Local)),
+            "return" => Some(Box::new(Return)),
+            "shift" => Some(Box::new(Shift)),
+            "source" => Some(Box::new(Source("source"))),
+            "." => Some(Box::new(Source("."))),
+            "alias" => Some(Box::new(Alias)),
+            "unalias" => Some(Box::new(Unalias)),
+            "bind" => Some(Box::new(Bind)),
             _ => None,
         }
     }
 
     pub fn all_builtins() -> Vec<&'static str> {
-        Vec::from(["exit", "echo", "pwd", "type", "cd", "history"])
+        Vec::from([
+            "exit", "echo", "pwd", "type", "cd", "history", "export", "unset", "set", "readonly",
+            "env", "jobs", "fg", "bg", "wait", "disown", "shopt", "let", "break", "continue",
+            "local", "return", "shift", "source", ".", "alias", "unalias", "bind",
+        ])
     }
 }
 
 pub enum ExecResult {
     Continue,
-    Res(String),
+    Status(i32),
     Exit(i32),
+    /// `break n`, leaving `n` enclosing loops.
+    Break(usize),
+    /// `continue n`, resuming the `n`th enclosing loop.
+    ContinueLoop(usize),
+    /// `return`, leaving the running function with a status.
+    Return(i32),
+}
+
+impl ExecResult {
+    /// Whether the rest of a list is skipped, as after `exit` or `break`.
+    pub fn leaves_list(&self) -> bool {
+        matches!(
+            self,
+            ExecResult::Exit(_)
+                | ExecResult::Break(_)
+                | ExecResult::ContinueLoop(_)
+                | ExecResult::Return(_)
+        )
+    }
 }
 
 pub trait ShellCommand: Debug {
@@ -49,5 +141,163 @@ pub trait ShellCommand: Debug {
     fn description(&self) -> String {
         format!("{} is a shell builtin", self.name())
     }
-    fn execute(&self, args: &Vec<String>, ctx: &mut ShellCtx) -> Result<ExecResult>;
+    fn execute(&self, args: &[String], io: &mut Io, ctx: &mut ShellCtx) -> Result<ExecResult>;
+}
+
+/// The standard streams a builtin reads from and writes to: the ends of its
+/// pipes, the files it is redirected to or the shell's own.
+pub struct Io<'a> {
+    pub stdin: Box<dyn Read>,
+    pub stdout: Box<dyn Write>,
+    pub stderr: Box<dyn Write>,
+    fds: &'a FdTable,
+}
+
+impl<'a> Io<'a> {
+    /// Opens descriptors 0, 1 and 2 of `fds`, where one that was closed
+    /// with `>&-` fails to read or write.
+    pub fn new(fds: &'a FdTable) -> Io<'a> {
+        let stdin: Box<dyn Read> = match fds.file(0) {
+            Ok(file) => Box::new(file),
+            Err(_) => Box::new(Closed),
+        };
+        let stdout: Box<dyn Write> = match fds.file(1) {
+            Ok(file) => Box::new(BufWriter::new(file)),
+            Err(_) => Box::new(Closed),
+        };
+        let stderr: Box<dyn Write> = match fds.file(2) {
+            Ok(file) => Box::new(file),
+            Err(_) => Box::new(Closed),
+        };
+
+        Io {
+            stdin,
+            stdout,
+            stderr,
+            fds,
+        }
+    }
+
+    /// Sets up a command started by a builtin, as `env` does, to run with
+    /// the builtin's descriptors.
+    pub fn configure(&mut self, cmd: &mut Command) -> Result<()> {
+        self.stdout.flush()?;
+        self.fds.configure(cmd)
+    }
+
+    /// Reports an error on the builtin's stderr the way the shell would,
+    /// for a builtin that carries on after it and fails with a status.
+    pub fn report(&mut self, ctx: &ShellCtx, err: &dyn fmt::Display) -> io::Result<()> {
+        // Keeps the order of what was printed when both go to a terminal.
+        let _ = self.stdout.flush();
+        writeln!(self.stderr, "{}", ctx.error_message(err))
+    }
+
+    /// Runs `f` with the builtin's descriptors in place of the shell's own,
+    /// so that the commands `source` runs see its redirects.
+    pub fn install<T>(&mut self, f: impl FnOnce() -> T) -> Result<T> {
+        self.stdout.flush()?;
+        self.fds.install(f)
+    }
+}
+
+/// A standard stream that was closed.
+struct Closed;
+
+impl Read for Closed {
+    fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+        Err(io::Error::from_raw_os_error(libc::EBADF))
+    }
+}
+
+impl Write for Closed {
+    fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+        Err(io::Error::from_raw_os_error(libc::EBADF))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Quotes `value` so that it reads back as a single word when fed to the shell.
+fn quote(value: &str) -> String {
+    let is_plain = !value.is_empty()
+        && value.chars().all(|c| {
+            c.is_ascii_alphanumeric()
+                || matches!(c, '_' | '-' | '.' | '/' | ':' | ',' | '+' | '@' | '%')
+        });
+
+    if is_plain {
+        return value.to_string();
+    }
+
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+/// The number of loops `break` or `continue` applies to, at most the number
+/// the command is nested in. `None` outside of a loop, which does nothing.
+fn loop_count(name: &str, args: &[String], io: &mut Io, ctx: &ShellCtx) -> Result<Option<usize>> {
+    let count = match args.first() {
+        None => 1,
+        Some(arg) => match arg.parse::<i64>() {
+            Ok(count) if count > 0 => count as usize,
+            Ok(_) => {
+                return Err(ShellError::Execution(format!(
+                    "{name}: {arg}: loop count out of range"
+                ))
+                .into());
+            }
+            Err(_) => {
+                return Err(ShellError::Execution(format!(
+                    "{name}: {arg}: numeric argument required"
+                ))
+                .into());
+            }
+        },
+    };
+
+    if ctx.loop_depth() == 0 {
+        let err = format!("{name}: only meaningful in a `for', `while', or `until' loop");
+        io.report(ctx, &err)?;
+        return Ok(None);
+    }
+
+    Ok(Some(count.min(ctx.loop_depth())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Redirect, Redirection};
+
+    fn read_stdin(redirects: &[Redirection<String>]) -> io::Result<String> {
+        let mut fds = FdTable::new(None, None).unwrap();
+        fds.apply(redirects).unwrap();
+        let mut io = Io::new(&fds);
+        let mut text = String::new();
+        io.stdin.read_to_string(&mut text)?;
+        Ok(text)
+    }
+
+    #[test]
+    fn stdin() {
+        let here = Redirection::new(Redirect::HereString { fd: 0 }, "one\ntwo\n".to_string());
+        assert_eq!(read_stdin(&[here]).unwrap(), "one\ntwo\n");
+
+        let path = std::env::temp_dir().join(format!("builtin-stdin-{}", std::process::id()));
+        std::fs::write(&path, "from a file\n").unwrap();
+        let input = Redirection::new(Redirect::Input { fd: 0 }, path.display().to_string());
+        assert_eq!(read_stdin(&[input]).unwrap(), "from a file\n");
+        std::fs::remove_file(path).unwrap();
+
+        let closed = Redirection::new(
+            Redirect::Duplicate {
+                fd: 0,
+                output: false,
+            },
+            "-".to_string(),
+        );
+        assert!(read_stdin(&[closed]).is_err());
+    }
 }
@@ -0,0 +1,81 @@
+use std::io::Write;
+
+use anyhow::Result;
+
+use super::{ExecResult, Io, ShellCommand, ShellCtx};
+
+#[derive(Debug)]
+pub struct Alias;
+
+impl ShellCommand for Alias {
+    fn name(&self) -> &'static str {
+        "alias"
+    }
+
+    fn execute(&self, args: &[String], io: &mut Io, ctx: &mut ShellCtx) -> Result<ExecResult> {
+        let args: Vec<&String> = args.iter().filter(|arg| *arg != "-p").collect();
+
+        if args.is_empty() {
+            for (name, value) in ctx.aliases() {
+                writeln!(io.stdout, "{}", format_alias(name, value))?;
+            }
+            return Ok(ExecResult::Continue);
+        }
+
+        let mut status = 0;
+        for arg in args {
+            match arg.split_once('=') {
+                Some((name, value)) => {
+                    if is_valid_alias_name(name) {
+                        ctx.set_alias(name, value.to_string());
+                    } else {
+                        io.report(
+                            ctx,
+                            &format!("{}: `{name}': invalid alias name", self.name()),
+                        )?;
+                        status = 1;
+                    }
+                }
+                None => match ctx.alias(arg) {
+                    Some(value) => writeln!(io.stdout, "{}", format_alias(arg, value))?,
+                    None => {
+                        io.report(ctx, &format!("{}: {arg}: not found", self.name()))?;
+                        status = 1;
+                    }
+                },
+            }
+        }
+
+        Ok(ExecResult::Status(status))
+    }
+}
+
+/// An alias as an `alias` command that defines it again.
+fn format_alias(name: &str, value: &str) -> String {
+    format!("alias {name}='{}'", value.replace('\'', "'\\''"))
+}
+
+/// Whether `name` can be used as an alias, which rules out anything the
+/// lexer would not read back as a single unquoted word.
+fn is_valid_alias_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name.chars().any(|c| {
+            c.is_whitespace()
+                || matches!(
+                    c,
+                    '/' | '$'
+                        | '`'
+                        | '='
+                        | '\''
+                        | '"'
+                        | '\\'
+                        | '|'
+                        | '&'
+                        | ';'
+                        | '('
+                        | ')'
+                        | '<'
+                        | '>'
+                )
+        })
+}
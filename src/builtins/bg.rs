@@ -0,0 +1,45 @@
+use std::io::Write;
+
+use anyhow::Result;
+
+use super::{ExecResult, Io, ShellCommand, ShellCtx, ShellError};
+use crate::JobState;
+
+#[derive(Debug)]
+pub struct Bg;
+
+impl ShellCommand for Bg {
+    fn name(&self) -> &'static str {
+        "bg"
+    }
+
+    fn execute(&self, args: &[String], io: &mut Io, ctx: &mut ShellCtx) -> Result<ExecResult> {
+        let specs: Vec<Option<&str>> = match args.is_empty() {
+            true => vec![None],
+            false => args.iter().map(|arg| Some(arg.as_str())).collect(),
+        };
+
+        for spec in specs {
+            let Some(id) = ctx.jobs().find(spec) else {
+                let spec = spec.unwrap_or("current");
+                return Err(
+                    ShellError::Execution(format!("{}: {spec}: no such job", self.name())).into(),
+                );
+            };
+
+            let job = ctx.jobs_mut().get_mut(id).expect("job was just found");
+            if job.state != JobState::Stopped {
+                return Err(ShellError::Execution(format!(
+                    "{}: job {id} already in background",
+                    self.name()
+                ))
+                .into());
+            }
+
+            job.resume();
+            writeln!(io.stdout, "[{id}] {} &", job.command)?;
+        }
+
+        Ok(ExecResult::Continue)
+    }
+}
@@ -0,0 +1,150 @@
+use std::io::Write;
+
+use anyhow::Result;
+
+use super::{ExecResult, Io, ShellCommand, ShellCtx, ShellError};
+use crate::{
+    error::io_error_reason,
+    keymap::{self, Action},
+};
+
+#[derive(Debug)]
+pub struct Bind;
+
+impl ShellCommand for Bind {
+    fn name(&self) -> &'static str {
+        "bind"
+    }
+
+    fn execute(&self, args: &[String], io: &mut Io, ctx: &mut ShellCtx) -> Result<ExecResult> {
+        let mut status = 0;
+        let mut args = args.iter();
+
+        while let Some(arg) = args.next() {
+            let flag = arg.as_str();
+            let mut operand = || {
+                args.next().ok_or_else(|| {
+                    ShellError::Execution(format!(
+                        "{}: {flag}: option requires an argument",
+                        self.name()
+                    ))
+                })
+            };
+
+            match flag {
+                "-l" => {
+                    for action in Action::all() {
+                        writeln!(io.stdout, "{}", action.name())?;
+                    }
+                }
+                "-p" => {
+                    for action in Action::all() {
+                        let mut keys = ctx.keymap().keys_for(action).peekable();
+                        if keys.peek().is_none() {
+                            writeln!(io.stdout, "# {} (not bound)", action.name())?;
+                        }
+                        for keys in keys {
+                            let seq = keymap::keys_to_string(keys);
+                            writeln!(io.stdout, "\"{seq}\": {}", action.name())?;
+                        }
+                    }
+                }
+                "-P" => {
+                    for action in Action::all() {
+                        writeln!(io.stdout, "{}", describe(action, "found on", ctx))?;
+                    }
+                }
+                "-s" | "-S" => {
+                    for (keys, text) in ctx.keymap().macros() {
+                        let (seq, text) = (keymap::keys_to_string(keys), keymap::escape(text));
+                        match flag {
+                            "-s" => writeln!(io.stdout, "\"{seq}\": \"{text}\"")?,
+                            _ => writeln!(io.stdout, "{seq} outputs {text}")?,
+                        }
+                    }
+                }
+                "-v" => writeln!(io.stdout, "set editing-mode {}", ctx.edit_mode().name())?,
+                "-q" | "-u" => {
+                    let name = operand()?;
+                    let Some(action) = Action::from_name(name) else {
+                        io.report(
+                            ctx,
+                            &format!("{}: {name}: unknown function name", self.name()),
+                        )?;
+                        status = 1;
+                        continue;
+                    };
+                    if flag == "-u" {
+                        ctx.keymap_mut().unbind_action(action);
+                        continue;
+                    }
+                    writeln!(io.stdout, "{}", describe(action, "invoked via", ctx))?;
+                    if ctx.keymap().keys_for(action).next().is_none() {
+                        status = 1;
+                    }
+                }
+                "-r" => {
+                    let seq = operand()?;
+                    match keymap::parse_keyseq(seq) {
+                        Ok(keys) => {
+                            ctx.keymap_mut().unbind(&keys);
+                        }
+                        Err(msg) => {
+                            io.report(ctx, &format!("{}: {msg}", self.name()))?;
+                            status = 1;
+                        }
+                    }
+                }
+                "-f" => {
+                    let path = operand()?;
+                    match keymap::read_init_file(path, ctx) {
+                        Ok(errors) => {
+                            for (line, msg) in &errors {
+                                let msg = format!("{}: {path}: line {line}: {msg}", self.name());
+                                io.report(ctx, &msg)?;
+                                status = 1;
+                            }
+                        }
+                        Err(e) => {
+                            let reason = match e.downcast_ref() {
+                                Some(e) => io_error_reason(e),
+                                None => e.to_string(),
+                            };
+                            io.report(ctx, &format!("{}: {path}: {reason}", self.name()))?;
+                            status = 1;
+                        }
+                    }
+                }
+                _ if flag.starts_with('-') && flag.len() > 1 => {
+                    return Err(ShellError::Execution(format!(
+                        "{}: {flag}: invalid option",
+                        self.name()
+                    ))
+                    .into());
+                }
+                line => {
+                    for (_, msg) in keymap::read_inputrc(line, ctx) {
+                        io.report(ctx, &format!("{}: {msg}", self.name()))?;
+                        status = 1;
+                    }
+                }
+            }
+        }
+
+        Ok(ExecResult::Status(status))
+    }
+}
+
+/// Says which keys run `action`, as `-P` and `-q` do.
+fn describe(action: Action, how: &str, ctx: &ShellCtx) -> String {
+    let keys: Vec<String> = ctx
+        .keymap()
+        .keys_for(action)
+        .map(|keys| format!("\"{}\"", keymap::keys_to_string(keys)))
+        .collect();
+
+    match keys.is_empty() {
+        true => format!("{} is not bound to any keys.", action.name()),
+        false => format!("{} can be {how} {}.", action.name(), keys.join(", ")),
+    }
+}
@@ -0,0 +1,19 @@
+use anyhow::Result;
+
+use super::{ExecResult, Io, ShellCommand, ShellCtx, loop_count};
+
+#[derive(Debug)]
+pub struct Break;
+
+impl ShellCommand for Break {
+    fn name(&self) -> &'static str {
+        "break"
+    }
+
+    fn execute(&self, args: &[String], io: &mut Io, ctx: &mut ShellCtx) -> Result<ExecResult> {
+        match loop_count(self.name(), args, io, ctx)? {
+            Some(count) => Ok(ExecResult::Break(count)),
+            None => Ok(ExecResult::Status(0)),
+        }
+    }
+}
@@ -1,7 +1,8 @@
 use anyhow::Result;
 use std::env;
 
-use super::{ExecResult, ShellCommand, ShellCtx, ShellError};
+use super::{ExecResult, Io, ShellCommand, ShellCtx, ShellError};
+use crate::error::io_error_reason;
 
 #[derive(Debug)]
 pub struct Cd;
@@ -11,41 +12,37 @@ impl ShellCommand for Cd {
         "cd"
     }
 
-    fn execute(&self, args: &Vec<String>, _ctx: &mut ShellCtx) -> Result<ExecResult> {
+    fn execute(&self, args: &[String], io: &mut Io, ctx: &mut ShellCtx) -> Result<ExecResult> {
         if args.len() > 1 {
             return Err(
                 ShellError::Execution(format!("{}: too many arguments", self.name())).into(),
             );
         }
 
-        let directory = args.first();
-
-        match directory {
-            Some(dir) => {
-                if dir.starts_with('~') {
-                    if let Some(home) = env::home_dir() {
-                        let path =
-                            dir.replace('~', home.to_str().expect("Could not find home directory"));
-                        if let Err(_) = env::set_current_dir(path) {
-                            return Err(ShellError::Execution(format!(
-                                "cd: {}; No such file or directory",
-                                dir
-                            ))
-                            .into());
-                        }
-                    }
-                } else {
-                    if let Err(_) = env::set_current_dir(dir) {
-                        return Err(ShellError::Execution(format!(
-                            "cd: {}: No such file or directory",
-                            dir
-                        ))
-                        .into());
-                    }
+        let home = ctx
+            .get_var("HOME")
+            .or_else(|| env::home_dir().map(|home| home.display().to_string()));
+        let dir = match args.first() {
+            None => match home {
+                Some(home) => home,
+                None => {
+                    io.report(ctx, &format!("{}: HOME not set", self.name()))?;
+                    return Ok(ExecResult::Status(1));
                 }
-            }
-            None => env::set_current_dir(env::home_dir().unwrap())
-                .expect("Could not find home directory"),
+            },
+            Some(dir) => match (dir.strip_prefix('~'), home) {
+                (Some(rest), Some(home)) => format!("{home}{rest}"),
+                _ => dir.to_string(),
+            },
+        };
+
+        if let Err(e) = env::set_current_dir(&dir) {
+            let arg = args.first().unwrap_or(&dir);
+            io.report(
+                ctx,
+                &format!("{}: {arg}: {}", self.name(), io_error_reason(&e)),
+            )?;
+            return Ok(ExecResult::Status(1));
         }
 
         Ok(ExecResult::Continue)
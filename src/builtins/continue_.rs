@@ -0,0 +1,19 @@
+use anyhow::Result;
+
+use super::{ExecResult, Io, ShellCommand, ShellCtx, loop_count};
+
+#[derive(Debug)]
+pub struct Continue;
+
+impl ShellCommand for Continue {
+    fn name(&self) -> &'static str {
+        "continue"
+    }
+
+    fn execute(&self, args: &[String], io: &mut Io, ctx: &mut ShellCtx) -> Result<ExecResult> {
+        match loop_count(self.name(), args, io, ctx)? {
+            Some(count) => Ok(ExecResult::ContinueLoop(count)),
+            None => Ok(ExecResult::Status(0)),
+        }
+    }
+}
@@ -1,6 +1,8 @@
+use std::io::Write;
+
 use anyhow::Result;
 
-use super::{super::Cmds, ExecResult, ShellCommand, ShellCtx};
+use super::{super::Cmds, ExecResult, Io, ShellCommand, ShellCtx};
 
 #[derive(Debug)]
 pub struct Describe;
@@ -10,15 +12,29 @@ impl ShellCommand for Describe {
         "type"
     }
 
-    fn execute(&self, args: &Vec<String>, _ctx: &mut ShellCtx) -> Result<ExecResult> {
-        if let Some(cmd_to_evaluate) = args.first() {
-            let description = match Cmds::new(cmd_to_evaluate) {
-                Cmds::Builtin(cmd) => cmd.description(),
-                Cmds::External(cmd) => cmd.description(),
-            };
+    fn execute(&self, args: &[String], io: &mut Io, ctx: &mut ShellCtx) -> Result<ExecResult> {
+        let mut status = 0;
+
+        for name in args {
+            if let Some(value) = ctx.alias(name) {
+                writeln!(io.stdout, "{name} is aliased to `{value}'")?;
+                continue;
+            }
 
-            return Ok(ExecResult::Res(description));
+            let description = match Cmds::new(name, ctx) {
+                Cmds::Function(function) => Some(format!("{name} is a function\n{function}")),
+                Cmds::Builtin(cmd) => Some(cmd.description()),
+                Cmds::External(cmd) => cmd.description(ctx),
+            };
+            match description {
+                Some(description) => writeln!(io.stdout, "{description}")?,
+                None => {
+                    io.report(ctx, &format!("{name}: not found"))?;
+                    status = 1;
+                }
+            }
         }
-        Ok(ExecResult::Continue)
+
+        Ok(ExecResult::Status(status))
     }
 }
@@ -0,0 +1,36 @@
+use anyhow::Result;
+
+use super::{ExecResult, Io, ShellCommand, ShellCtx, ShellError};
+
+#[derive(Debug)]
+pub struct Disown;
+
+impl ShellCommand for Disown {
+    fn name(&self) -> &'static str {
+        "disown"
+    }
+
+    fn execute(&self, args: &[String], _io: &mut Io, ctx: &mut ShellCtx) -> Result<ExecResult> {
+        if args.iter().any(|arg| arg == "-a") {
+            ctx.jobs_mut().clear();
+            return Ok(ExecResult::Continue);
+        }
+
+        let specs: Vec<Option<&str>> = match args.is_empty() {
+            true => vec![None],
+            false => args.iter().map(|arg| Some(arg.as_str())).collect(),
+        };
+
+        for spec in specs {
+            let Some(id) = ctx.jobs().find(spec) else {
+                let spec = spec.unwrap_or("current");
+                return Err(
+                    ShellError::Execution(format!("{}: {spec}: no such job", self.name())).into(),
+                );
+            };
+            ctx.jobs_mut().remove(id);
+        }
+
+        Ok(ExecResult::Continue)
+    }
+}
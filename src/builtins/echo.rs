@@ -1,6 +1,8 @@
+use std::io::Write;
+
 use anyhow::Result;
 
-use super::{ExecResult, ShellCommand, ShellCtx};
+use super::{ExecResult, Io, ShellCommand, ShellCtx};
 
 #[derive(Debug)]
 pub struct Echo;
@@ -10,8 +12,8 @@ impl ShellCommand for Echo {
         "echo"
     }
 
-    fn execute(&self, args: &Vec<String>, _ctx: &mut ShellCtx) -> Result<ExecResult> {
-        let display_string = format!("{}\n", args.join(" "));
-        Ok(ExecResult::Res(display_string))
+    fn execute(&self, args: &[String], io: &mut Io, _ctx: &mut ShellCtx) -> Result<ExecResult> {
+        writeln!(io.stdout, "{}", args.join(" "))?;
+        Ok(ExecResult::Continue)
     }
 }
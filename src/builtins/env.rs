@@ -0,0 +1,81 @@
+use std::io::Write;
+
+use anyhow::Result;
+
+use super::{ExecResult, Io, ShellCommand, ShellCtx};
+use crate::{context::Job, external::External, is_valid_name, process};
+
+/// The status `env` fails with when it can't make sense of its options,
+/// telling them apart from those of the command it runs.
+const USAGE_STATUS: i32 = 125;
+
+#[derive(Debug)]
+pub struct Env;
+
+impl ShellCommand for Env {
+    fn name(&self) -> &'static str {
+        "env"
+    }
+
+    fn execute(&self, args: &[String], io: &mut Io, ctx: &mut ShellCtx) -> Result<ExecResult> {
+        let mut envs = ctx.exported_vars();
+        let mut args = args.iter().peekable();
+
+        while let Some(arg) = args.next_if(|arg| arg.starts_with('-')) {
+            match arg.as_str() {
+                "-i" => envs.clear(),
+                "-u" => {
+                    let Some(name) = args.next() else {
+                        let msg = format!("{}: option requires an argument -- 'u'", self.name());
+                        io.report(ctx, &msg)?;
+                        return Ok(ExecResult::Status(USAGE_STATUS));
+                    };
+                    envs.remove(name);
+                }
+                _ => {
+                    io.report(ctx, &format!("{}: invalid option -- '{arg}'", self.name()))?;
+                    return Ok(ExecResult::Status(USAGE_STATUS));
+                }
+            }
+        }
+
+        // The command is looked up in the shell's `PATH` unless an operand
+        // gives it one of its own.
+        let mut paths = ctx.get_var("PATH");
+        while let Some(arg) = args.next_if(|arg| arg.contains('=')) {
+            let (name, value) = arg.split_once('=').expect("checked to contain '='");
+            if is_valid_name(name) {
+                if name == "PATH" {
+                    paths = Some(value.to_string());
+                }
+                envs.insert(name.to_string(), value.to_string());
+            }
+        }
+
+        if let Some(name) = args.next() {
+            let args: Vec<String> = args.cloned().collect();
+            let mut cmd = External::new(name.to_string()).build(&args, &envs, paths.as_deref())?;
+            // Started in the foreground as any other command is, so that it
+            // can be stopped and gets Ctrl-C rather than the shell.
+            if ctx.job_control().is_some() {
+                process::join_process_group(&mut cmd, None, true);
+            }
+            io.configure(&mut cmd)?;
+            let pid = cmd.spawn()?.id() as i32;
+            if ctx.job_control().is_some() {
+                unsafe { libc::setpgid(pid, pid) };
+                process::give_terminal(pid);
+            }
+
+            let command = std::iter::once(name).chain(&args).cloned();
+            let command = format!("{} {}", self.name(), command.collect::<Vec<_>>().join(" "));
+            let status = process::wait_foreground(ctx, Job::new(pid, vec![pid], command));
+            return Ok(ExecResult::Status(status));
+        }
+
+        for (name, value) in envs {
+            writeln!(io.stdout, "{name}={value}")?;
+        }
+        Ok(ExecResult::Continue)
+    }
+}
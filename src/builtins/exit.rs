@@ -1,6 +1,6 @@
 use anyhow::Result;
 
-use super::{ExecResult, ShellCommand, ShellCtx};
+use super::{ExecResult, Io, ShellCommand, ShellCtx};
 
 #[derive(Debug)]
 pub struct Exit;
@@ -10,8 +10,11 @@ impl ShellCommand for Exit {
         "exit"
     }
 
-    fn execute(&self, args: &Vec<String>, ctx: &mut ShellCtx) -> Result<ExecResult> {
-        let status = args.get(0).and_then(|s| s.parse::<i32>().ok()).unwrap_or(0);
+    fn execute(&self, args: &[String], _io: &mut Io, ctx: &mut ShellCtx) -> Result<ExecResult> {
+        let status = args
+            .first()
+            .and_then(|s| s.parse::<i32>().ok())
+            .unwrap_or(ctx.last_status());
 
         ctx.shut_down()?;
         Ok(ExecResult::Exit(status))
@@ -0,0 +1,66 @@
+use std::io::Write;
+
+use anyhow::Result;
+
+use super::{ExecResult, Io, ShellCommand, ShellCtx, quote};
+use crate::is_valid_name;
+
+#[derive(Debug)]
+pub struct Export;
+
+impl ShellCommand for Export {
+    fn name(&self) -> &'static str {
+        "export"
+    }
+
+    fn execute(&self, args: &[String], io: &mut Io, ctx: &mut ShellCtx) -> Result<ExecResult> {
+        let mut unexport = false;
+        let mut names = Vec::new();
+
+        for arg in args {
+            match arg.as_str() {
+                "-n" => unexport = true,
+                "-p" => {}
+                _ => names.push(arg),
+            }
+        }
+
+        if names.is_empty() {
+            for (name, var) in ctx.vars().filter(|(_, var)| var.exported) {
+                match &var.value {
+                    Some(value) => writeln!(io.stdout, "export {name}={}", quote(value))?,
+                    None => writeln!(io.stdout, "export {name}")?,
+                }
+            }
+            return Ok(ExecResult::Continue);
+        }
+
+        let mut status = 0;
+        for arg in names {
+            let (name, value) = match arg.split_once('=') {
+                Some((name, value)) => (name, Some(value.to_string())),
+                None => (arg.as_str(), None),
+            };
+
+            if !is_valid_name(name) {
+                io.report(
+                    ctx,
+                    &format!("{}: `{arg}': not a valid identifier", self.name()),
+                )?;
+                status = 1;
+                continue;
+            }
+
+            if unexport {
+                if let Some(value) = value {
+                    ctx.set_var(name, value)?;
+                }
+                ctx.unexport_var(name);
+            } else {
+                ctx.export_var(name, value)?;
+            }
+        }
+
+        Ok(ExecResult::Status(status))
+    }
+}
@@ -0,0 +1,31 @@
+use std::io::Write;
+
+use anyhow::Result;
+
+use super::{ExecResult, Io, ShellCommand, ShellCtx, ShellError};
+use crate::process;
+
+#[derive(Debug)]
+pub struct Fg;
+
+impl ShellCommand for Fg {
+    fn name(&self) -> &'static str {
+        "fg"
+    }
+
+    fn execute(&self, args: &[String], io: &mut Io, ctx: &mut ShellCtx) -> Result<ExecResult> {
+        let spec = args.first().map(String::as_str);
+        let Some(id) = ctx.jobs().find(spec) else {
+            let spec = spec.unwrap_or("current");
+            return Err(
+                ShellError::Execution(format!("{}: {spec}: no such job", self.name())).into(),
+            );
+        };
+
+        let job = ctx.jobs_mut().remove(id).expect("job was just found");
+        writeln!(io.stdout, "{}", job.command)?;
+        io.stdout.flush()?;
+
+        Ok(ExecResult::Status(process::wait_foreground(ctx, job)))
+    }
+}
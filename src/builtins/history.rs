@@ -1,6 +1,8 @@
+use std::io::Write;
+
 use anyhow::Result;
 
-use super::{ExecResult, ShellCommand, ShellCtx, ShellError};
+use super::{ExecResult, Io, ShellCommand, ShellCtx, ShellError};
 
 #[derive(Debug)]
 pub struct History;
@@ -10,7 +12,7 @@ impl ShellCommand for History {
         "history"
     }
 
-    fn execute(&self, args: &Vec<String>, ctx: &mut ShellCtx) -> Result<ExecResult> {
+    fn execute(&self, args: &[String], io: &mut Io, ctx: &mut ShellCtx) -> Result<ExecResult> {
         let mut args = args.iter().to_owned();
 
         let hist: Vec<String> = match args.next() {
@@ -55,7 +57,9 @@ impl ShellCommand for History {
                 .collect(),
         };
 
-        println!("{}", hist.join("\n"));
+        for line in hist {
+            writeln!(io.stdout, "{line}")?;
+        }
 
         Ok(ExecResult::Continue)
     }
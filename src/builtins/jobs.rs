@@ -0,0 +1,59 @@
+use std::io::Write;
+
+use anyhow::Result;
+
+use super::{ExecResult, Io, ShellCommand, ShellCtx, ShellError};
+
+#[derive(Debug)]
+pub struct Jobs;
+
+impl ShellCommand for Jobs {
+    fn name(&self) -> &'static str {
+        "jobs"
+    }
+
+    fn execute(&self, args: &[String], io: &mut Io, ctx: &mut ShellCtx) -> Result<ExecResult> {
+        let mut long = false;
+        let mut pgids_only = false;
+        let mut specs = Vec::new();
+
+        for arg in args {
+            match arg.as_str() {
+                "-l" => long = true,
+                "-p" => pgids_only = true,
+                _ => specs.push(arg.as_str()),
+            }
+        }
+
+        ctx.jobs_mut().poll();
+
+        let ids: Vec<usize> = match specs.is_empty() {
+            true => ctx.jobs().iter().map(|job| job.id).collect(),
+            false => specs
+                .iter()
+                .map(|spec| {
+                    ctx.jobs().find(Some(spec)).ok_or_else(|| {
+                        ShellError::Execution(format!("{}: {spec}: no such job", self.name()))
+                    })
+                })
+                .collect::<Result<_, _>>()?,
+        };
+
+        let jobs = ctx.jobs();
+        let lines: Vec<String> = ids
+            .iter()
+            .filter_map(|id| jobs.get(*id))
+            .map(|job| match pgids_only {
+                true => job.pgid.to_string(),
+                false => jobs.format(job, long),
+            })
+            .collect();
+
+        ctx.jobs_mut().notifications();
+
+        for line in lines {
+            writeln!(io.stdout, "{line}")?;
+        }
+        Ok(ExecResult::Continue)
+    }
+}
@@ -0,0 +1,29 @@
+use anyhow::Result;
+
+use super::{ExecResult, Io, ShellCommand, ShellCtx, ShellError};
+use crate::arithmetic;
+
+#[derive(Debug)]
+pub struct Let;
+
+impl ShellCommand for Let {
+    fn name(&self) -> &'static str {
+        "let"
+    }
+
+    fn execute(&self, args: &[String], _io: &mut Io, ctx: &mut ShellCtx) -> Result<ExecResult> {
+        if args.is_empty() {
+            return Err(
+                ShellError::Execution(format!("{}: expression expected", self.name())).into(),
+            );
+        }
+
+        let mut value = 0;
+        for expression in args {
+            value = arithmetic::evaluate(expression, ctx)
+                .map_err(|e| ShellError::Execution(format!("{}: {e}", self.name())))?;
+        }
+
+        Ok(ExecResult::Status((value == 0) as i32))
+    }
+}
@@ -0,0 +1,35 @@
+use anyhow::Result;
+
+use super::{ExecResult, Io, ShellCommand, ShellCtx, ShellError};
+use crate::is_valid_name;
+
+#[derive(Debug)]
+pub struct Local;
+
+impl ShellCommand for Local {
+    fn name(&self) -> &'static str {
+        "local"
+    }
+
+    fn execute(&self, args: &[String], _io: &mut Io, ctx: &mut ShellCtx) -> Result<ExecResult> {
+        for arg in args {
+            let (name, value) = match arg.split_once('=') {
+                Some((name, value)) => (name, Some(value.to_string())),
+                None => (arg.as_str(), None),
+            };
+
+            if !is_valid_name(name) {
+                return Err(ShellError::Execution(format!(
+                    "{}: `{arg}': not a valid identifier",
+                    self.name()
+                ))
+                .into());
+            }
+
+            ctx.set_local(name, value)
+                .map_err(|e| ShellError::Execution(format!("{}: {e}", self.name())))?;
+        }
+
+        Ok(ExecResult::Continue)
+    }
+}
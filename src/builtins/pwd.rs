@@ -1,7 +1,7 @@
 use anyhow::Result;
-use std::env;
+use std::{env, io::Write};
 
-use super::{ExecResult, ShellCommand, ShellCtx, ShellError};
+use super::{ExecResult, Io, ShellCommand, ShellCtx, ShellError};
 
 #[derive(Debug)]
 pub struct Pwd;
@@ -11,15 +11,16 @@ impl ShellCommand for Pwd {
         "pwd"
     }
 
-    fn execute(&self, args: &Vec<String>, _ctx: &mut ShellCtx) -> Result<ExecResult> {
+    fn execute(&self, args: &[String], io: &mut Io, _ctx: &mut ShellCtx) -> Result<ExecResult> {
         if !args.is_empty() {
             return Err(
                 ShellError::Execution(format!("{}: too many arguments", self.name())).into(),
             );
         }
         if let Ok(current_dir) = env::current_dir() {
-            return Ok(ExecResult::Res(format!("{}", &current_dir.display())));
+            writeln!(io.stdout, "{}", current_dir.display())?;
+            return Ok(ExecResult::Continue);
         }
-        Err(ShellError::Execution(format!("Could not find current directory")).into())
+        Err(ShellError::Execution("Could not find current directory".to_string()).into())
     }
 }
@@ -0,0 +1,50 @@
+use std::io::Write;
+
+use anyhow::Result;
+
+use super::{ExecResult, Io, ShellCommand, ShellCtx, quote};
+use crate::is_valid_name;
+
+#[derive(Debug)]
+pub struct Readonly;
+
+impl ShellCommand for Readonly {
+    fn name(&self) -> &'static str {
+        "readonly"
+    }
+
+    fn execute(&self, args: &[String], io: &mut Io, ctx: &mut ShellCtx) -> Result<ExecResult> {
+        let names: Vec<&String> = args.iter().filter(|arg| *arg != "-p").collect();
+
+        if names.is_empty() {
+            for (name, var) in ctx.vars().filter(|(_, var)| var.readonly) {
+                match &var.value {
+                    Some(value) => writeln!(io.stdout, "readonly {name}={}", quote(value))?,
+                    None => writeln!(io.stdout, "readonly {name}")?,
+                }
+            }
+            return Ok(ExecResult::Continue);
+        }
+
+        let mut status = 0;
+        for arg in names {
+            let (name, value) = match arg.split_once('=') {
+                Some((name, value)) => (name, Some(value.to_string())),
+                None => (arg.as_str(), None),
+            };
+
+            if !is_valid_name(name) {
+                io.report(
+                    ctx,
+                    &format!("{}: `{arg}': not a valid identifier", self.name()),
+                )?;
+                status = 1;
+                continue;
+            }
+
+            ctx.readonly_var(name, value)?;
+        }
+
+        Ok(ExecResult::Status(status))
+    }
+}
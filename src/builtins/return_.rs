@@ -0,0 +1,31 @@
+use anyhow::Result;
+
+use super::{ExecResult, Io, ShellCommand, ShellCtx, ShellError};
+
+#[derive(Debug)]
+pub struct Return;
+
+impl ShellCommand for Return {
+    fn name(&self) -> &'static str {
+        "return"
+    }
+
+    fn execute(&self, args: &[String], _io: &mut Io, ctx: &mut ShellCtx) -> Result<ExecResult> {
+        if !ctx.in_function() && !ctx.is_sourcing() {
+            return Err(ShellError::Execution(format!(
+                "{}: can only `return' from a function or sourced script",
+                self.name()
+            ))
+            .into());
+        }
+
+        let status = match args.first() {
+            Some(arg) => arg.parse::<i64>().map_err(|_| {
+                ShellError::Execution(format!("{}: {arg}: numeric argument required", self.name()))
+            })?,
+            None => ctx.last_status().into(),
+        };
+
+        Ok(ExecResult::Return(status.rem_euclid(256) as i32))
+    }
+}
@@ -0,0 +1,75 @@
+use std::io::Write;
+
+use anyhow::Result;
+
+use super::{ExecResult, Io, ShellCommand, ShellCtx, ShellError, quote};
+use crate::context::EditMode;
+
+#[derive(Debug)]
+pub struct Set;
+
+impl ShellCommand for Set {
+    fn name(&self) -> &'static str {
+        "set"
+    }
+
+    fn execute(&self, args: &[String], io: &mut Io, ctx: &mut ShellCtx) -> Result<ExecResult> {
+        match args {
+            [flag] if flag == "-o" || flag == "+o" => {
+                return self.print_options(flag == "+o", io, ctx);
+            }
+            [flag, name] if flag == "-o" || flag == "+o" => {
+                let Some(mode) = EditMode::ALL.into_iter().find(|mode| mode.name() == name) else {
+                    return Err(ShellError::Execution(format!(
+                        "{}: {name}: invalid option name",
+                        self.name()
+                    ))
+                    .into());
+                };
+                // There is always one mode, so turning one off turns on the other.
+                match (flag == "-o", mode) {
+                    (true, mode) => ctx.set_edit_mode(mode),
+                    (false, EditMode::Emacs) => ctx.set_edit_mode(EditMode::Vi),
+                    (false, EditMode::Vi) => ctx.set_edit_mode(EditMode::Emacs),
+                }
+                return Ok(ExecResult::Continue);
+            }
+            // The rest replace the positional parameters, even when there
+            // are none.
+            [flag, params @ ..] if flag == "--" => {
+                ctx.set_positional(params.to_vec());
+                return Ok(ExecResult::Continue);
+            }
+            _ => {}
+        }
+
+        if let Some(arg) = args.first() {
+            return Err(
+                ShellError::Execution(format!("{}: {arg}: invalid option", self.name())).into(),
+            );
+        }
+
+        for (name, var) in ctx.vars() {
+            if let Some(value) = &var.value {
+                writeln!(io.stdout, "{name}={}", quote(value))?;
+            }
+        }
+        Ok(ExecResult::Continue)
+    }
+}
+
+impl Set {
+    /// Lists the options, as commands that set them again with `+o`.
+    fn print_options(&self, as_commands: bool, io: &mut Io, ctx: &ShellCtx) -> Result<ExecResult> {
+        for mode in EditMode::ALL {
+            let enabled = ctx.edit_mode() == mode;
+            match (as_commands, enabled) {
+                (true, true) => writeln!(io.stdout, "set -o {}", mode.name())?,
+                (true, false) => writeln!(io.stdout, "set +o {}", mode.name())?,
+                (false, true) => writeln!(io.stdout, "{:<15}\ton", mode.name())?,
+                (false, false) => writeln!(io.stdout, "{:<15}\toff", mode.name())?,
+            }
+        }
+        Ok(ExecResult::Continue)
+    }
+}
@@ -0,0 +1,38 @@
+use anyhow::Result;
+
+use super::{ExecResult, Io, ShellCommand, ShellCtx, ShellError};
+
+#[derive(Debug)]
+pub struct Shift;
+
+impl ShellCommand for Shift {
+    fn name(&self) -> &'static str {
+        "shift"
+    }
+
+    fn execute(&self, args: &[String], _io: &mut Io, ctx: &mut ShellCtx) -> Result<ExecResult> {
+        let count = match args.first() {
+            Some(arg) => match arg.parse::<i64>() {
+                Ok(count) if count >= 0 => count as usize,
+                Ok(_) => {
+                    return Err(ShellError::Execution(format!(
+                        "{}: {arg}: shift count out of range",
+                        self.name()
+                    ))
+                    .into());
+                }
+                Err(_) => {
+                    return Err(ShellError::Execution(format!(
+                        "{}: {arg}: numeric argument required",
+                        self.name()
+                    ))
+                    .into());
+                }
+            },
+            None => 1,
+        };
+
+        // Shifting past the last parameter fails without a message.
+        Ok(ExecResult::Status(!ctx.shift(count) as i32))
+    }
+}
@@ -0,0 +1,90 @@
+use std::io::Write;
+
+use anyhow::Result;
+
+use super::{ExecResult, Io, ShellCommand, ShellCtx, ShellError};
+use crate::context::SHELL_OPTIONS;
+
+#[derive(Debug)]
+pub struct Shopt;
+
+impl ShellCommand for Shopt {
+    fn name(&self) -> &'static str {
+        "shopt"
+    }
+
+    fn execute(&self, args: &[String], io: &mut Io, ctx: &mut ShellCtx) -> Result<ExecResult> {
+        let mut set = None;
+        let mut print = false;
+        let mut quiet = false;
+        let mut names = Vec::new();
+
+        for arg in args {
+            match arg.as_str() {
+                "-s" => set = Some(true),
+                "-u" => set = Some(false),
+                "-p" => print = true,
+                "-q" => quiet = true,
+                flag if flag.starts_with('-') => {
+                    return Err(ShellError::Execution(format!(
+                        "{}: {flag}: invalid option",
+                        self.name()
+                    ))
+                    .into());
+                }
+                name => names.push(name),
+            }
+        }
+
+        if let Some(enabled) = set
+            && !names.is_empty()
+        {
+            for name in names {
+                ctx.set_shopt(name, enabled)?;
+            }
+            return Ok(ExecResult::Continue);
+        }
+
+        for name in &names {
+            if !SHELL_OPTIONS.contains(name) {
+                return Err(ShellError::Execution(format!(
+                    "{}: {name}: invalid shell option name",
+                    self.name()
+                ))
+                .into());
+            }
+        }
+
+        let names = match names.is_empty() {
+            true => SHELL_OPTIONS.to_vec(),
+            false => names,
+        };
+        let shown: Vec<&str> = names
+            .into_iter()
+            .filter(|name| set.is_none_or(|enabled| ctx.shopt(name) == enabled))
+            .collect();
+
+        let all_on = shown.iter().all(|name| ctx.shopt(name));
+        if quiet {
+            return Ok(ExecResult::Status(if all_on { 0 } else { 1 }));
+        }
+
+        let lines: Vec<String> = shown
+            .iter()
+            .map(|name| {
+                let enabled = ctx.shopt(name);
+                match (print, enabled) {
+                    (true, true) => format!("shopt -s {name}"),
+                    (true, false) => format!("shopt -u {name}"),
+                    (false, true) => format!("{name:<15}\ton"),
+                    (false, false) => format!("{name:<15}\toff"),
+                }
+            })
+            .collect();
+
+        for line in lines {
+            writeln!(io.stdout, "{line}")?;
+        }
+        Ok(ExecResult::Continue)
+    }
+}
@@ -0,0 +1,46 @@
+use std::{env, fs, path::PathBuf};
+
+use anyhow::Result;
+
+use super::{ExecResult, Io, ShellCommand, ShellCtx, ShellError};
+use crate::error::io_error_reason;
+
+/// `source`, which also goes by `.`.
+#[derive(Debug)]
+pub struct Source(pub &'static str);
+
+impl ShellCommand for Source {
+    fn name(&self) -> &'static str {
+        self.0
+    }
+
+    fn execute(&self, args: &[String], io: &mut Io, ctx: &mut ShellCtx) -> Result<ExecResult> {
+        let Some((file, params)) = args.split_first() else {
+            return Err(ShellError::Execution(format!(
+                "{}: filename argument required",
+                self.name()
+            ))
+            .into());
+        };
+
+        let text = fs::read_to_string(find_script(file))
+            .map_err(|e| ShellError::Execution(format!("{file}: {}", io_error_reason(&e))))?;
+
+        io.install(|| ctx.source(params.to_vec(), |ctx| crate::run_script(&text, file, ctx)))
+    }
+}
+
+/// Looks a file name without a `/` up in `PATH` first, then in the current
+/// directory.
+fn find_script(file: &str) -> PathBuf {
+    if !file.contains('/')
+        && let Some(paths) = env::var_os("PATH")
+        && let Some(path) = env::split_paths(&paths)
+            .map(|dir| dir.join(file))
+            .find(|path| path.is_file())
+    {
+        return path;
+    }
+
+    PathBuf::from(file)
+}
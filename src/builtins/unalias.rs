@@ -0,0 +1,37 @@
+use anyhow::Result;
+
+use super::{ExecResult, Io, ShellCommand, ShellCtx, ShellError};
+
+#[derive(Debug)]
+pub struct Unalias;
+
+impl ShellCommand for Unalias {
+    fn name(&self) -> &'static str {
+        "unalias"
+    }
+
+    fn execute(&self, args: &[String], io: &mut Io, ctx: &mut ShellCtx) -> Result<ExecResult> {
+        if args.first().is_some_and(|arg| arg == "-a") {
+            ctx.clear_aliases();
+            return Ok(ExecResult::Continue);
+        }
+
+        if args.is_empty() {
+            return Err(ShellError::Execution(format!(
+                "{}: usage: unalias [-a] name [name ...]",
+                self.name()
+            ))
+            .into());
+        }
+
+        let mut status = 0;
+        for name in args {
+            if !ctx.remove_alias(name) {
+                io.report(ctx, &format!("{}: {name}: not found", self.name()))?;
+                status = 1;
+            }
+        }
+
+        Ok(ExecResult::Status(status))
+    }
+}
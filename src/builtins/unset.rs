@@ -0,0 +1,54 @@
+use anyhow::Result;
+
+use super::{ExecResult, Io, ShellCommand, ShellCtx, ShellError};
+use crate::is_valid_name;
+
+#[derive(Debug)]
+pub struct Unset;
+
+impl ShellCommand for Unset {
+    fn name(&self) -> &'static str {
+        "unset"
+    }
+
+    fn execute(&self, args: &[String], io: &mut Io, ctx: &mut ShellCtx) -> Result<ExecResult> {
+        // Names are variables unless `-f` says they are functions.
+        let mut functions = false;
+        let mut names = args.iter().peekable();
+        while let Some(flag) = names.next_if(|arg| arg.starts_with('-')) {
+            match flag.as_str() {
+                "-f" => functions = true,
+                "-v" => functions = false,
+                "--" => break,
+                flag => {
+                    return Err(ShellError::Execution(format!(
+                        "{}: {flag}: invalid option",
+                        self.name()
+                    ))
+                    .into());
+                }
+            }
+        }
+
+        let mut status = 0;
+        for name in names {
+            if functions {
+                ctx.remove_function(name);
+                continue;
+            }
+
+            if !is_valid_name(name) {
+                io.report(
+                    ctx,
+                    &format!("{}: `{name}': not a valid identifier", self.name()),
+                )?;
+                status = 1;
+                continue;
+            }
+
+            ctx.unset_var(name)?;
+        }
+
+        Ok(ExecResult::Status(status))
+    }
+}
@@ -0,0 +1,68 @@
+use anyhow::Result;
+
+use super::{ExecResult, Io, ShellCommand, ShellCtx, ShellError};
+use crate::{JobState, process};
+
+/// The status of a `wait` cut short by Ctrl-C.
+const INTERRUPTED: i32 = 128 + libc::SIGINT;
+
+#[derive(Debug)]
+pub struct Wait;
+
+impl ShellCommand for Wait {
+    fn name(&self) -> &'static str {
+        "wait"
+    }
+
+    fn execute(&self, args: &[String], io: &mut Io, ctx: &mut ShellCtx) -> Result<ExecResult> {
+        process::take_interrupt();
+
+        if args.is_empty() {
+            let ids: Vec<usize> = ctx.jobs().iter().map(|job| job.id).collect();
+            for id in ids {
+                if let Some(job) = ctx.jobs_mut().get_mut(id)
+                    && !job.wait_interruptible()
+                {
+                    return Ok(ExecResult::Status(INTERRUPTED));
+                }
+            }
+            ctx.jobs_mut().remove_done();
+
+            return Ok(ExecResult::Continue);
+        }
+
+        let mut status = 0;
+        for arg in args {
+            let id = match arg.parse::<i32>() {
+                Ok(pid) => ctx.jobs().find_by_pid(pid).ok_or_else(|| {
+                    ShellError::Execution(format!(
+                        "{}: pid {pid} is not a child of this shell",
+                        self.name()
+                    ))
+                }),
+                Err(_) => ctx.jobs().find(Some(arg)).ok_or_else(|| {
+                    ShellError::Execution(format!("{}: {arg}: no such job", self.name()))
+                }),
+            };
+            let id = match id {
+                Ok(id) => id,
+                Err(e) => {
+                    io.report(ctx, &e)?;
+                    status = 127;
+                    continue;
+                }
+            };
+
+            let job = ctx.jobs_mut().get_mut(id).expect("job was just found");
+            if !job.wait_interruptible() {
+                return Ok(ExecResult::Status(INTERRUPTED));
+            }
+            if let JobState::Done(job_status) = job.state {
+                status = job_status;
+                ctx.jobs_mut().remove(id);
+            }
+        }
+
+        Ok(ExecResult::Status(status))
+    }
+}
@@ -0,0 +1,206 @@
+use anyhow::Result;
+
+use super::{
+    CaseItem, Compound, ExecResult, FdTable, ParsedLine, ShellCtx, Word, expansion, glob, process,
+};
+use crate::context::Job;
+
+/// What a loop does after running its condition or body.
+enum Flow {
+    Next,
+    Stop,
+    /// Leaves the loop with a result for the enclosing command, as for
+    /// `break 2`, `return` or `exit`.
+    Leave(ExecResult),
+}
+
+/// Runs a compound command in the current shell.
+pub fn execute_compound(compound: &Compound, ctx: &mut ShellCtx) -> Result<ExecResult> {
+    match compound {
+        Compound::Group(body) => crate::execute_line(body, ctx),
+        Compound::Subshell(body) => {
+            let fds = FdTable::new(None, None)?;
+            let pid = process::fork_stage(ctx, &fds, None, |ctx| crate::execute_line(body, ctx))?;
+            let job = Job::new(pid, vec![pid], compound.to_string());
+            Ok(ExecResult::Status(process::wait_foreground(ctx, job)))
+        }
+        Compound::If {
+            branches,
+            otherwise,
+        } => execute_if(branches, otherwise.as_deref(), ctx),
+        Compound::Loop {
+            until,
+            condition,
+            body,
+        } => in_loop(ctx, |ctx| execute_while(*until, condition, body, ctx)),
+        Compound::For { name, words, body } => {
+            let values = match words {
+                Some(words) => expansion::expand_words(words, ctx)?,
+                None => ctx.positional().to_vec(),
+            };
+            in_loop(ctx, |ctx| execute_for(name, values, body, ctx))
+        }
+        Compound::ArithmeticFor {
+            init,
+            condition,
+            step,
+            body,
+        } => in_loop(ctx, |ctx| {
+            execute_arithmetic_for(init, condition, step, body, ctx)
+        }),
+        Compound::Case { word, items } => execute_case(word, items, ctx),
+        Compound::Arithmetic(expression) => {
+            let value = expansion::expand_arithmetic(expression, ctx)?;
+            Ok(ExecResult::Status((value == 0) as i32))
+        }
+    }
+}
+
+fn execute_if(
+    branches: &[(ParsedLine, ParsedLine)],
+    otherwise: Option<&ParsedLine>,
+    ctx: &mut ShellCtx,
+) -> Result<ExecResult> {
+    for (condition, body) in branches {
+        let res = crate::execute_line(condition, ctx)?;
+        if res.leaves_list() || process::interrupted() {
+            return Ok(res);
+        }
+        if ctx.last_status() == 0 {
+            return crate::execute_line(body, ctx);
+        }
+    }
+
+    match otherwise {
+        Some(otherwise) => crate::execute_line(otherwise, ctx),
+        None => Ok(ExecResult::Status(0)),
+    }
+}
+
+fn execute_while(
+    until: bool,
+    condition: &ParsedLine,
+    body: &ParsedLine,
+    ctx: &mut ShellCtx,
+) -> Result<ExecResult> {
+    let mut status = 0;
+
+    loop {
+        match run(condition, ctx)? {
+            Flow::Next => {}
+            Flow::Stop => break,
+            Flow::Leave(res) => return Ok(res),
+        }
+        if (ctx.last_status() == 0) == until {
+            break;
+        }
+
+        let flow = run(body, ctx)?;
+        status = ctx.last_status();
+        match flow {
+            Flow::Next => {}
+            Flow::Stop => break,
+            Flow::Leave(res) => return Ok(res),
+        }
+    }
+
+    Ok(ExecResult::Status(status))
+}
+
+fn execute_for(
+    name: &str,
+    values: Vec<String>,
+    body: &ParsedLine,
+    ctx: &mut ShellCtx,
+) -> Result<ExecResult> {
+    let mut status = 0;
+
+    for value in values {
+        ctx.set_var(name, value)?;
+
+        let flow = run(body, ctx)?;
+        status = ctx.last_status();
+        match flow {
+            Flow::Next => {}
+            Flow::Stop => break,
+            Flow::Leave(res) => return Ok(res),
+        }
+    }
+
+    Ok(ExecResult::Status(status))
+}
+
+fn execute_arithmetic_for(
+    init: &Word,
+    condition: &Word,
+    step: &Word,
+    body: &ParsedLine,
+    ctx: &mut ShellCtx,
+) -> Result<ExecResult> {
+    let mut status = 0;
+
+    evaluate(init, ctx)?;
+    // An empty condition is always true.
+    while condition.0.is_empty() || evaluate(condition, ctx)? != 0 {
+        let flow = run(body, ctx)?;
+        status = ctx.last_status();
+        match flow {
+            Flow::Next => {}
+            Flow::Stop => break,
+            Flow::Leave(res) => return Ok(res),
+        }
+
+        evaluate(step, ctx)?;
+    }
+
+    Ok(ExecResult::Status(status))
+}
+
+fn execute_case(word: &Word, items: &[CaseItem], ctx: &mut ShellCtx) -> Result<ExecResult> {
+    let value = expansion::expand_word(word, ctx)?.unwrap_or_default();
+
+    for item in items {
+        for pattern in &item.patterns {
+            if glob::matches(&expansion::expand_pattern(pattern, ctx)?, &value) {
+                // An empty body leaves the status at zero.
+                ctx.set_last_status(0);
+                return crate::execute_line(&item.body, ctx);
+            }
+        }
+    }
+
+    Ok(ExecResult::Status(0))
+}
+
+/// Runs a loop, so that `break` and `continue` know they are in one.
+fn in_loop(
+    ctx: &mut ShellCtx,
+    f: impl FnOnce(&mut ShellCtx) -> Result<ExecResult>,
+) -> Result<ExecResult> {
+    ctx.enter_loop();
+    let res = f(ctx);
+    ctx.leave_loop();
+
+    res
+}
+
+/// Runs the condition or body of a loop and works out where to go next.
+fn run(line: &ParsedLine, ctx: &mut ShellCtx) -> Result<Flow> {
+    Ok(match crate::execute_line(line, ctx)? {
+        ExecResult::Break(1) => Flow::Stop,
+        ExecResult::Break(n) => Flow::Leave(ExecResult::Break(n - 1)),
+        ExecResult::ContinueLoop(1) => Flow::Next,
+        ExecResult::ContinueLoop(n) => Flow::Leave(ExecResult::ContinueLoop(n - 1)),
+        res @ (ExecResult::Exit(_) | ExecResult::Return(_)) => Flow::Leave(res),
+        _ if process::interrupted() => Flow::Leave(ExecResult::Status(128 + libc::SIGINT)),
+        _ => Flow::Next,
+    })
+}
+
+/// Evaluates an arithmetic word, where an empty one is zero.
+fn evaluate(expression: &Word, ctx: &mut ShellCtx) -> Result<i64> {
+    match expression.0.is_empty() {
+        true => Ok(0),
+        false => expansion::expand_arithmetic(expression, ctx),
+    }
+}
@@ -1,11 +1,22 @@
 use std::{
-    env, fs,
+    collections::{BTreeMap, BTreeSet},
+    env, fmt, fs,
     io::{self, BufRead, Write},
+    rc::Rc,
 };
 
 use anyhow::Result;
 
-#[derive(Clone)]
+use crate::{Function, ShellError, keymap::Keymap};
+
+mod jobs;
+mod variables;
+
+pub use jobs::{Job, JobCtx, JobState};
+use variables::VarCtx;
+pub use variables::{Variable, is_valid_name};
+
+#[derive(Clone, Default)]
 struct HistCtx {
     entries: Vec<String>,
     breakpoint: usize,
@@ -21,6 +32,7 @@ impl HistCtx {
             Some(path) => {
                 let file = fs::OpenOptions::new()
                     .create(true)
+                    .truncate(false)
                     .write(true)
                     .read(true)
                     .open(path)?;
@@ -58,7 +70,7 @@ impl HistCtx {
         let mut writer = io::BufWriter::new(fs::File::create(&tmp_path)?);
         writeln!(writer, "{line}")?;
 
-        if let Ok(mut reader) = fs::File::open(&path).map(io::BufReader::new) {
+        if let Ok(mut reader) = fs::File::open(path).map(io::BufReader::new) {
             io::copy(&mut reader, &mut writer)?;
         }
 
@@ -110,17 +122,454 @@ impl HistCtx {
     }
 }
 
+/// Options toggled with `shopt`.
+pub const SHELL_OPTIONS: [&str; 3] = ["dotglob", "failglob", "nullglob"];
+
+/// The keys the line editor follows, picked with `set -o`.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum EditMode {
+    #[default]
+    Emacs,
+    Vi,
+}
+
+impl EditMode {
+    pub const ALL: [EditMode; 2] = [EditMode::Emacs, EditMode::Vi];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            EditMode::Emacs => "emacs",
+            EditMode::Vi => "vi",
+        }
+    }
+}
+
+/// How deeply functions may call each other before the call fails.
+const MAX_FUNCTION_DEPTH: usize = 1000;
+
+/// What a running function call replaced, put back when it returns.
+struct Frame {
+    positional: Vec<String>,
+    locals: Vec<(String, Option<Variable>)>,
+}
+
 pub struct ShellCtx {
     history: HistCtx,
+    vars: VarCtx,
+    jobs: JobCtx,
     current_buf: Option<String>,
+    shell_name: String,
+    /// The shell's own pid for `$$`, which subshells keep.
+    pid: u32,
+    last_status: i32,
+    /// The status of the last command substitution in the current command.
+    substitution_status: Option<i32>,
+    last_background: Option<i32>,
+    job_control: Option<i32>,
+    /// Whether lines come from a user at the prompt rather than from a
+    /// script, `-c` string or subshell.
+    interactive: bool,
+    /// How many loops the running command is nested in, for `break`.
+    loop_depth: usize,
+    /// `$1` and on, set by the running function.
+    positional: Vec<String>,
+    /// The script being run and the line of its current command, which
+    /// errors are reported with.
+    script: Option<(String, usize)>,
+    /// How many `source` commands are running, which `return` can leave.
+    sourcing: usize,
+    frames: Vec<Frame>,
+    functions: BTreeMap<String, Rc<Function>>,
+    aliases: BTreeMap<String, String>,
+    /// The aliases being expanded by the running command, which are not
+    /// expanded again inside their own values.
+    active_aliases: Vec<String>,
+    shopts: BTreeSet<&'static str>,
+    edit_mode: EditMode,
+    keymap: Keymap,
 }
 
 impl ShellCtx {
     pub fn build() -> Result<Self> {
-        Ok(ShellCtx {
-            history: HistCtx::build()?,
+        Ok(ShellCtx::with_history(HistCtx::build()?))
+    }
+
+    /// A context that starts with no history and never saves it, whatever
+    /// `HISTFILE` says, as the line editor's tests need.
+    pub fn build_without_history() -> Self {
+        ShellCtx::with_history(HistCtx::default())
+    }
+
+    fn with_history(history: HistCtx) -> Self {
+        ShellCtx {
+            history,
+            vars: VarCtx::build(),
+            jobs: JobCtx::default(),
             current_buf: None,
-        })
+            shell_name: env::args()
+                .next()
+                .unwrap_or_else(|| "codecrafters-shell".to_string()),
+            pid: std::process::id(),
+            last_status: 0,
+            substitution_status: None,
+            last_background: None,
+            job_control: None,
+            interactive: false,
+            loop_depth: 0,
+            positional: Vec::new(),
+            script: None,
+            sourcing: 0,
+            frames: Vec::new(),
+            functions: BTreeMap::new(),
+            aliases: BTreeMap::new(),
+            active_aliases: Vec::new(),
+            shopts: BTreeSet::new(),
+            edit_mode: EditMode::default(),
+            keymap: Keymap::default(),
+        }
+    }
+
+    pub fn get_var(&self, name: &str) -> Option<String> {
+        match name {
+            "?" => Some(self.last_status.to_string()),
+            "$" => Some(self.pid.to_string()),
+            "#" => Some(self.positional.len().to_string()),
+            "0" => Some(self.shell_name.clone()),
+            "@" => Some(self.positional.join(" ")),
+            "*" => {
+                let ifs = self.vars.get("IFS").unwrap_or(" ");
+                Some(
+                    self.positional
+                        .join(&ifs.chars().next().map(String::from).unwrap_or_default()),
+                )
+            }
+            _ if name.starts_with(|c: char| c.is_ascii_digit()) => {
+                let index: usize = name.parse().ok()?;
+                self.positional.get(index.checked_sub(1)?).cloned()
+            }
+            "!" => self.last_background.map(|pid| pid.to_string()),
+            _ => self.vars.get(name).map(str::to_string),
+        }
+    }
+
+    pub fn last_status(&self) -> i32 {
+        self.last_status
+    }
+
+    pub fn set_last_status(&mut self, status: i32) {
+        self.last_status = status;
+    }
+
+    /// Records the status of a command substitution, which is also what `$?`
+    /// gives from then on.
+    pub fn set_substitution_status(&mut self, status: i32) {
+        self.substitution_status = Some(status);
+        self.last_status = status;
+    }
+
+    pub fn take_substitution_status(&mut self) -> Option<i32> {
+        self.substitution_status.take()
+    }
+
+    pub fn set_last_background(&mut self, pid: i32) {
+        self.last_background = Some(pid);
+    }
+
+    pub fn set_shell_name(&mut self, name: &str) {
+        self.shell_name = name.to_string();
+    }
+
+    pub fn positional(&self) -> &[String] {
+        &self.positional
+    }
+
+    pub fn set_positional(&mut self, params: Vec<String>) {
+        self.positional = params;
+    }
+
+    /// Drops the first `count` positional parameters, unless there are
+    /// fewer than that.
+    pub fn shift(&mut self, count: usize) -> bool {
+        if count > self.positional.len() {
+            return false;
+        }
+        self.positional.drain(..count);
+
+        true
+    }
+
+    pub fn function(&self, name: &str) -> Option<Rc<Function>> {
+        self.functions.get(name).cloned()
+    }
+
+    pub fn functions(&self) -> impl Iterator<Item = &String> {
+        self.functions.keys()
+    }
+
+    pub fn define_function(&mut self, function: Rc<Function>) {
+        self.functions.insert(function.name.clone(), function);
+    }
+
+    pub fn remove_function(&mut self, name: &str) {
+        self.functions.remove(name);
+    }
+
+    pub fn in_function(&self) -> bool {
+        !self.frames.is_empty()
+    }
+
+    /// Runs `f` as a call to the function `name`, with `args` as its
+    /// positional parameters and any `local` variables undone afterwards.
+    /// Loops around the call are out of reach of `break` in the body.
+    pub fn call_function<T>(
+        &mut self,
+        name: &str,
+        args: Vec<String>,
+        f: impl FnOnce(&mut Self) -> Result<T>,
+    ) -> Result<T> {
+        if self.frames.len() >= MAX_FUNCTION_DEPTH {
+            return Err(ShellError::Execution(format!(
+                "{name}: maximum function nesting level exceeded ({MAX_FUNCTION_DEPTH})"
+            ))
+            .into());
+        }
+
+        self.frames.push(Frame {
+            positional: std::mem::replace(&mut self.positional, args),
+            locals: Vec::new(),
+        });
+        let loop_depth = std::mem::take(&mut self.loop_depth);
+        let res = f(self);
+        self.loop_depth = loop_depth;
+
+        let frame = self.frames.pop().expect("pushed above");
+        self.positional = frame.positional;
+        self.vars.restore(frame.locals);
+
+        res
+    }
+
+    /// Makes `name` local to the running function, set to `value` if given.
+    pub fn set_local(&mut self, name: &str, value: Option<String>) -> Result<()> {
+        let Some(frame) = self.frames.last_mut() else {
+            return Err(ShellError::Execution("can only be used in a function".to_string()).into());
+        };
+
+        if !frame.locals.iter().any(|(local, _)| local == name) {
+            let saved = self.vars.localize(name)?;
+            frame.locals.push((name.to_string(), saved));
+        }
+        match value {
+            Some(value) => self.vars.set(name, value),
+            None => Ok(()),
+        }
+    }
+
+    pub fn alias(&self, name: &str) -> Option<&str> {
+        self.aliases.get(name).map(String::as_str)
+    }
+
+    pub fn aliases(&self) -> impl Iterator<Item = (&String, &String)> {
+        self.aliases.iter()
+    }
+
+    pub fn set_alias(&mut self, name: &str, value: String) {
+        self.aliases.insert(name.to_string(), value);
+    }
+
+    /// Removes an alias, returning whether it was defined.
+    pub fn remove_alias(&mut self, name: &str) -> bool {
+        self.aliases.remove(name).is_some()
+    }
+
+    pub fn clear_aliases(&mut self) {
+        self.aliases.clear();
+    }
+
+    pub fn active_aliases(&self) -> &[String] {
+        &self.active_aliases
+    }
+
+    /// Runs `f` for the commands an alias expanded to, with `names` being
+    /// the aliases it took to get there.
+    pub fn with_active_aliases<T>(
+        &mut self,
+        names: Vec<String>,
+        f: impl FnOnce(&mut Self) -> T,
+    ) -> T {
+        let saved = std::mem::replace(&mut self.active_aliases, names);
+        let res = f(self);
+        self.active_aliases = saved;
+
+        res
+    }
+
+    /// Runs `f` for the commands of the script `name`, restoring the
+    /// location of the one that ran it afterwards.
+    pub fn in_script<T>(&mut self, name: &str, f: impl FnOnce(&mut Self) -> T) -> T {
+        let saved = self.script.replace((name.to_string(), 0));
+        let res = f(self);
+        self.script = saved;
+
+        res
+    }
+
+    /// Records the line of the script command about to run.
+    pub fn set_script_line(&mut self, line: usize) {
+        if let Some((_, current)) = &mut self.script {
+            *current = line;
+        }
+    }
+
+    /// Formats an error for reporting, prefixed with where it happened when
+    /// a script is running, as in `setup.sh: line 3: foo: command not found`.
+    pub fn error_message(&self, err: &dyn fmt::Display) -> String {
+        match &self.script {
+            Some((name, line)) => format!("{name}: line {line}: {err}"),
+            None => err.to_string(),
+        }
+    }
+
+    /// Runs `f` as `source` does, with `args` as the positional parameters
+    /// if any are given.
+    pub fn source<T>(&mut self, args: Vec<String>, f: impl FnOnce(&mut Self) -> T) -> T {
+        let saved = (!args.is_empty()).then(|| std::mem::replace(&mut self.positional, args));
+        self.sourcing += 1;
+        let res = f(self);
+        self.sourcing -= 1;
+        if let Some(saved) = saved {
+            self.positional = saved;
+        }
+
+        res
+    }
+
+    pub fn is_sourcing(&self) -> bool {
+        self.sourcing > 0
+    }
+
+    pub fn loop_depth(&self) -> usize {
+        self.loop_depth
+    }
+
+    pub fn enter_loop(&mut self) {
+        self.loop_depth += 1;
+    }
+
+    pub fn leave_loop(&mut self) {
+        self.loop_depth -= 1;
+    }
+
+    pub fn jobs(&self) -> &JobCtx {
+        &self.jobs
+    }
+
+    pub fn jobs_mut(&mut self) -> &mut JobCtx {
+        &mut self.jobs
+    }
+
+    pub fn shopt(&self, name: &str) -> bool {
+        self.shopts.contains(name)
+    }
+
+    pub fn set_shopt(&mut self, name: &str, enabled: bool) -> Result<()> {
+        let Some(name) = SHELL_OPTIONS.iter().find(|option| **option == name) else {
+            return Err(
+                ShellError::Execution(format!("shopt: {name}: invalid shell option name")).into(),
+            );
+        };
+
+        match enabled {
+            true => self.shopts.insert(name),
+            false => self.shopts.remove(name),
+        };
+        Ok(())
+    }
+
+    pub fn edit_mode(&self) -> EditMode {
+        self.edit_mode
+    }
+
+    pub fn set_edit_mode(&mut self, mode: EditMode) {
+        self.edit_mode = mode;
+    }
+
+    pub fn keymap(&self) -> &Keymap {
+        &self.keymap
+    }
+
+    pub fn keymap_mut(&mut self) -> &mut Keymap {
+        &mut self.keymap
+    }
+
+    /// The shell's own process group when job control is enabled.
+    pub fn job_control(&self) -> Option<i32> {
+        self.job_control
+    }
+
+    pub fn set_job_control(&mut self, shell_pgid: Option<i32>) {
+        self.job_control = shell_pgid;
+    }
+
+    pub fn is_interactive(&self) -> bool {
+        self.interactive
+    }
+
+    pub fn set_interactive(&mut self, interactive: bool) {
+        self.interactive = interactive;
+    }
+
+    /// Takes over the terminal for job control when running interactively.
+    pub fn enable_job_control(&mut self) {
+        self.job_control = crate::process::init_job_control();
+    }
+
+    /// Checks on background jobs and returns the lines to report for those
+    /// that finished or stopped since the last prompt.
+    pub fn reap_jobs(&mut self) -> Vec<String> {
+        self.jobs.poll();
+        self.jobs.notifications()
+    }
+
+    pub fn set_var(&mut self, name: &str, value: String) -> Result<()> {
+        self.vars.set(name, value)
+    }
+
+    pub fn export_var(&mut self, name: &str, value: Option<String>) -> Result<()> {
+        self.vars.export(name, value)
+    }
+
+    pub fn unexport_var(&mut self, name: &str) {
+        self.vars.unexport(name);
+    }
+
+    pub fn readonly_var(&mut self, name: &str, value: Option<String>) -> Result<()> {
+        self.vars.set_readonly(name, value)
+    }
+
+    pub fn unset_var(&mut self, name: &str) -> Result<()> {
+        self.vars.unset(name)
+    }
+
+    pub fn vars(&self) -> impl Iterator<Item = (&String, &Variable)> {
+        self.vars.all()
+    }
+
+    pub fn exported_vars(&self) -> BTreeMap<String, String> {
+        self.vars.exported()
+    }
+
+    /// Runs `f` with `assignments` in effect, as for `FOO=bar cmd`.
+    pub fn with_temporary_vars<T>(
+        &mut self,
+        assignments: &[(String, String)],
+        f: impl FnOnce(&mut Self) -> Result<T>,
+    ) -> Result<T> {
+        let saved = self.vars.assign_temporary(assignments)?;
+        let res = f(self);
+        self.vars.restore(saved);
+
+        res
     }
 
     pub fn set_read_history(&mut self, path: &str) -> Result<()> {
@@ -162,14 +611,26 @@ impl ShellCtx {
         &self.history.entries
     }
 
+    /// Finds the entry nearest to `from`, which counts itself, that contains
+    /// `query`, looking at older entries when `reverse` is set and at newer
+    /// ones otherwise.
+    pub fn search_history(&self, query: &str, from: usize, reverse: bool) -> Option<usize> {
+        let entries = &self.history.entries;
+        let matches = |idx: &usize| entries[*idx].contains(query);
+
+        match reverse {
+            true => (0..=from.min(entries.len().checked_sub(1)?))
+                .rev()
+                .find(matches),
+            false => (from..entries.len()).find(matches),
+        }
+    }
+
     pub fn get_history_entry(&mut self, pos: usize, current: String) -> String {
         let current_buf = self.current_buf.get_or_insert(current);
 
-        self.history
-            .entries
-            .iter()
-            .rev()
-            .nth(pos.saturating_sub(1))
+        pos.checked_sub(1)
+            .and_then(|pos| self.history.entries.iter().rev().nth(pos))
             .cloned()
             .unwrap_or_else(|| current_buf.clone())
     }
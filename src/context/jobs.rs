@@ -0,0 +1,329 @@
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum JobState {
+    Running,
+    Stopped,
+    Done(i32),
+}
+
+impl fmt::Display for JobState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JobState::Running => write!(f, "Running"),
+            JobState::Stopped => write!(f, "Stopped"),
+            JobState::Done(0) => write!(f, "Done"),
+            JobState::Done(status) => write!(f, "Exit {status}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Process {
+    pid: i32,
+    status: Option<i32>,
+    stopped: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct Job {
+    pub id: usize,
+    pub pgid: i32,
+    pub command: String,
+    pub state: JobState,
+    /// Terminal modes saved when the job stopped, restored when it is
+    /// brought back to the foreground.
+    pub modes: Option<libc::termios>,
+    processes: Vec<Process>,
+    notified: bool,
+}
+
+impl Job {
+    /// A running job that is not in the job table yet.
+    pub fn new(pgid: i32, pids: Vec<i32>, command: String) -> Self {
+        let processes = pids
+            .into_iter()
+            .map(|pid| Process {
+                pid,
+                status: None,
+                stopped: false,
+            })
+            .collect();
+
+        Job {
+            id: 0,
+            pgid,
+            command,
+            state: JobState::Running,
+            modes: None,
+            processes,
+            notified: true,
+        }
+    }
+
+    pub fn pids(&self) -> impl Iterator<Item = i32> + '_ {
+        self.processes.iter().map(|process| process.pid)
+    }
+
+    fn update(&mut self, pid: i32, wstatus: i32) {
+        let Some(process) = self.processes.iter_mut().find(|process| process.pid == pid) else {
+            return;
+        };
+
+        if libc::WIFSTOPPED(wstatus) {
+            process.stopped = true;
+        } else if libc::WIFCONTINUED(wstatus) {
+            process.stopped = false;
+        } else {
+            process.status = Some(crate::process::wait_status_code(wstatus));
+        }
+
+        let state = if self
+            .processes
+            .iter()
+            .all(|process| process.status.is_some())
+        {
+            let status = self.processes.last().and_then(|process| process.status);
+            JobState::Done(status.unwrap_or(0))
+        } else if self
+            .processes
+            .iter()
+            .all(|process| process.status.is_some() || process.stopped)
+        {
+            JobState::Stopped
+        } else {
+            JobState::Running
+        };
+
+        if state != self.state {
+            self.state = state;
+            self.notified = false;
+        }
+    }
+
+    /// Sends `SIGCONT` to the whole process group.
+    pub fn resume(&mut self) {
+        unsafe { libc::kill(-self.pgid, libc::SIGCONT) };
+
+        for process in &mut self.processes {
+            process.stopped = false;
+        }
+        self.state = JobState::Running;
+    }
+
+    /// Waits for the job to finish, or to stop when `untraced` is set.
+    pub fn wait(&mut self, untraced: bool) {
+        let flags = if untraced { libc::WUNTRACED } else { 0 };
+        self.wait_with(flags, false);
+    }
+
+    /// Waits for the job to finish unless Ctrl-C interrupts the shell first,
+    /// in which case this returns `false`.
+    pub fn wait_interruptible(&mut self) -> bool {
+        self.wait_with(0, true)
+    }
+
+    fn wait_with(&mut self, flags: i32, interruptible: bool) -> bool {
+        while self.state == JobState::Running {
+            let Some(pid) = self
+                .processes
+                .iter()
+                .find(|process| process.status.is_none() && !process.stopped)
+                .map(|process| process.pid)
+            else {
+                break;
+            };
+
+            let mut wstatus = 0;
+            let res = unsafe { libc::waitpid(pid, &mut wstatus, flags) };
+            if res == pid {
+                self.update(pid, wstatus);
+            } else if res == -1
+                && std::io::Error::last_os_error().raw_os_error() != Some(libc::EINTR)
+            {
+                // The process is gone without us seeing it exit.
+                self.update(pid, 0);
+            } else if interruptible && crate::process::take_interrupt() {
+                return false;
+            }
+        }
+
+        true
+    }
+
+    fn poll(&mut self) {
+        let flags = libc::WNOHANG | libc::WUNTRACED | libc::WCONTINUED;
+
+        for pid in self.pids().collect::<Vec<_>>() {
+            let mut wstatus = 0;
+            if unsafe { libc::waitpid(pid, &mut wstatus, flags) } == pid {
+                self.update(pid, wstatus);
+            }
+        }
+    }
+}
+
+/// The job table, ordered by job number.
+#[derive(Debug, Clone, Default)]
+pub struct JobCtx {
+    jobs: Vec<Job>,
+    recent: Vec<usize>,
+    /// Set in a subshell, whose copy of the shell's jobs is only there for
+    /// `jobs | ...` to list, as they are not its children to wait for.
+    inherited: bool,
+}
+
+impl JobCtx {
+    /// Adds a job to the table, numbering it unless it already has a number
+    /// from an earlier stay in the table. The caller reports the new job.
+    pub fn add(&mut self, mut job: Job) -> usize {
+        if self.inherited {
+            self.clear();
+        }
+        if job.id == 0 {
+            job.id = self.jobs.last().map_or(1, |job| job.id + 1);
+        }
+        job.notified = true;
+        let id = job.id;
+
+        let idx = self.jobs.partition_point(|other| other.id < id);
+        self.jobs.insert(idx, job);
+        self.touch(id);
+
+        id
+    }
+
+    pub fn get(&self, id: usize) -> Option<&Job> {
+        self.jobs.iter().find(|job| job.id == id)
+    }
+
+    pub fn get_mut(&mut self, id: usize) -> Option<&mut Job> {
+        self.jobs.iter_mut().find(|job| job.id == id)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Job> {
+        self.jobs.iter()
+    }
+
+    pub fn remove(&mut self, id: usize) -> Option<Job> {
+        self.recent.retain(|recent| *recent != id);
+        let idx = self.jobs.iter().position(|job| job.id == id)?;
+
+        Some(self.jobs.remove(idx))
+    }
+
+    pub fn clear(&mut self) {
+        self.jobs.clear();
+        self.recent.clear();
+        self.inherited = false;
+    }
+
+    /// Keeps the table in a subshell for listing only, until the subshell
+    /// starts a job of its own.
+    pub fn inherit(&mut self) {
+        self.inherited = true;
+    }
+
+    /// Marks the job as the current one, `%+`.
+    pub fn touch(&mut self, id: usize) {
+        self.recent.retain(|recent| *recent != id);
+        self.recent.push(id);
+    }
+
+    /// Resolves a job spec such as `%2`, `%+`, `%-` or `%vim`, where no spec
+    /// means the current job.
+    pub fn find(&self, spec: Option<&str>) -> Option<usize> {
+        let spec = spec.map(|spec| spec.strip_prefix('%').unwrap_or(spec));
+
+        match spec {
+            None | Some("") | Some("%") | Some("+") => self.recent.last().copied(),
+            Some("-") => self.recent.iter().rev().nth(1).copied(),
+            Some(spec) => match spec.parse::<usize>() {
+                Ok(id) => self.get(id).map(|job| job.id),
+                Err(_) => self
+                    .jobs
+                    .iter()
+                    .find(|job| job.command.starts_with(spec))
+                    .map(|job| job.id),
+            },
+        }
+    }
+
+    pub fn find_by_pid(&self, pid: i32) -> Option<usize> {
+        self.jobs
+            .iter()
+            .find(|job| job.pids().any(|job_pid| job_pid == pid))
+            .map(|job| job.id)
+    }
+
+    pub fn format(&self, job: &Job, long: bool) -> String {
+        let marker = if self.recent.last() == Some(&job.id) {
+            '+'
+        } else if self.recent.iter().rev().nth(1) == Some(&job.id) {
+            '-'
+        } else {
+            ' '
+        };
+        let background = if job.state == JobState::Running {
+            " &"
+        } else {
+            ""
+        };
+
+        let pgid = if long {
+            format!("{} ", job.pgid)
+        } else {
+            String::new()
+        };
+
+        format!(
+            "[{}]{}  {}{:<24}{}{}",
+            job.id,
+            marker,
+            pgid,
+            job.state.to_string(),
+            job.command,
+            background
+        )
+    }
+
+    pub fn poll(&mut self) {
+        if self.inherited {
+            return;
+        }
+        for job in &mut self.jobs {
+            job.poll();
+        }
+    }
+
+    /// Reports jobs that changed state since they were last shown, and
+    /// forgets those that are done.
+    pub fn notifications(&mut self) -> Vec<String> {
+        let lines = self
+            .jobs
+            .iter()
+            .filter(|job| !job.notified)
+            .map(|job| self.format(job, false))
+            .collect();
+
+        for job in &mut self.jobs {
+            job.notified = true;
+        }
+        self.remove_done();
+
+        lines
+    }
+
+    pub fn remove_done(&mut self) {
+        let done: Vec<usize> = self
+            .jobs
+            .iter()
+            .filter(|job| matches!(job.state, JobState::Done(_)))
+            .map(|job| job.id)
+            .collect();
+
+        for id in done {
+            self.remove(id);
+        }
+    }
+}
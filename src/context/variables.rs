@@ -0,0 +1,159 @@
+use std::{collections::BTreeMap, env};
+
+use anyhow::Result;
+
+use crate::ShellError;
+
+#[derive(Clone, Debug, Default)]
+pub struct Variable {
+    pub value: Option<String>,
+    pub exported: bool,
+    pub readonly: bool,
+}
+
+#[derive(Clone, Debug)]
+pub struct VarCtx {
+    vars: BTreeMap<String, Variable>,
+}
+
+impl VarCtx {
+    pub fn build() -> Self {
+        let vars = env::vars()
+            .map(|(name, value)| {
+                let var = Variable {
+                    value: Some(value),
+                    exported: true,
+                    readonly: false,
+                };
+                (name, var)
+            })
+            .collect();
+
+        VarCtx { vars }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.vars.get(name).and_then(|var| var.value.as_deref())
+    }
+
+    pub fn all(&self) -> impl Iterator<Item = (&String, &Variable)> {
+        self.vars.iter()
+    }
+
+    pub fn set(&mut self, name: &str, value: String) -> Result<()> {
+        let var = self.writable(name)?;
+        var.value = Some(value);
+
+        Ok(())
+    }
+
+    pub fn export(&mut self, name: &str, value: Option<String>) -> Result<()> {
+        let var = match value {
+            Some(_) => self.writable(name)?,
+            None => self.vars.entry(name.to_string()).or_default(),
+        };
+        var.exported = true;
+        if value.is_some() {
+            var.value = value;
+        }
+
+        Ok(())
+    }
+
+    pub fn unexport(&mut self, name: &str) {
+        if let Some(var) = self.vars.get_mut(name) {
+            var.exported = false;
+        }
+    }
+
+    pub fn set_readonly(&mut self, name: &str, value: Option<String>) -> Result<()> {
+        let var = match value {
+            Some(_) => self.writable(name)?,
+            None => self.vars.entry(name.to_string()).or_default(),
+        };
+        var.readonly = true;
+        if value.is_some() {
+            var.value = value;
+        }
+
+        Ok(())
+    }
+
+    pub fn unset(&mut self, name: &str) -> Result<()> {
+        if self.vars.get(name).is_some_and(|var| var.readonly) {
+            return Err(ShellError::Execution(format!(
+                "unset: {name}: cannot unset: readonly variable"
+            ))
+            .into());
+        }
+        self.vars.remove(name);
+
+        Ok(())
+    }
+
+    /// The environment handed to external commands.
+    pub fn exported(&self) -> BTreeMap<String, String> {
+        self.vars
+            .iter()
+            .filter(|(_, var)| var.exported)
+            .filter_map(|(name, var)| Some((name.clone(), var.value.clone()?)))
+            .collect()
+    }
+
+    /// Applies assignments that only last for a single command and returns
+    /// what is needed to undo them again with [`VarCtx::restore`].
+    pub fn assign_temporary(
+        &mut self,
+        assignments: &[(String, String)],
+    ) -> Result<Vec<(String, Option<Variable>)>> {
+        let mut saved = Vec::new();
+
+        for (name, value) in assignments {
+            if let Err(e) = self.writable(name) {
+                self.restore(saved);
+                return Err(e);
+            }
+            saved.push((name.clone(), self.vars.get(name).cloned()));
+
+            let var = self.vars.entry(name.clone()).or_default();
+            var.value = Some(value.clone());
+            var.exported = true;
+        }
+
+        Ok(saved)
+    }
+
+    /// Replaces a variable with an unset one for `local`, and returns what
+    /// to put back with [`VarCtx::restore`] once the function returns.
+    pub fn localize(&mut self, name: &str) -> Result<Option<Variable>> {
+        if self.vars.get(name).is_some_and(|var| var.readonly) {
+            return Err(ShellError::Execution(format!("{name}: readonly variable")).into());
+        }
+
+        Ok(self.vars.insert(name.to_string(), Variable::default()))
+    }
+
+    pub fn restore(&mut self, saved: Vec<(String, Option<Variable>)>) {
+        for (name, var) in saved.into_iter().rev() {
+            match var {
+                Some(var) => self.vars.insert(name, var),
+                None => self.vars.remove(&name),
+            };
+        }
+    }
+
+    fn writable(&mut self, name: &str) -> Result<&mut Variable> {
+        let var = self.vars.entry(name.to_string()).or_default();
+        if var.readonly {
+            return Err(ShellError::Execution(format!("{name}: readonly variable")).into());
+        }
+
+        Ok(var)
+    }
+}
+
+pub fn is_valid_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
@@ -1,19 +1,86 @@
+use std::io;
+
 use thiserror::Error;
 
 #[derive(Error, Debug)]
 pub enum ShellError {
     #[error("{0}")]
     Execution(String),
+    #[error("{0}: command not found")]
+    CommandNotFound(String),
     #[error("{0:?}: Failed to write to file")]
     WriteFile(std::fs::File),
     #[error("{0}: Failed to create file")]
     CreateFile(String),
-    #[error("Missing endquote")]
-    MissingQuote,
     #[error("End of line error")]
     Eol,
     #[error("Missing argument")]
     MissingArg,
     #[error("Failed to parse command")]
     Parsing,
+    #[error("syntax error near unexpected token `{0}'")]
+    Syntax(String),
+    #[error("syntax error: unexpected end of file")]
+    Incomplete,
+    #[error("{0}: {1}")]
+    Redirect(String, String),
+    /// A glob that matched nothing with `failglob` set, which abandons the
+    /// whole line it is on.
+    #[error("no match: {0}")]
+    NoMatch(String),
+    /// An assignment such as `name=value` to a readonly variable, which a
+    /// shell that isn't interactive can't carry on from.
+    #[error("{0}: readonly variable")]
+    ReadonlyAssignment(String),
+    /// Ctrl-C while typing or expanding a command, which abandons it. The
+    /// `^C` line was already ended by then.
+    #[error("")]
+    Interrupted,
+    /// Ctrl-Z during a command substitution, which abandons the command
+    /// once the stopped job is reported.
+    #[error("")]
+    Stopped,
+}
+
+impl ShellError {
+    pub fn status(&self) -> i32 {
+        match self {
+            ShellError::MissingArg
+            | ShellError::Parsing
+            | ShellError::Syntax(_)
+            | ShellError::Incomplete => 2,
+            ShellError::CommandNotFound(_) => 127,
+            ShellError::Interrupted => 128 + libc::SIGINT,
+            ShellError::Stopped => 128 + libc::SIGTSTP,
+            _ => 1,
+        }
+    }
+}
+
+/// Describes a failure to open a redirect target the way other shells do,
+/// e.g. `missing.txt: No such file or directory`.
+pub fn redirect_error(path: &str, err: io::Error) -> ShellError {
+    ShellError::Redirect(path.to_string(), io_error_reason(&err))
+}
+
+/// The message for an I/O error without the trailing `(os error N)`.
+pub fn io_error_reason(err: &io::Error) -> String {
+    let reason = err.to_string();
+    match reason.find(" (os error") {
+        Some(end) => reason[..end].to_string(),
+        None => reason,
+    }
+}
+
+/// Maps an error to the exit status a shell would report for it.
+pub fn error_status(err: &anyhow::Error) -> i32 {
+    if let Some(err) = err.downcast_ref::<ShellError>() {
+        return err.status();
+    }
+
+    match err.downcast_ref::<io::Error>().map(io::Error::kind) {
+        Some(io::ErrorKind::NotFound) => 127,
+        Some(io::ErrorKind::PermissionDenied) => 126,
+        _ => 1,
+    }
 }
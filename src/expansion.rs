@@ -0,0 +1,287 @@
+use anyhow::Result;
+
+use super::{ShellCtx, ShellError, Word, WordPart, arithmetic, glob, is_valid_name, process};
+
+/// Where a character of an expanded word came from, which decides whether
+/// it takes part in pathname expansion.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Origin {
+    Literal,
+    Quoted,
+    Expanded,
+    /// The break between two parameters of a quoted `$@`, which always
+    /// separates fields.
+    Boundary,
+}
+
+/// A word after variable expansion, before pathname expansion.
+#[derive(Debug, Default)]
+struct Expanded {
+    chars: Vec<(char, Origin)>,
+}
+
+impl Expanded {
+    fn push_str(&mut self, text: &str, origin: Origin) {
+        self.chars.extend(text.chars().map(|c| (c, origin)));
+    }
+
+    fn text(&self) -> String {
+        self.chars.iter().map(|(c, _)| c).collect()
+    }
+
+    fn has_glob(&self) -> bool {
+        self.chars
+            .iter()
+            .any(|(c, origin)| *origin != Origin::Quoted && matches!(c, '*' | '?' | '['))
+    }
+
+    /// The word as a glob pattern, with quoted metacharacters escaped.
+    fn pattern(&self) -> String {
+        let mut pattern = String::new();
+        for (c, origin) in &self.chars {
+            if *origin == Origin::Quoted && matches!(c, '*' | '?' | '[' | ']' | '\\') {
+                pattern.push('\\');
+            }
+            pattern.push(*c);
+        }
+
+        pattern
+    }
+}
+
+/// Expands command words, splitting the results of unquoted expansions into
+/// fields and expanding unquoted glob characters into paths.
+pub fn expand_words(words: &[Word], ctx: &mut ShellCtx) -> Result<Vec<String>> {
+    let mut fields = Vec::new();
+
+    for word in words {
+        if let Some(expanded) = expand(word, ctx)? {
+            for field in split_fields(expanded, ctx) {
+                fields.extend(expand_pathname(&field, ctx)?);
+            }
+        }
+    }
+
+    Ok(fields)
+}
+
+/// Expands the words of a simple command. The `name=value` arguments of
+/// `local`, `export` and `readonly` are expanded like assignments, without
+/// field splitting or pathname expansion.
+pub fn expand_command(words: &[Word], ctx: &mut ShellCtx) -> Result<Vec<String>> {
+    const DECLARATIONS: [&str; 3] = ["local", "export", "readonly"];
+
+    let Some((first, rest)) = words.split_first() else {
+        return Ok(Vec::new());
+    };
+    let mut fields = expand_words(std::slice::from_ref(first), ctx)?;
+    let is_declaration = fields
+        .first()
+        .is_some_and(|name| DECLARATIONS.contains(&name.as_str()));
+
+    for word in rest {
+        let is_assignment = matches!(word.0.first(), Some(WordPart::Literal(text))
+            if text.split_once('=').is_some_and(|(name, _)| is_valid_name(name)));
+        match is_declaration && is_assignment {
+            true => fields.push(expand_word(word, ctx)?.unwrap_or_default()),
+            false => fields.extend(expand_words(std::slice::from_ref(word), ctx)?),
+        }
+    }
+
+    Ok(fields)
+}
+
+/// Expands a single word without field splitting or pathname expansion. A
+/// word made up only of unquoted expansions that give nothing disappears,
+/// as in `echo $UNSET foo`.
+pub fn expand_word(word: &Word, ctx: &mut ShellCtx) -> Result<Option<String>> {
+    Ok(expand(word, ctx)?.map(|expanded| expanded.text()))
+}
+
+/// Expands a word that has to stay exactly one word, such as a redirect target.
+pub fn expand_single(word: &Word, ctx: &mut ShellCtx) -> Result<String> {
+    let ambiguous = || ShellError::Execution("ambiguous redirect".to_string());
+
+    let expanded = expand(word, ctx)?.ok_or_else(ambiguous)?;
+    let mut fields = expand_pathname(&expanded, ctx)?;
+    match fields.len() {
+        1 => Ok(fields.remove(0)),
+        _ => Err(ambiguous().into()),
+    }
+}
+
+/// Expands a `case` pattern, keeping quoted glob characters literal.
+pub fn expand_pattern(word: &Word, ctx: &mut ShellCtx) -> Result<String> {
+    Ok(expand(word, ctx)?
+        .map(|expanded| expanded.pattern())
+        .unwrap_or_default())
+}
+
+/// Evaluates the expression of a `((...))` command after expanding it.
+pub fn expand_arithmetic(expression: &Word, ctx: &mut ShellCtx) -> Result<i64> {
+    evaluate(&expression.0, ctx)
+}
+
+fn expand(word: &Word, ctx: &mut ShellCtx) -> Result<Option<Expanded>> {
+    let mut expanded = Expanded::default();
+    let mut is_word = false;
+
+    for part in &word.0 {
+        match part {
+            WordPart::Variable(_) | WordPart::Command(_) => {
+                let len = expanded.chars.len();
+                expand_part(part, &mut expanded, ctx)?;
+                is_word |= expanded.chars.len() > len;
+            }
+            // `"$@"` gives no word at all without positional parameters.
+            WordPart::DoubleQuoted(parts) if is_parameters(parts) => {
+                is_word |= !ctx.positional().is_empty();
+                expand_part(part, &mut expanded, ctx)?;
+            }
+            part => {
+                is_word = true;
+                expand_part(part, &mut expanded, ctx)?;
+            }
+        }
+    }
+
+    Ok(is_word.then_some(expanded))
+}
+
+fn expand_part(part: &WordPart, expanded: &mut Expanded, ctx: &mut ShellCtx) -> Result<()> {
+    match part {
+        WordPart::Literal(text) => expanded.push_str(text, Origin::Literal),
+        WordPart::Quoted(text) => expanded.push_str(text, Origin::Quoted),
+        WordPart::Variable(name) => {
+            expanded.push_str(&ctx.get_var(name).unwrap_or_default(), Origin::Expanded)
+        }
+        WordPart::Command(command) => {
+            expanded.push_str(&substitute(command, ctx)?, Origin::Expanded)
+        }
+        WordPart::Arithmetic(parts) => {
+            let value = evaluate(parts, ctx)?;
+            expanded.push_str(&value.to_string(), Origin::Expanded)
+        }
+        WordPart::DoubleQuoted(parts) => {
+            for part in parts {
+                if is_parameters(std::slice::from_ref(part)) {
+                    for (i, param) in ctx.positional().iter().enumerate() {
+                        if i > 0 {
+                            expanded.push_str(" ", Origin::Boundary);
+                        }
+                        expanded.push_str(param, Origin::Quoted);
+                    }
+                    continue;
+                }
+
+                let mut inner = Expanded::default();
+                expand_part(part, &mut inner, ctx)?;
+                expanded.push_str(&inner.text(), Origin::Quoted);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Whether the parts are just `$@`, which gives one word per parameter.
+fn is_parameters(parts: &[WordPart]) -> bool {
+    matches!(parts, [WordPart::Variable(name)] if name == "@")
+}
+
+fn evaluate(parts: &[WordPart], ctx: &mut ShellCtx) -> Result<i64> {
+    let mut expression = Expanded::default();
+    for part in parts {
+        expand_part(part, &mut expression, ctx)?;
+    }
+
+    arithmetic::evaluate(&expression.text(), ctx)
+}
+
+/// Runs the command of a `$(...)` in a subshell and returns its output
+/// without trailing newlines.
+fn substitute(command: &str, ctx: &mut ShellCtx) -> Result<String> {
+    let line = crate::parse(command)?;
+    let (mut output, status) =
+        process::capture_output(ctx, command, |ctx| crate::execute_line(&line, ctx))?;
+    ctx.set_substitution_status(status);
+
+    output.truncate(output.trim_end_matches('\n').len());
+    Ok(output)
+}
+
+/// Splits a word at the `IFS` characters that came from unquoted
+/// expansions. Runs of IFS whitespace count as one separator, while every
+/// other IFS character ends a field, even an empty one.
+fn split_fields(expanded: Expanded, ctx: &ShellCtx) -> Vec<Expanded> {
+    let ifs = ctx.get_var("IFS").unwrap_or_else(|| " \t\n".to_string());
+    let is_separator =
+        |(c, origin): &(char, Origin)| *origin == Origin::Expanded && ifs.contains(*c);
+    if !expanded
+        .chars
+        .iter()
+        .any(|char| is_separator(char) || char.1 == Origin::Boundary)
+    {
+        return vec![expanded];
+    }
+
+    let mut fields = Vec::new();
+    let mut field = Expanded::default();
+    // Whether the field came from `$@`, where it counts even when empty.
+    let mut is_parameter = false;
+    // Whether the field was ended by whitespace, which a following
+    // non-whitespace separator then belongs to.
+    let mut after_space = false;
+
+    for (c, origin) in expanded.chars {
+        if origin == Origin::Boundary {
+            fields.push(std::mem::take(&mut field));
+            after_space = false;
+            is_parameter = true;
+            continue;
+        }
+        if !is_separator(&(c, origin)) {
+            field.chars.push((c, origin));
+            after_space = false;
+            continue;
+        }
+
+        if c.is_whitespace() {
+            if !field.chars.is_empty() {
+                fields.push(std::mem::take(&mut field));
+                after_space = true;
+            }
+        } else if !(field.chars.is_empty() && after_space) {
+            fields.push(std::mem::take(&mut field));
+        } else {
+            after_space = false;
+        }
+    }
+    if !field.chars.is_empty() || is_parameter {
+        fields.push(field);
+    }
+
+    fields
+}
+
+/// Replaces a word holding unquoted glob characters with the paths it
+/// matches. What happens when nothing matches depends on the `nullglob`
+/// and `failglob` options.
+fn expand_pathname(expanded: &Expanded, ctx: &ShellCtx) -> Result<Vec<String>> {
+    if !expanded.has_glob() {
+        return Ok(vec![expanded.text()]);
+    }
+
+    let paths = glob::glob(&expanded.pattern(), ctx.shopt("dotglob"));
+    if !paths.is_empty() {
+        return Ok(paths);
+    }
+
+    if ctx.shopt("failglob") {
+        return Err(ShellError::NoMatch(expanded.text()).into());
+    }
+    match ctx.shopt("nullglob") {
+        true => Ok(Vec::new()),
+        false => Ok(vec![expanded.text()]),
+    }
+}
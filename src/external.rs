@@ -1,10 +1,16 @@
 use anyhow::{Ok, Result};
 use std::{
-    env, path,
+    collections::BTreeMap,
+    env,
+    os::unix::process::CommandExt,
+    path::{self, PathBuf},
     process::{self, Command},
 };
 
-use super::{ShellError, is_executable};
+use super::{ShellCtx, ShellError, is_executable};
+
+/// Where commands are looked for when `PATH` is unset, as in other shells.
+const DEFAULT_PATH: &str = "/usr/local/sbin:/usr/local/bin:/usr/sbin:/usr/bin:/sbin:/bin";
 
 #[derive(Debug)]
 pub struct External {
@@ -18,43 +24,51 @@ impl External {
 }
 
 impl External {
-    fn name(&self) -> &str {
+    pub fn name(&self) -> &str {
         &self.cmd
     }
 
-    pub fn description(&self) -> String {
-        let key = "PATH";
-        if let Some(paths) = env::var_os(key) {
-            for path in env::split_paths(&paths) {
-                let cmd_path = path.join(self.name());
-                if path::Path::new(&cmd_path).exists() & is_executable(&cmd_path) {
-                    return format!("{} is {}", self.name(), cmd_path.display());
-                }
-            }
-        }
-
-        format!("{}: not found", self.name())
+    /// What `type` says about the command, `None` when it is not in `PATH`.
+    pub fn description(&self, ctx: &ShellCtx) -> Option<String> {
+        let paths = ctx.get_var("PATH");
+        let cmd_path = find_in_path(self.name(), paths.as_deref().unwrap_or(DEFAULT_PATH))?;
+
+        Some(format!("{} is {}", self.name(), cmd_path.display()))
     }
 
-    pub fn build(&self, args: &Vec<String>) -> Result<Command> {
-        let mut program = build_command(self.name())?;
-        program.args(args);
+    /// Builds the command with exactly `envs` as its environment. It is
+    /// looked up in `paths`, the shell's `PATH`, whether or not that is
+    /// exported, or in a default path when it is unset.
+    pub fn build(
+        &self,
+        args: &[String],
+        envs: &BTreeMap<String, String>,
+        paths: Option<&str>,
+    ) -> Result<Command> {
+        let mut program = build_command(self.name(), paths.unwrap_or(DEFAULT_PATH))?;
+        program.args(args).env_clear().envs(envs);
 
         Ok(program)
     }
 }
 
-fn build_command(cmd: &str) -> Result<Command> {
-    let key = "PATH";
-    let paths = env::var(key)?;
-    for path in env::split_paths(&paths) {
-        let cmd_path = path.join(cmd);
-        if !path::Path::new(&cmd_path).exists() || !is_executable(&cmd_path) {
-            continue;
-        };
+fn find_in_path(cmd: &str, paths: &str) -> Option<PathBuf> {
+    env::split_paths(paths)
+        .map(|path| path.join(cmd))
+        .find(|cmd_path| path::Path::new(cmd_path).exists() && is_executable(cmd_path))
+}
 
+fn build_command(cmd: &str, paths: &str) -> Result<Command> {
+    if cmd.contains('/') {
         return Ok(process::Command::new(cmd));
     }
 
-    Err(ShellError::Execution(format!("{cmd}: command not found")).into())
+    let Some(cmd_path) = find_in_path(cmd, paths) else {
+        return Err(ShellError::CommandNotFound(cmd.to_string()).into());
+    };
+
+    let mut program = process::Command::new(cmd_path);
+    program.arg0(cmd);
+
+    Ok(program)
 }
@@ -0,0 +1,248 @@
+use std::{fs, path::Path};
+
+/// Whether the pattern has an unescaped `*`, `?` or `[`.
+pub fn has_magic(pattern: &str) -> bool {
+    let mut chars = pattern.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next();
+            }
+            '*' | '?' | '[' => return true,
+            _ => {}
+        }
+    }
+
+    false
+}
+
+/// Removes the backslashes that escape characters in a pattern.
+pub fn unescape(pattern: &str) -> String {
+    let mut text = String::with_capacity(pattern.len());
+    let mut chars = pattern.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => text.extend(chars.next()),
+            c => text.push(c),
+        }
+    }
+
+    text
+}
+
+/// Returns the paths matching the pattern in sorted order. Names starting
+/// with a dot are only matched by a literal dot, unless `dotglob` is set.
+pub fn glob(pattern: &str, dotglob: bool) -> Vec<String> {
+    let (mut paths, rest) = match pattern.strip_prefix('/') {
+        Some(rest) => (vec!["/".to_string()], rest),
+        None => (vec![String::new()], pattern),
+    };
+
+    let components: Vec<&str> = rest.split('/').collect();
+    for (i, component) in components.iter().enumerate() {
+        let is_last = i + 1 == components.len();
+        let mut next = Vec::new();
+
+        for base in &paths {
+            if component.is_empty() {
+                // A trailing slash only keeps directories.
+                if is_last && Path::new(base).is_dir() {
+                    next.push(format!("{base}/"));
+                }
+                continue;
+            }
+
+            if !has_magic(component) {
+                let path = join(base, &unescape(component));
+                if !is_last || Path::new(&path).symlink_metadata().is_ok() {
+                    next.push(path);
+                }
+                continue;
+            }
+
+            let dir = if base.is_empty() { "." } else { base.as_str() };
+            let Ok(entries) = fs::read_dir(dir) else {
+                continue;
+            };
+
+            let explicit_dot = component.starts_with('.') || component.starts_with("\\.");
+            for entry in entries.flatten() {
+                let Ok(name) = entry.file_name().into_string() else {
+                    continue;
+                };
+                if name.starts_with('.') && !(explicit_dot || dotglob) {
+                    continue;
+                }
+                if !matches(component, &name) {
+                    continue;
+                }
+
+                let path = join(base, &name);
+                if is_last || Path::new(&path).is_dir() {
+                    next.push(path);
+                }
+            }
+        }
+
+        paths = next;
+    }
+
+    paths.sort();
+    paths
+}
+
+fn join(base: &str, name: &str) -> String {
+    match base {
+        "" => name.to_string(),
+        base if base.ends_with('/') => format!("{base}{name}"),
+        base => format!("{base}/{name}"),
+    }
+}
+
+/// Matches a whole string against a pattern with `*`, `?`, bracket
+/// expressions and backslash escapes.
+pub fn matches(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+
+    matches_from(&pattern, &text)
+}
+
+fn matches_from(pattern: &[char], text: &[char]) -> bool {
+    let (mut p, mut t) = (0, 0);
+    // Where to resume after the last `*` when the rest fails to match.
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        let step = match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, t));
+                p += 1;
+                continue;
+            }
+            Some('?') => Some(1),
+            Some('[') => match match_bracket(&pattern[p..], text[t]) {
+                Some((matched, len)) => matched.then_some(len),
+                // An unclosed `[` matches itself.
+                None => (text[t] == '[').then_some(1),
+            },
+            Some('\\') if p + 1 < pattern.len() => (pattern[p + 1] == text[t]).then_some(2),
+            Some(c) => (*c == text[t]).then_some(1),
+            None => None,
+        };
+
+        match (step, backtrack) {
+            (Some(len), _) => {
+                p += len;
+                t += 1;
+            }
+            (None, Some((star, start))) => {
+                p = star + 1;
+                t = start + 1;
+                backtrack = Some((star, start + 1));
+            }
+            (None, None) => return false,
+        }
+    }
+
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+/// Matches `c` against the bracket expression at the start of `pattern`,
+/// returning whether it matched and the expression's length, or `None`
+/// when the bracket is never closed.
+fn match_bracket(pattern: &[char], c: char) -> Option<(bool, usize)> {
+    let mut i = 1;
+    let negate = matches!(pattern.get(i), Some('!' | '^'));
+    if negate {
+        i += 1;
+    }
+
+    let mut matched = false;
+    let mut first = true;
+    loop {
+        let current = *pattern.get(i)?;
+        if current == ']' && !first {
+            break;
+        }
+        first = false;
+
+        if current == '['
+            && pattern.get(i + 1) == Some(&':')
+            && let Some(len) = pattern[i + 2..]
+                .windows(2)
+                .position(|end| end == [':', ']'])
+        {
+            let class: String = pattern[i + 2..i + 2 + len].iter().collect();
+            matched |= match_class(&class, c);
+            i += len + 4;
+            continue;
+        }
+
+        let current = match current {
+            '\\' => {
+                i += 1;
+                *pattern.get(i)?
+            }
+            current => current,
+        };
+
+        if pattern.get(i + 1) == Some(&'-') && pattern.get(i + 2).is_some_and(|end| *end != ']') {
+            matched |= (current..=pattern[i + 2]).contains(&c);
+            i += 3;
+        } else {
+            matched |= current == c;
+            i += 1;
+        }
+    }
+
+    Some((matched != negate, i + 1))
+}
+
+fn match_class(class: &str, c: char) -> bool {
+    match class {
+        "alpha" => c.is_alphabetic(),
+        "digit" => c.is_ascii_digit(),
+        "alnum" => c.is_alphanumeric(),
+        "upper" => c.is_uppercase(),
+        "lower" => c.is_lowercase(),
+        "space" => c.is_whitespace(),
+        "punct" => c.is_ascii_punctuation(),
+        "xdigit" => c.is_ascii_hexdigit(),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn patterns() {
+        assert!(matches("*.rs", "main.rs"));
+        assert!(!matches("*.rs", "main.rsx"));
+        assert!(matches("a*b*c", "aXbYbc"));
+        assert!(matches("?at", "cat"));
+        assert!(!matches("?at", "at"));
+        assert!(matches("[ch]at", "hat"));
+        assert!(!matches("[!ch]at", "hat"));
+        assert!(matches("[a-c]x", "bx"));
+        assert!(matches("[]]", "]"));
+        assert!(matches("[[:digit:]]*", "1st"));
+        assert!(matches("\\*", "*"));
+        assert!(!matches("\\*", "x"));
+        assert!(matches("[", "["));
+        assert!(matches("*", ""));
+    }
+
+    #[test]
+    fn magic() {
+        assert!(has_magic("*.rs"));
+        assert!(has_magic("src/[ab]"));
+        assert!(!has_magic("\\*.rs"));
+        assert!(!has_magic("plain"));
+        assert_eq!(unescape("\\*a\\\\"), "*a\\");
+    }
+}
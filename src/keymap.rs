@@ -0,0 +1,728 @@
+use std::{fs, iter::Peekable, str::Chars};
+
+use anyhow::Result;
+use termion::event::Key;
+
+use crate::{EditMode, ShellCtx};
+
+/// A line-editing command that keys can be bound to, named as in readline.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Abort,
+    AcceptLine,
+    BackwardChar,
+    BackwardDeleteChar,
+    BackwardKillWord,
+    BackwardWord,
+    BeginningOfLine,
+    ClearScreen,
+    Complete,
+    DeleteChar,
+    EmacsEditingMode,
+    EndOfLine,
+    ForwardChar,
+    ForwardSearchHistory,
+    ForwardWord,
+    HistorySearchBackward,
+    HistorySearchForward,
+    KillLine,
+    KillWord,
+    NextHistory,
+    PreviousHistory,
+    ReReadInitFile,
+    ReverseSearchHistory,
+    SelfInsert,
+    TransposeChars,
+    Undo,
+    UnixLineDiscard,
+    UnixWordRubout,
+    ViEditingMode,
+    Yank,
+    YankPop,
+}
+
+const ACTIONS: [(Action, &str); 31] = [
+    (Action::Abort, "abort"),
+    (Action::AcceptLine, "accept-line"),
+    (Action::BackwardChar, "backward-char"),
+    (Action::BackwardDeleteChar, "backward-delete-char"),
+    (Action::BackwardKillWord, "backward-kill-word"),
+    (Action::BackwardWord, "backward-word"),
+    (Action::BeginningOfLine, "beginning-of-line"),
+    (Action::ClearScreen, "clear-screen"),
+    (Action::Complete, "complete"),
+    (Action::DeleteChar, "delete-char"),
+    (Action::EmacsEditingMode, "emacs-editing-mode"),
+    (Action::EndOfLine, "end-of-line"),
+    (Action::ForwardChar, "forward-char"),
+    (Action::ForwardSearchHistory, "forward-search-history"),
+    (Action::ForwardWord, "forward-word"),
+    (Action::HistorySearchBackward, "history-search-backward"),
+    (Action::HistorySearchForward, "history-search-forward"),
+    (Action::KillLine, "kill-line"),
+    (Action::KillWord, "kill-word"),
+    (Action::NextHistory, "next-history"),
+    (Action::PreviousHistory, "previous-history"),
+    (Action::ReReadInitFile, "re-read-init-file"),
+    (Action::ReverseSearchHistory, "reverse-search-history"),
+    (Action::SelfInsert, "self-insert"),
+    (Action::TransposeChars, "transpose-chars"),
+    (Action::Undo, "undo"),
+    (Action::UnixLineDiscard, "unix-line-discard"),
+    (Action::UnixWordRubout, "unix-word-rubout"),
+    (Action::ViEditingMode, "vi-editing-mode"),
+    (Action::Yank, "yank"),
+    (Action::YankPop, "yank-pop"),
+];
+
+impl Action {
+    /// Every action, in order of name.
+    pub fn all() -> impl Iterator<Item = Action> {
+        ACTIONS.iter().map(|(action, _)| *action)
+    }
+
+    pub fn name(self) -> &'static str {
+        ACTIONS
+            .iter()
+            .find(|(action, _)| *action == self)
+            .map(|(_, name)| *name)
+            .expect("every action has a name")
+    }
+
+    pub fn from_name(name: &str) -> Option<Action> {
+        ACTIONS
+            .iter()
+            .find(|(_, known)| known.eq_ignore_ascii_case(name))
+            .map(|(action, _)| *action)
+    }
+}
+
+/// What a key sequence does: run an action, or type the text of a macro.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Binding {
+    Action(Action),
+    Macro(String),
+}
+
+/// What the keys typed so far are bound to.
+#[derive(Debug, PartialEq)]
+pub enum Lookup<'a> {
+    Found(&'a Binding),
+    /// The start of a longer sequence, so more keys are needed.
+    Prefix,
+    None,
+}
+
+/// The key sequences bound in emacs mode, and in vi insert mode for keys vi
+/// leaves alone.
+#[derive(Debug, Clone)]
+pub struct Keymap {
+    bindings: Vec<(Vec<Key>, Binding)>,
+    /// The inputrc file read at startup, which `re-read-init-file` reads
+    /// again.
+    init_file: Option<String>,
+}
+
+impl Default for Keymap {
+    /// The readline defaults.
+    fn default() -> Self {
+        use Action::*;
+        let keys = [
+            (Key::Ctrl('a'), BeginningOfLine),
+            (Key::Home, BeginningOfLine),
+            (Key::Ctrl('e'), EndOfLine),
+            (Key::End, EndOfLine),
+            (Key::Ctrl('b'), BackwardChar),
+            (Key::Left, BackwardChar),
+            (Key::Ctrl('f'), ForwardChar),
+            (Key::Right, ForwardChar),
+            (Key::Alt('b'), BackwardWord),
+            (Key::AltLeft, BackwardWord),
+            (Key::CtrlLeft, BackwardWord),
+            (Key::Alt('f'), ForwardWord),
+            (Key::AltRight, ForwardWord),
+            (Key::CtrlRight, ForwardWord),
+            (Key::Ctrl('p'), PreviousHistory),
+            (Key::Up, PreviousHistory),
+            (Key::Ctrl('n'), NextHistory),
+            (Key::Down, NextHistory),
+            (Key::Ctrl('r'), ReverseSearchHistory),
+            (Key::Ctrl('s'), ForwardSearchHistory),
+            (Key::Char('\n'), AcceptLine),
+            (Key::Char('\t'), Complete),
+            (Key::Ctrl('d'), DeleteChar),
+            (Key::Delete, DeleteChar),
+            (Key::Backspace, BackwardDeleteChar),
+            (Key::Ctrl('h'), BackwardDeleteChar),
+            (Key::Ctrl('t'), TransposeChars),
+            (Key::Ctrl('k'), KillLine),
+            (Key::Ctrl('u'), UnixLineDiscard),
+            (Key::Ctrl('w'), UnixWordRubout),
+            (Key::Alt('d'), KillWord),
+            (Key::Alt('\x7f'), BackwardKillWord),
+            (Key::Ctrl('y'), Yank),
+            (Key::Alt('y'), YankPop),
+            // Ctrl-_, which the terminal sends as 0x1f.
+            (Key::Ctrl('7'), Undo),
+            (Key::Ctrl('l'), ClearScreen),
+            (Key::Ctrl('g'), Abort),
+        ];
+
+        let mut bindings: Vec<_> = keys
+            .into_iter()
+            .map(|(key, action)| (vec![key], Binding::Action(action)))
+            .collect();
+        bindings.push((
+            vec![Key::Ctrl('x'), Key::Ctrl('r')],
+            Binding::Action(ReReadInitFile),
+        ));
+        bindings.push((vec![Key::Ctrl('x'), Key::Ctrl('u')], Binding::Action(Undo)));
+
+        Keymap {
+            bindings,
+            init_file: None,
+        }
+    }
+}
+
+impl Keymap {
+    /// Looks up the keys typed so far. A sequence that is bound runs even
+    /// when it also starts a longer one.
+    pub fn lookup(&self, keys: &[Key]) -> Lookup<'_> {
+        let mut prefix = false;
+        for (bound, binding) in &self.bindings {
+            if bound == keys {
+                return Lookup::Found(binding);
+            }
+            prefix |= bound.starts_with(keys);
+        }
+        match prefix {
+            true => Lookup::Prefix,
+            false => Lookup::None,
+        }
+    }
+
+    pub fn bind(&mut self, keys: Vec<Key>, binding: Binding) {
+        match self.bindings.iter_mut().find(|(bound, _)| *bound == keys) {
+            Some((_, old)) => *old = binding,
+            None => self.bindings.push((keys, binding)),
+        }
+    }
+
+    /// Removes the binding of `keys`, returning whether there was one.
+    pub fn unbind(&mut self, keys: &[Key]) -> bool {
+        let before = self.bindings.len();
+        self.bindings.retain(|(bound, _)| bound != keys);
+        self.bindings.len() != before
+    }
+
+    pub fn unbind_action(&mut self, action: Action) {
+        self.bindings
+            .retain(|(_, binding)| *binding != Binding::Action(action));
+    }
+
+    /// The key sequences that run `action`.
+    pub fn keys_for(&self, action: Action) -> impl Iterator<Item = &[Key]> {
+        self.bindings
+            .iter()
+            .filter(move |(_, binding)| *binding == Binding::Action(action))
+            .map(|(keys, _)| keys.as_slice())
+    }
+
+    pub fn macros(&self) -> impl Iterator<Item = (&[Key], &str)> {
+        self.bindings
+            .iter()
+            .filter_map(|(keys, binding)| match binding {
+                Binding::Macro(text) => Some((keys.as_slice(), text.as_str())),
+                Binding::Action(_) => None,
+            })
+    }
+
+    pub fn init_file(&self) -> Option<&str> {
+        self.init_file.as_deref()
+    }
+}
+
+/// Reads an inputrc file into the keymap and remembers it for
+/// `re-read-init-file`, returning the problems with its lines.
+pub fn read_init_file(path: &str, ctx: &mut ShellCtx) -> Result<Vec<(usize, String)>> {
+    let text = fs::read_to_string(path)?;
+    ctx.keymap_mut().init_file = Some(path.to_string());
+    Ok(read_inputrc(&text, ctx))
+}
+
+/// Applies lines written as in an inputrc file, returning the problems
+/// with them by line number.
+pub fn read_inputrc(text: &str, ctx: &mut ShellCtx) -> Vec<(usize, String)> {
+    read_lines(text, ctx, 0)
+}
+
+/// How deeply `$include` may nest before it is taken to be a loop.
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+/// Applies the lines of an inputrc file: key bindings, `set editing-mode`,
+/// `$include` and `$if` with `mode=` or `term=`. Other variables are left
+/// alone as readline ignores ones it does not know.
+fn read_lines(text: &str, ctx: &mut ShellCtx, depth: usize) -> Vec<(usize, String)> {
+    let mut errors = Vec::new();
+    // Whether each `$if` being read is taking its branch.
+    let mut branches: Vec<bool> = Vec::new();
+
+    for (idx, line) in text.lines().enumerate() {
+        let line = line.trim();
+        let mut error = |msg: String| errors.push((idx + 1, msg));
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let (word, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let rest = rest.trim();
+        match word {
+            "$if" => branches.push(condition(rest, ctx)),
+            "$else" => match branches.last_mut() {
+                Some(taken) => *taken = !*taken,
+                None => error("$else found without matching $if".to_string()),
+            },
+            "$endif" => {
+                if branches.pop().is_none() {
+                    error("$endif without matching $if".to_string());
+                }
+            }
+            _ if branches.contains(&false) => {}
+            "$include" if depth >= MAX_INCLUDE_DEPTH => {
+                error(format!("{rest}: too many nested includes"));
+            }
+            "$include" => {
+                let path = match (rest.strip_prefix('~'), ctx.get_var("HOME")) {
+                    (Some(tail), Some(home)) => format!("{home}{tail}"),
+                    _ => rest.to_string(),
+                };
+                match fs::read_to_string(&path) {
+                    Ok(text) => {
+                        for (line, msg) in read_lines(&text, ctx, depth + 1) {
+                            error(format!("{path}: line {line}: {msg}"));
+                        }
+                    }
+                    Err(e) => error(format!("{path}: {}", crate::io_error_reason(&e))),
+                }
+            }
+            "set" => {
+                let (name, value) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+                if name != "editing-mode" {
+                    continue;
+                }
+                match EditMode::ALL
+                    .into_iter()
+                    .find(|mode| mode.name() == value.trim())
+                {
+                    Some(mode) => ctx.set_edit_mode(mode),
+                    None => error(format!("{}: invalid editing mode", value.trim())),
+                }
+            }
+            _ => match parse_binding(line) {
+                Ok((keys, binding)) => ctx.keymap_mut().bind(keys, binding),
+                Err(msg) => error(msg),
+            },
+        }
+    }
+
+    if !branches.is_empty() {
+        errors.push((text.lines().count(), "missing $endif".to_string()));
+    }
+    errors
+}
+
+/// Whether the test of a `$if` holds: `mode=` the editing mode or `term=`
+/// the terminal, which also matches the part of `$TERM` before a `-`.
+/// Tests for applications never hold, as their bindings are for others.
+fn condition(test: &str, ctx: &ShellCtx) -> bool {
+    match test.split_once('=') {
+        Some(("mode", mode)) => ctx.edit_mode().name() == mode.trim(),
+        Some(("term", term)) => ctx.get_var("TERM").is_some_and(|current| {
+            let term = term.trim();
+            current == term || current.split('-').next() == Some(term)
+        }),
+        _ => false,
+    }
+}
+
+/// Parses a binding as written in an inputrc file or given to `bind`:
+/// `"\C-x\C-e": function-name`, `Control-u: function-name` or
+/// `"\C-xg": "macro text"`.
+pub fn parse_binding(line: &str) -> Result<(Vec<Key>, Binding), String> {
+    let (keys, rest) = match line.strip_prefix('"') {
+        Some(quoted) => {
+            let end = closing_quote(quoted, '"')
+                .ok_or_else(|| format!("{line}: no closing `\"' in key binding"))?;
+            (parse_keys(&unescape(&quoted[..end]))?, &quoted[end + 1..])
+        }
+        None => {
+            let end = line.find(':').unwrap_or(line.len());
+            (parse_keys(&key_name(line[..end].trim())?)?, &line[end..])
+        }
+    };
+    let value = rest
+        .trim_start()
+        .strip_prefix(':')
+        .ok_or_else(|| format!("{line}: missing `:' after key sequence"))?
+        .trim();
+
+    if keys.is_empty() {
+        return Err(format!("{line}: empty key sequence"));
+    }
+
+    let binding = match value.chars().next() {
+        Some(quote @ ('"' | '\'')) => {
+            let text = &value[1..];
+            let end = closing_quote(text, quote).unwrap_or(text.len());
+            let bytes = unescape(&text[..end]);
+            Binding::Macro(String::from_utf8_lossy(&bytes).into_owned())
+        }
+        _ => {
+            let name = value.split_whitespace().next().unwrap_or_default();
+            let action =
+                Action::from_name(name).ok_or_else(|| format!("{name}: unknown function name"))?;
+            Binding::Action(action)
+        }
+    };
+    Ok((keys, binding))
+}
+
+/// Parses a key sequence on its own, as `bind -r` takes it, with or without
+/// the quotes.
+pub fn parse_keyseq(seq: &str) -> Result<Vec<Key>, String> {
+    let seq = seq
+        .strip_prefix('"')
+        .and_then(|seq| seq.strip_suffix('"'))
+        .unwrap_or(seq);
+    parse_keys(&unescape(seq))
+}
+
+/// Where the quote that ends `text` is, skipping escaped ones.
+fn closing_quote(text: &str, quote: char) -> Option<usize> {
+    let mut escaped = false;
+    for (idx, ch) in text.char_indices() {
+        match ch {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            _ if ch == quote => return Some(idx),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// The bytes of a key name such as `Control-u`, `M-DEL` or `TAB`.
+fn key_name(name: &str) -> Result<Vec<u8>, String> {
+    let mut rest = name;
+    let (mut control, mut meta) = (false, false);
+    loop {
+        if let Some(tail) = rest.strip_prefix("Control-").or(rest.strip_prefix("C-")) {
+            control = true;
+            rest = tail;
+        } else if let Some(tail) = rest.strip_prefix("Meta-").or(rest.strip_prefix("M-")) {
+            meta = true;
+            rest = tail;
+        } else {
+            break;
+        }
+    }
+
+    let byte = match rest.to_ascii_uppercase().as_str() {
+        "DEL" | "RUBOUT" => 0x7f,
+        "ESC" | "ESCAPE" => 0x1b,
+        "LFD" | "NEWLINE" => b'\n',
+        "RET" | "RETURN" => b'\r',
+        "SPC" | "SPACE" => b' ',
+        "TAB" => b'\t',
+        _ if rest.len() == 1 && rest.is_ascii() => rest.as_bytes()[0],
+        _ => return Err(format!("{name}: unknown key name")),
+    };
+
+    let byte = match (control, byte) {
+        (true, b'?') => 0x7f,
+        (true, byte) => byte & 0x1f,
+        (false, byte) => byte,
+    };
+    Ok(match meta {
+        true => vec![0x1b, byte],
+        false => vec![byte],
+    })
+}
+
+/// Turns the escapes of a quoted key sequence or macro into the bytes they
+/// stand for: `\C-x`, `\M-x`, `\e`, the C escapes and octal and hex codes.
+fn unescape(text: &str) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    let mut chars = text.chars().peekable();
+    while chars.peek().is_some() {
+        unescape_one(&mut chars, &mut bytes);
+    }
+    bytes
+}
+
+fn unescape_one(chars: &mut Peekable<Chars>, bytes: &mut Vec<u8>) {
+    let Some(ch) = chars.next() else {
+        return;
+    };
+    if ch != '\\' {
+        bytes.extend(ch.encode_utf8(&mut [0; 4]).as_bytes());
+        return;
+    }
+
+    let Some(escape) = chars.next() else {
+        bytes.push(b'\\');
+        return;
+    };
+    let byte = match escape {
+        'C' | 'M' if chars.peek() == Some(&'-') => {
+            chars.next();
+            let mut inner = Vec::new();
+            unescape_one(chars, &mut inner);
+            match (escape, inner.as_slice()) {
+                ('M', _) => bytes.push(0x1b),
+                ('C', [b'?']) => inner = vec![0x7f],
+                ('C', [byte]) => inner = vec![byte & 0x1f],
+                _ => {}
+            }
+            bytes.extend(inner);
+            return;
+        }
+        'e' => 0x1b,
+        'a' => 0x07,
+        'b' => 0x08,
+        'd' => 0x7f,
+        'f' => 0x0c,
+        'n' => b'\n',
+        'r' => b'\r',
+        't' => b'\t',
+        'v' => 0x0b,
+        '0'..='7' => {
+            let mut value = escape.to_digit(8).unwrap();
+            for _ in 0..2 {
+                match chars.peek().and_then(|ch| ch.to_digit(8)) {
+                    Some(digit) => value = value * 8 + digit,
+                    None => break,
+                }
+                chars.next();
+            }
+            value as u8
+        }
+        'x' if chars.peek().is_some_and(char::is_ascii_hexdigit) => {
+            let mut value = 0;
+            for _ in 0..2 {
+                match chars.peek().and_then(|ch| ch.to_digit(16)) {
+                    Some(digit) => value = value * 16 + digit,
+                    None => break,
+                }
+                chars.next();
+            }
+            value as u8
+        }
+        other => {
+            bytes.extend(other.encode_utf8(&mut [0; 4]).as_bytes());
+            return;
+        }
+    };
+    bytes.push(byte);
+}
+
+/// Splits bytes into the keys the terminal would report for them, the
+/// way termion reads keys, so bound sequences match what is typed.
+pub fn parse_keys(bytes: &[u8]) -> Result<Vec<Key>, String> {
+    let text = String::from_utf8_lossy(bytes);
+    let mut chars = text.chars().peekable();
+    let mut keys = Vec::new();
+
+    while let Some(ch) = chars.next() {
+        let key = match ch {
+            '\x1b' => match chars.next() {
+                None => Key::Esc,
+                Some('[') => csi_key(&mut chars)?,
+                Some(ch) => Key::Alt(ch),
+            },
+            '\n' | '\r' => Key::Char('\n'),
+            '\t' => Key::Char('\t'),
+            '\x7f' => Key::Backspace,
+            '\0' => Key::Null,
+            '\x01'..='\x1a' => Key::Ctrl((ch as u8 - 0x01 + b'a') as char),
+            '\x1c'..='\x1f' => Key::Ctrl((ch as u8 - 0x1c + b'4') as char),
+            ch => Key::Char(ch),
+        };
+        keys.push(key);
+    }
+    Ok(keys)
+}
+
+/// The key an escape sequence after `ESC [` stands for.
+fn csi_key(chars: &mut Peekable<Chars>) -> Result<Key, String> {
+    let mut params = String::new();
+    while let Some(ch) = chars.next_if(|ch| ch.is_ascii_digit() || *ch == ';') {
+        params.push(ch);
+    }
+    let end = chars.next();
+
+    let key = match (params.as_str(), end) {
+        ("", Some('A')) => Key::Up,
+        ("", Some('B')) => Key::Down,
+        ("", Some('C')) => Key::Right,
+        ("", Some('D')) => Key::Left,
+        ("", Some('H')) | ("1" | "7", Some('~')) => Key::Home,
+        ("", Some('F')) | ("4" | "8", Some('~')) => Key::End,
+        ("", Some('Z')) => Key::BackTab,
+        ("2", Some('~')) => Key::Insert,
+        ("3", Some('~')) => Key::Delete,
+        ("5", Some('~')) => Key::PageUp,
+        ("6", Some('~')) => Key::PageDown,
+        ("1;3", Some('C')) => Key::AltRight,
+        ("1;3", Some('D')) => Key::AltLeft,
+        ("1;5", Some('C')) => Key::CtrlRight,
+        ("1;5", Some('D')) => Key::CtrlLeft,
+        ("1;5", Some('H')) => Key::CtrlHome,
+        ("1;5", Some('F')) => Key::CtrlEnd,
+        _ => {
+            let end = end.map(String::from).unwrap_or_default();
+            return Err(format!("\\e[{params}{end}: unknown escape sequence"));
+        }
+    };
+    Ok(key)
+}
+
+/// The bytes the terminal sends for `key`.
+fn key_bytes(key: Key) -> Vec<u8> {
+    let csi = |seq: &str| [b"\x1b[", seq.as_bytes()].concat();
+    match key {
+        Key::Char('\n') => vec![b'\r'],
+        Key::Char(ch) => ch.to_string().into_bytes(),
+        Key::Alt(ch) => [vec![0x1b], key_bytes(Key::Char(ch))].concat(),
+        Key::Ctrl(ch @ 'a'..='z') => vec![ch as u8 - b'a' + 0x01],
+        Key::Ctrl(ch @ '4'..='7') => vec![ch as u8 - b'4' + 0x1c],
+        Key::Ctrl(ch) => vec![ch as u8 & 0x1f],
+        Key::Backspace => vec![0x7f],
+        Key::Esc => vec![0x1b],
+        Key::Null => vec![0],
+        Key::Up => csi("A"),
+        Key::Down => csi("B"),
+        Key::Right => csi("C"),
+        Key::Left => csi("D"),
+        Key::Home => csi("H"),
+        Key::End => csi("F"),
+        Key::BackTab => csi("Z"),
+        Key::Insert => csi("2~"),
+        Key::Delete => csi("3~"),
+        Key::PageUp => csi("5~"),
+        Key::PageDown => csi("6~"),
+        Key::AltRight => csi("1;3C"),
+        Key::AltLeft => csi("1;3D"),
+        Key::CtrlRight => csi("1;5C"),
+        Key::CtrlLeft => csi("1;5D"),
+        Key::CtrlHome => csi("1;5H"),
+        Key::CtrlEnd => csi("1;5F"),
+        _ => Vec::new(),
+    }
+}
+
+/// Writes keys as an inputrc key sequence, without the quotes.
+pub fn keys_to_string(keys: &[Key]) -> String {
+    let bytes: Vec<u8> = keys.iter().flat_map(|key| key_bytes(*key)).collect();
+    escape(&String::from_utf8_lossy(&bytes))
+}
+
+/// Writes text with the escapes `unescape` reads back.
+pub fn escape(text: &str) -> String {
+    let mut escaped = String::new();
+    for ch in text.chars() {
+        match ch {
+            '\x1b' => escaped.push_str("\\e"),
+            '\x7f' => escaped.push_str("\\C-?"),
+            '"' | '\\' => {
+                escaped.push('\\');
+                escaped.push(ch);
+            }
+            '\0'..='\x1f' => {
+                escaped.push_str("\\C-");
+                escaped.push((ch as u8 + 0x40).to_ascii_lowercase() as char);
+            }
+            ch => escaped.push(ch),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bindings() {
+        assert_eq!(
+            parse_binding(r#""\C-x\C-r": re-read-init-file"#),
+            Ok((
+                vec![Key::Ctrl('x'), Key::Ctrl('r')],
+                Binding::Action(Action::ReReadInitFile)
+            ))
+        );
+        assert_eq!(
+            parse_binding("Control-u: unix-line-discard"),
+            Ok((
+                vec![Key::Ctrl('u')],
+                Binding::Action(Action::UnixLineDiscard)
+            ))
+        );
+        assert_eq!(
+            parse_binding("M-DEL: backward-kill-word"),
+            Ok((
+                vec![Key::Alt('\x7f')],
+                Binding::Action(Action::BackwardKillWord)
+            ))
+        );
+        assert_eq!(
+            parse_binding(r#""\e[A": history-search-backward"#),
+            Ok((
+                vec![Key::Up],
+                Binding::Action(Action::HistorySearchBackward)
+            ))
+        );
+        assert_eq!(
+            parse_binding(r#""\C-xg": "git status\n""#),
+            Ok((
+                vec![Key::Ctrl('x'), Key::Char('g')],
+                Binding::Macro("git status\n".to_string())
+            ))
+        );
+        assert!(parse_binding(r#""\C-a": no-such-thing"#).is_err());
+        assert!(parse_binding(r#""\C-a" beginning-of-line"#).is_err());
+    }
+
+    #[test]
+    fn key_sequences_round_trip() {
+        for seq in [
+            r"\C-a",
+            r"\eb",
+            r"\e[A",
+            r"\C-?",
+            r"\e\C-?",
+            r"\C-x\C-r",
+            r"\e[1;5D",
+        ] {
+            let keys = parse_keys(&unescape(seq)).unwrap();
+            assert_eq!(keys_to_string(&keys), seq);
+        }
+        assert_eq!(keys_to_string(&[Key::Ctrl('7')]), r"\C-_");
+        assert_eq!(keys_to_string(&[Key::Char('\n')]), r"\C-m");
+    }
+
+    #[test]
+    fn lookup() {
+        let keymap = Keymap::default();
+        assert_eq!(
+            keymap.lookup(&[Key::Ctrl('a')]),
+            Lookup::Found(&Binding::Action(Action::BeginningOfLine))
+        );
+        assert_eq!(keymap.lookup(&[Key::Ctrl('x')]), Lookup::Prefix);
+        assert_eq!(
+            keymap.lookup(&[Key::Ctrl('x'), Key::Char('z')]),
+            Lookup::None
+        );
+    }
+}
@@ -1,13 +1,54 @@
+use crate::ShellError;
 use anyhow::Result;
-use codecrafters_shell::ShellError;
 
 #[derive(PartialEq, Clone, Debug)]
 pub enum TokenType {
     Word,
+    SingleQuote,
     DoubleQuote,
+    Variable,
     Redirects,
     Escaped,
     Pipe,
+    And,
+    Or,
+    Semicolon,
+    Background,
+    Newline,
+    /// `;;`, ending a `case` item.
+    CaseEnd,
+    LeftParen,
+    RightParen,
+    /// The command inside `$(...)`.
+    CommandSubst,
+    /// The command inside backticks, with its escapes still in place.
+    Backtick,
+    /// The expression inside `$((...))`.
+    ArithmeticSubst,
+    /// The expression of a `((...))` command.
+    ArithmeticCommand,
+    /// The body of a here-doc, taken from the lines after the command.
+    HereDoc {
+        delimiter: String,
+        expand: bool,
+    },
+}
+
+impl TokenType {
+    /// Whether the token is (part of) a word, as opposed to an operator.
+    pub fn is_word(&self) -> bool {
+        matches!(
+            self,
+            TokenType::Word
+                | TokenType::SingleQuote
+                | TokenType::DoubleQuote
+                | TokenType::Variable
+                | TokenType::Escaped
+                | TokenType::CommandSubst
+                | TokenType::Backtick
+                | TokenType::ArithmeticSubst
+        )
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -18,12 +59,89 @@ pub struct Token<'a> {
 }
 
 struct Lexer<'a> {
+    input: &'a str,
     rest: &'a str,
+    /// A token that is ready to be returned next.
+    pending: Option<Token<'a>>,
+    /// Here-doc bodies read so far for the current line, as the offset of
+    /// the line's end and the offset right after the last body.
+    here_docs: Option<(usize, usize)>,
 }
 
 impl<'a> Lexer<'a> {
     pub fn new(input: &'a str) -> Self {
-        Self { rest: input }
+        Self {
+            input,
+            rest: input,
+            pending: None,
+            here_docs: None,
+        }
+    }
+
+    fn is_adjacent(&self) -> bool {
+        self.rest.starts_with(|c: char| !c.is_whitespace())
+    }
+
+    /// Whether `current`, which was just read, begins a new word rather
+    /// than continuing the one before it.
+    fn starts_word(&self, current: char) -> bool {
+        let start = self.offset() - current.len_utf8();
+        self.input[..start].chars().next_back().is_none_or(|c| {
+            c.is_whitespace() || matches!(c, ';' | '&' | '|' | '(' | ')' | '<' | '>')
+        })
+    }
+
+    fn offset(&self) -> usize {
+        self.input.len() - self.rest.len()
+    }
+
+    /// Reads the delimiter after `<<` and the body that follows the current
+    /// line, up to a line holding just the delimiter.
+    fn here_doc(&mut self, strip_tabs: bool) -> Result<Token<'a>> {
+        self.rest = self.rest.trim_start_matches([' ', '\t']);
+        let (delimiter, quoted, len) = scan_delimiter(self.rest);
+        if delimiter.is_empty() {
+            let next = self.rest.split_whitespace().next().unwrap_or("newline");
+            return Err(ShellError::Syntax(next.to_string()).into());
+        }
+        self.rest = &self.rest[len..];
+
+        let (line_end, body_start) = match self.here_docs {
+            Some(here_docs) => here_docs,
+            None => {
+                let line_end =
+                    self.offset() + self.rest.find('\n').ok_or(ShellError::Incomplete)?;
+                (line_end, line_end + 1)
+            }
+        };
+
+        let mut line_start = body_start;
+        loop {
+            let rest = self.input.get(line_start..).ok_or(ShellError::Incomplete)?;
+            if rest.is_empty() {
+                return Err(ShellError::Incomplete.into());
+            }
+
+            let line = rest.split('\n').next().unwrap_or_default();
+            let trimmed = match strip_tabs {
+                true => line.trim_start_matches('\t'),
+                false => line,
+            };
+            let line_next = line_start + line.len() + 1;
+
+            if trimmed == delimiter {
+                self.here_docs = Some((line_end, line_next.min(self.input.len())));
+                return Ok(Token {
+                    origin: &self.input[body_start..line_start],
+                    token_type: TokenType::HereDoc {
+                        delimiter,
+                        expand: !quoted,
+                    },
+                    is_adjacent: false,
+                });
+            }
+            line_start = line_next;
+        }
     }
 }
 
@@ -31,7 +149,25 @@ impl<'a> Iterator for Lexer<'a> {
     type Item = Result<Token<'a>>;
 
     fn next(&mut self) -> Option<Self::Item> {
+        if let Some(token) = self.pending.take() {
+            return Some(Ok(token));
+        }
+
         loop {
+            if let Some((line_end, body_end)) = self.here_docs
+                && self.offset() >= line_end
+            {
+                // Skip over the here-doc bodies that follow this line.
+                self.rest = &self.input[body_end..];
+                self.here_docs = None;
+
+                return Some(Ok(Token {
+                    origin: &self.input[line_end..line_end + 1],
+                    token_type: TokenType::Newline,
+                    is_adjacent: false,
+                }));
+            }
+
             let mut chars = self.rest.chars();
             let current = chars.next()?;
             let current_str = self.rest;
@@ -44,8 +180,18 @@ impl<'a> Iterator for Lexer<'a> {
                 Escape,
                 Redirection,
                 Pipe,
+                Variable,
+                CommandSubst,
+                Backtick,
+                Arithmetic(TokenType),
+                ListOperator(TokenType),
             }
 
+            let variable = match current {
+                '$' => scan_variable(self.rest),
+                _ => None,
+            };
+
             let compare_next = |c| match chars.clone().peekable().peek() {
                 Some(next_val) => c == *next_val,
                 None => false,
@@ -54,24 +200,49 @@ impl<'a> Iterator for Lexer<'a> {
             let started = match current {
                 '"' => Started::DoubleQuote,
                 '\'' => Started::SingleQuote,
+                '\\' if self.rest.starts_with('\n') => {
+                    // A line continuation.
+                    self.rest = &self.rest[1..];
+                    continue;
+                }
+                // The line continues on the next one.
+                '\\' if self.rest.is_empty() => {
+                    return Some(Err(ShellError::Incomplete.into()));
+                }
                 '\\' => Started::Escape,
-                '>' => Started::Redirection,
+                '#' if self.starts_word(current) => {
+                    // A comment, up to the end of the line.
+                    let end = self.rest.find('\n').unwrap_or(self.rest.len());
+                    self.rest = &self.rest[end..];
+                    continue;
+                }
+                '`' => Started::Backtick,
+                '$' if self.rest.starts_with("((") && arithmetic_end(&self.rest[2..]).is_some() => {
+                    Started::Arithmetic(TokenType::ArithmeticSubst)
+                }
+                '$' if self.rest.starts_with('(') => Started::CommandSubst,
+                '(' if self.rest.starts_with('(') && arithmetic_end(&self.rest[1..]).is_some() => {
+                    Started::Arithmetic(TokenType::ArithmeticCommand)
+                }
+                _ if redirect_len(current_str).is_some() => Started::Redirection,
+                '|' if compare_next('|') => Started::ListOperator(TokenType::Or),
+                '&' if compare_next('&') => Started::ListOperator(TokenType::And),
+                ';' if compare_next(';') => Started::ListOperator(TokenType::CaseEnd),
+                ';' => Started::ListOperator(TokenType::Semicolon),
+                '\n' => Started::ListOperator(TokenType::Newline),
+                '(' => Started::ListOperator(TokenType::LeftParen),
+                ')' => Started::ListOperator(TokenType::RightParen),
+                '&' => Started::ListOperator(TokenType::Background),
                 '|' => Started::Pipe,
-                '1' if compare_next('>') => Started::Redirection,
-                '2' if compare_next('>') => Started::Redirection,
+                '$' if variable.is_some() => Started::Variable,
                 c if c.is_whitespace() => continue,
                 _ => {
-                    let end_index = current_str
-                        .find(|c| matches!(c, '\'' | '"' | ' ' | '\n' | '\\'))
-                        .unwrap_or(current_str.len());
+                    let end_index = word_end(current_str);
 
                     let origin = &current_str[..end_index];
                     self.rest = &current_str[end_index..];
 
-                    let is_adjacent = match self.rest.chars().peekable().peek() {
-                        Some(c) if !c.is_whitespace() => true,
-                        _ => false,
-                    };
+                    let is_adjacent = self.is_adjacent();
 
                     return Some(Ok(Token {
                         origin,
@@ -83,17 +254,13 @@ impl<'a> Iterator for Lexer<'a> {
 
             match started {
                 Started::DoubleQuote => {
-                    let end = match handle_double(chars) {
-                        Ok(end) => end,
-                        Err(e) => return Some(Err(e)),
+                    let Some(end) = double_quote_end(self.rest) else {
+                        return Some(Err(ShellError::Incomplete.into()));
                     };
                     let origin = &self.rest[..end];
                     self.rest = &self.rest[end + 1..];
 
-                    let is_adjacent = match self.rest.chars().peekable().peek() {
-                        Some(c) if !c.is_whitespace() => true,
-                        _ => false,
-                    };
+                    let is_adjacent = self.is_adjacent();
 
                     return Some(Ok(Token {
                         origin,
@@ -103,30 +270,85 @@ impl<'a> Iterator for Lexer<'a> {
                 }
                 Started::SingleQuote => {
                     let Some(end) = self.rest.find('\'') else {
-                        return Some(Err(ShellError::MissingQuote.into()));
+                        return Some(Err(ShellError::Incomplete.into()));
                     };
                     let origin = &self.rest[..end];
                     self.rest = &self.rest[end + 1..];
 
-                    let is_adjacent = match self.rest.chars().peekable().peek() {
-                        Some(c) if !c.is_whitespace() => true,
-                        _ => false,
+                    let is_adjacent = self.is_adjacent();
+
+                    return Some(Ok(Token {
+                        origin,
+                        token_type: TokenType::SingleQuote,
+                        is_adjacent,
+                    }));
+                }
+                Started::CommandSubst => {
+                    let Some(end) = parens_end(&self.rest[1..]) else {
+                        return Some(Err(ShellError::Incomplete.into()));
                     };
+                    let origin = &self.rest[1..end + 1];
+                    self.rest = &self.rest[end + 2..];
+
+                    let is_adjacent = self.is_adjacent();
 
                     return Some(Ok(Token {
                         origin,
-                        token_type: TokenType::Word,
+                        token_type: TokenType::CommandSubst,
                         is_adjacent,
                     }));
                 }
-                Started::Escape => {
-                    let escaped = &self.rest[..1];
-                    self.rest = &self.rest[1..];
+                Started::Arithmetic(token_type) => {
+                    let start = match token_type {
+                        TokenType::ArithmeticSubst => 2,
+                        _ => 1,
+                    };
+                    let end = arithmetic_end(&self.rest[start..])
+                        .expect("checked when matching the token start");
+                    let origin = &self.rest[start..start + end];
+                    self.rest = &self.rest[start + end + 2..];
+
+                    let is_adjacent = self.is_adjacent();
 
-                    let is_adjacent = match self.rest.chars().peekable().peek() {
-                        Some(c) if !c.is_whitespace() => true,
-                        _ => false,
+                    return Some(Ok(Token {
+                        origin,
+                        token_type,
+                        is_adjacent,
+                    }));
+                }
+                Started::Backtick => {
+                    let Some(end) = backtick_end(self.rest) else {
+                        return Some(Err(ShellError::Incomplete.into()));
                     };
+                    let origin = &self.rest[..end];
+                    self.rest = &self.rest[end + 1..];
+
+                    let is_adjacent = self.is_adjacent();
+
+                    return Some(Ok(Token {
+                        origin,
+                        token_type: TokenType::Backtick,
+                        is_adjacent,
+                    }));
+                }
+                Started::Variable => {
+                    let (name, len) = variable.expect("checked when matching the token start");
+                    self.rest = &self.rest[len..];
+
+                    let is_adjacent = self.is_adjacent();
+
+                    return Some(Ok(Token {
+                        origin: name,
+                        token_type: TokenType::Variable,
+                        is_adjacent,
+                    }));
+                }
+                Started::Escape => {
+                    let len = self.rest.chars().next().map_or(0, char::len_utf8);
+                    let escaped = &self.rest[..len];
+                    self.rest = &self.rest[len..];
+
+                    let is_adjacent = self.is_adjacent();
 
                     return Some(Ok(Token {
                         origin: escaped,
@@ -135,14 +357,18 @@ impl<'a> Iterator for Lexer<'a> {
                     }));
                 }
                 Started::Redirection => {
-                    let Some(end) = self.rest.find(|c| !matches!(c, '>')) else {
-                        return Some(Err(ShellError::MissingArg.into()));
-                    };
-                    let origin = current_str[..end + 1].trim();
-                    if !matches!(origin, "1>" | "1>>" | ">" | ">>" | "2>" | "2>>") {
-                        return Some(Err(ShellError::Parsing.into()));
+                    let len =
+                        redirect_len(current_str).expect("checked when matching the token start");
+                    let origin = &current_str[..len];
+                    self.rest = &current_str[len..];
+
+                    let operator = origin.trim_start_matches(|c: char| c.is_ascii_digit());
+                    if operator == "<<" || operator == "<<-" {
+                        match self.here_doc(operator == "<<-") {
+                            Ok(token) => self.pending = Some(token),
+                            Err(e) => return Some(Err(e)),
+                        }
                     }
-                    self.rest = &self.rest[end + 1..];
 
                     return Some(Ok(Token {
                         origin,
@@ -150,6 +376,24 @@ impl<'a> Iterator for Lexer<'a> {
                         is_adjacent: false,
                     }));
                 }
+                Started::ListOperator(token_type) => {
+                    let len = match token_type {
+                        TokenType::Semicolon
+                        | TokenType::Background
+                        | TokenType::Newline
+                        | TokenType::LeftParen
+                        | TokenType::RightParen => 1,
+                        _ => 2,
+                    };
+                    let origin = &current_str[..len];
+                    self.rest = &current_str[len..];
+
+                    return Some(Ok(Token {
+                        origin,
+                        token_type,
+                        is_adjacent: false,
+                    }));
+                }
                 Started::Pipe => {
                     let origin = &current_str[..current.len_utf8()];
                     self.rest = &current_str[current.len_utf8()..];
@@ -177,20 +421,305 @@ pub fn run_lexer(input: &str) -> Result<Vec<Token<'_>>> {
     Ok(tokens)
 }
 
-fn handle_double<T>(chars: T) -> Result<usize>
-where
-    T: Iterator<Item = char>,
-{
-    let mut escaped = false;
-    for (idx, c) in chars.enumerate() {
-        if escaped {
-            escaped = false;
-        } else if c == '\\' {
-            escaped = true;
-        } else if c == '"' {
-            return Ok(idx);
+/// Returns the offset of the `"` closing a double-quoted string, skipping
+/// over escapes and command substitutions inside it.
+pub fn double_quote_end(input: &str) -> Option<usize> {
+    let mut idx = 0;
+    while let Some(c) = input[idx..].chars().next() {
+        match c {
+            '"' => return Some(idx),
+            '\\' => idx += input[idx + 1..].chars().next().map_or(0, char::len_utf8),
+            '`' => idx += backtick_end(&input[idx + 1..])? + 1,
+            '$' if input[idx + 1..].starts_with('(') => idx += parens_end(&input[idx + 2..])? + 2,
+            _ => {}
+        }
+        idx += c.len_utf8();
+    }
+
+    None
+}
+
+/// Returns the offset of the closing backtick.
+pub fn backtick_end(input: &str) -> Option<usize> {
+    let mut chars = input.char_indices();
+    while let Some((idx, c)) = chars.next() {
+        match c {
+            '`' => return Some(idx),
+            '\\' => {
+                chars.next();
+            }
+            _ => {}
+        }
+    }
+
+    None
+}
+
+/// Returns the offset of the `)` closing a command substitution, where
+/// `input` starts right after the opening `$(`. Quotes and nested
+/// parentheses inside the command are skipped over.
+pub fn parens_end(input: &str) -> Option<usize> {
+    let mut depth = 0;
+    let mut idx = 0;
+    while let Some(c) = input[idx..].chars().next() {
+        match c {
+            '(' => depth += 1,
+            ')' if depth == 0 => return Some(idx),
+            ')' => depth -= 1,
+            '\\' => idx += input[idx + 1..].chars().next().map_or(0, char::len_utf8),
+            '\'' => idx += input[idx + 1..].find('\'')? + 1,
+            '"' => idx += double_quote_end(&input[idx + 1..])? + 1,
+            '`' => idx += backtick_end(&input[idx + 1..])? + 1,
+            _ => {}
+        }
+        idx += c.len_utf8();
+    }
+
+    None
+}
+
+/// Returns the offset of the `))` closing an arithmetic expression, where
+/// `input` starts right after the opening `((`. Without one, `$((` starts a
+/// command substitution holding a subshell instead.
+pub fn arithmetic_end(input: &str) -> Option<usize> {
+    let end = parens_end(input)?;
+    input[end + 1..].starts_with(')').then_some(end)
+}
+
+/// Scans the text following a `$` and returns the variable name together with
+/// the number of bytes it occupies, e.g. `{HOME}/bin` gives `("HOME", 6)`.
+pub fn scan_variable(input: &str) -> Option<(&str, usize)> {
+    match input.chars().next()? {
+        '{' => {
+            let end = input.find('}')?;
+            let name = &input[1..end];
+            is_variable_name(name).then_some((name, end + 1))
+        }
+        c if is_special_variable(c) => Some((&input[..1], 1)),
+        c if c.is_ascii_alphabetic() || c == '_' => {
+            let end = input
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(input.len());
+            Some((&input[..end], end))
+        }
+        _ => None,
+    }
+}
+
+/// Returns the length of the redirect operator at the start of `input`,
+/// including a leading file descriptor as in `2>&1`.
+fn redirect_len(input: &str) -> Option<usize> {
+    const OPERATORS: [&str; 8] = ["<<<", "<<-", "<<", "<&", "<", ">>", ">&", ">"];
+
+    if let Some(rest) = input.strip_prefix('&') {
+        let len = [">>", ">"].iter().find(|op| rest.starts_with(*op))?.len();
+        return Some(len + 1);
+    }
+
+    let digits = input
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(input.len());
+    let rest = &input[digits..];
+    let len = OPERATORS.iter().find(|op| rest.starts_with(*op))?.len();
+
+    Some(digits + len)
+}
+
+/// Scans a here-doc delimiter, removing any quoting. Returns the delimiter,
+/// whether any of it was quoted, and the number of bytes it occupies.
+fn scan_delimiter(input: &str) -> (String, bool, usize) {
+    let mut delimiter = String::new();
+    let mut quoted = false;
+    let mut chars = input.char_indices();
+
+    while let Some((idx, c)) = chars.next() {
+        match c {
+            '\'' | '"' => {
+                quoted = true;
+                for (_, inner) in chars.by_ref() {
+                    if inner == c {
+                        break;
+                    }
+                    delimiter.push(inner);
+                }
+            }
+            '\\' => {
+                quoted = true;
+                if let Some((_, escaped)) = chars.next() {
+                    delimiter.push(escaped);
+                }
+            }
+            c if c.is_whitespace() || matches!(c, '|' | '&' | ';' | '<' | '>') => {
+                return (delimiter, quoted, idx);
+            }
+            c => delimiter.push(c),
+        }
+    }
+
+    (delimiter, quoted, input.len())
+}
+
+fn is_special_variable(c: char) -> bool {
+    matches!(c, '?' | '$' | '#' | '!' | '@' | '*' | '0'..='9')
+}
+
+fn is_variable_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_digit() => name.chars().all(|c| c.is_ascii_digit()),
+        Some(c) if is_special_variable(c) => chars.next().is_none(),
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {
+            chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
         }
+        _ => false,
+    }
+}
+
+fn word_end(input: &str) -> usize {
+    input
+        .char_indices()
+        .skip(1)
+        .find(|&(idx, c)| {
+            matches!(
+                c,
+                '\'' | '"' | '\\' | '`' | '|' | '&' | ';' | '>' | '<' | '(' | ')'
+            ) || c.is_whitespace()
+                || (c == '$'
+                    && (input[idx + 1..].starts_with('(')
+                        || scan_variable(&input[idx + 1..]).is_some()))
+        })
+        .map(|(idx, _)| idx)
+        .unwrap_or(input.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn variables() {
+        assert_eq!(scan_variable("HOME/bin"), Some(("HOME", 4)));
+        assert_eq!(scan_variable("{HOME}/bin"), Some(("HOME", 6)));
+        assert_eq!(scan_variable("?x"), Some(("?", 1)));
+        assert_eq!(scan_variable("@\""), Some(("@", 1)));
+        assert_eq!(scan_variable("{*}"), Some(("*", 3)));
+        assert_eq!(scan_variable("12"), Some(("1", 1)));
+        assert_eq!(scan_variable("{12}"), Some(("12", 4)));
+        assert_eq!(scan_variable("{A B}"), None);
+        assert_eq!(scan_variable("{HOME"), None);
+        assert_eq!(scan_variable(" HOME"), None);
     }
 
-    Err(ShellError::MissingQuote.into())
+    #[test]
+    fn redirects() {
+        let tokens = run_lexer("cmd 2>&1 &>log 3<in a2>b && x").unwrap();
+        let origins: Vec<&str> = tokens.iter().map(|token| token.origin).collect();
+        assert_eq!(
+            origins,
+            [
+                "cmd", "2>&", "1", "&>", "log", "3<", "in", "a2", ">", "b", "&&", "x"
+            ]
+        );
+
+        let tokens = run_lexer("cat <<< hi 2<<<x").unwrap();
+        let origins: Vec<&str> = tokens.iter().map(|token| token.origin).collect();
+        assert_eq!(origins, ["cat", "<<<", "hi", "2<<<", "x"]);
+    }
+
+    #[test]
+    fn escapes() {
+        let tokens = run_lexer("echo \\é\\x").unwrap();
+        let origins: Vec<&str> = tokens.iter().map(|token| token.origin).collect();
+        assert_eq!(origins, ["echo", "é", "x"]);
+        assert!(
+            tokens[1..]
+                .iter()
+                .all(|token| token.token_type == TokenType::Escaped)
+        );
+    }
+
+    #[test]
+    fn here_docs() {
+        let tokens = run_lexer("cat <<-'END' <<B x\n\tone $A\n\tEND\ntwo\nB\n").unwrap();
+        let origins: Vec<&str> = tokens.iter().map(|token| token.origin).collect();
+        assert_eq!(
+            origins,
+            ["cat", "<<-", "\tone $A\n", "<<", "two\n", "x", "\n"]
+        );
+        assert_eq!(
+            tokens[2].token_type,
+            TokenType::HereDoc {
+                delimiter: "END".to_string(),
+                expand: false
+            }
+        );
+
+        let incomplete = run_lexer("cat <<EOF\nbody\n").unwrap_err();
+        assert!(matches!(
+            incomplete.downcast_ref(),
+            Some(ShellError::Incomplete)
+        ));
+    }
+
+    #[test]
+    fn command_substitution() {
+        let tokens = run_lexer("echo a$(echo \")\" $(pwd))b `echo \\`x\\``").unwrap();
+        let origins: Vec<&str> = tokens.iter().map(|token| token.origin).collect();
+        assert_eq!(
+            origins,
+            ["echo", "a", "echo \")\" $(pwd)", "b", "echo \\`x\\`"]
+        );
+        assert_eq!(tokens[2].token_type, TokenType::CommandSubst);
+        assert!(tokens[2].is_adjacent);
+        assert_eq!(tokens[4].token_type, TokenType::Backtick);
+
+        assert_eq!(double_quote_end("a $(echo \"b\") c\" d"), Some(15));
+        assert_eq!(parens_end("echo ')' (x) y) z"), Some(14));
+
+        let tokens = run_lexer("((x++)) $((1 + (2))) $( (ls) )").unwrap();
+        let types: Vec<&TokenType> = tokens.iter().map(|token| &token.token_type).collect();
+        assert_eq!(
+            types,
+            [
+                &TokenType::ArithmeticCommand,
+                &TokenType::ArithmeticSubst,
+                &TokenType::CommandSubst
+            ]
+        );
+        assert_eq!(tokens[1].origin, "1 + (2)");
+
+        let incomplete = run_lexer("echo $(echo").unwrap_err();
+        assert!(matches!(
+            incomplete.downcast_ref(),
+            Some(ShellError::Incomplete)
+        ));
+    }
+
+    #[test]
+    fn control_operators() {
+        let tokens = run_lexer("case $x in\na|b) echo \\\n  hi;;\nesac").unwrap();
+        let origins: Vec<&str> = tokens.iter().map(|token| token.origin).collect();
+        assert_eq!(
+            origins,
+            [
+                "case", "x", "in", "\n", "a", "|", "b", ")", "echo", "hi", ";;", "\n", "esac"
+            ]
+        );
+        assert_eq!(tokens[3].token_type, TokenType::Newline);
+        assert_eq!(tokens[7].token_type, TokenType::RightParen);
+        assert_eq!(tokens[10].token_type, TokenType::CaseEnd);
+
+        let incomplete = run_lexer("echo a \\").unwrap_err();
+        assert!(matches!(
+            incomplete.downcast_ref(),
+            Some(ShellError::Incomplete)
+        ));
+    }
+
+    #[test]
+    fn comments() {
+        let tokens = run_lexer("echo a#b \"c\"#d # e 'f\nls;# g").unwrap();
+        let origins: Vec<&str> = tokens.iter().map(|token| token.origin).collect();
+        assert_eq!(origins, ["echo", "a#b", "c", "#d", "\n", "ls", ";"]);
+    }
 }
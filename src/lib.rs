@@ -1,35 +1,73 @@
 use std::{
     fs,
-    io::Write,
-    os::unix::fs::MetadataExt,
-    process::{Child, ChildStdout, Command, Stdio},
-    thread,
+    io::{self, Write},
+    os::{fd::OwnedFd, unix::fs::MetadataExt},
+    rc::Rc,
 };
 
+mod alias;
+mod arithmetic;
+mod ast;
 mod builtins;
+mod compound;
 mod context;
 mod error;
+mod expansion;
 mod external;
-mod writer;
+mod glob;
+mod lexer;
+mod parser;
+mod process;
+mod script;
 
+pub mod keymap;
 pub mod redirection;
 
+use alias::Aliased;
 use anyhow::Result;
+use context::Job;
 use external::External;
 
-pub use builtins::{Builtins, ExecResult, ShellCommand};
-pub use context::ShellCtx;
-pub use error::ShellError;
-pub use redirection::{Redirect, Redirection};
+pub use ast::{CaseItem, Command, CommandStage, Compound, Function, ParsedLine, Word, WordPart};
+pub use builtins::{Builtins, ExecResult, Io, ShellCommand};
+pub use context::{EditMode, JobState, ShellCtx, Variable, is_valid_name};
+pub use error::{ShellError, error_status, io_error_reason};
+pub use lexer::{Token, TokenType, run_lexer};
+pub use redirection::{FdTable, Redirect, Redirection};
+pub use script::run_script;
+
+/// Parses a line of input into the commands to run.
+pub fn parse(input: &str) -> Result<ParsedLine> {
+    parser::parse(lexer::run_lexer(input)?)
+}
+
+/// Expands a prompt string such as `PS1` before it is shown. Expanding it
+/// leaves `$?` as it was, so the prompt can show it.
+pub fn expand_prompt(prompt: &str, ctx: &mut ShellCtx) -> String {
+    let status = ctx.last_status();
+    let expanded = expansion::expand_word(&parser::parse_prompt(prompt), ctx);
+    ctx.set_last_status(status);
+
+    match expanded {
+        Ok(expanded) => expanded.unwrap_or_default(),
+        Err(_) => prompt.to_string(),
+    }
+}
 
 #[derive(Debug)]
 pub enum Cmds {
+    Function(Rc<Function>),
     Builtin(Box<dyn ShellCommand>),
     External(External),
 }
 
 impl Cmds {
-    pub fn new(cmd: &str) -> Cmds {
+    /// Looks up a command, where functions come before builtins.
+    pub fn new(cmd: &str, ctx: &ShellCtx) -> Cmds {
+        if let Some(function) = ctx.function(cmd) {
+            return Cmds::Function(function);
+        }
+
         match Builtins::new(cmd) {
             Some(cmd) => Cmds::Builtin(cmd),
             None => Cmds::External(External::new(cmd.to_string())),
@@ -37,28 +75,22 @@ impl Cmds {
     }
 }
 
-fn is_executable(path: &std::path::Path) -> bool {
+pub fn is_executable(path: &std::path::Path) -> bool {
     fs::metadata(path)
         .map(|metadata| {
             let mode = metadata.mode();
             let owner_executable = (mode & 0o100) != 0;
             let group_executable = (mode & 0o010) != 0;
             let others_executable = (mode & 0o001) != 0;
-            if owner_executable || group_executable || others_executable {
-                true
-            } else {
-                false
-            }
+            owner_executable || group_executable || others_executable
         })
         .unwrap_or(false)
 }
 
-pub fn search_executables(partial: &str) -> Result<Vec<String>> {
+pub fn search_executables(partial: &str, paths: &str) -> Result<Vec<String>> {
     let mut possibilities = Vec::new();
-    let key = "PATH";
-    let paths = std::env::var(key)?;
 
-    for path in std::env::split_paths(&paths) {
+    for path in std::env::split_paths(paths) {
         let Some(dirs) = std::fs::read_dir(path).ok() else {
             continue;
         };
@@ -68,22 +100,8 @@ pub fn search_executables(partial: &str) -> Result<Vec<String>> {
                 continue;
             };
             let file_name = f.file_name().display().to_string();
-            let is_executable = f
-                .metadata()
-                .map(|metadata| {
-                    let mode = metadata.mode();
-                    let owner_executable = (mode & 0o100) != 0;
-                    let group_executable = (mode & 0o010) != 0;
-                    let others_executable = (mode & 0o001) != 0;
-                    if owner_executable || group_executable || others_executable {
-                        true
-                    } else {
-                        false
-                    }
-                })
-                .unwrap_or(false);
 
-            if file_name.starts_with(partial) && is_executable {
+            if file_name.starts_with(partial) && is_executable(&f.path()) {
                 possibilities.push(format!("{} ", f.file_name().display()));
             }
         }
@@ -92,201 +110,437 @@ pub fn search_executables(partial: &str) -> Result<Vec<String>> {
     Ok(possibilities)
 }
 
-pub enum ParsedLine<'a> {
-    Pipeline(Vec<CommandStage<'a>>),
+/// Runs a line of input, stopping early only for `exit`. Errors are
+/// reported as they happen so that the rest of a list still runs.
+pub fn execute(line: &ParsedLine, ctx: &mut ShellCtx) -> Result<ExecResult> {
+    // Forget a Ctrl-C that nothing was left to stop.
+    process::take_interrupt();
+    execute_line(line, ctx)
 }
 
-#[derive(Debug)]
-pub struct CommandStage<'a> {
-    pub cmd: Cmds,
-    pub args: Vec<String>,
-    pub redirects: Vec<Redirection<'a>>,
+/// Runs a parsed line, as [`execute`] does, from within another command.
+pub(crate) fn execute_line(line: &ParsedLine, ctx: &mut ShellCtx) -> Result<ExecResult> {
+    match line {
+        ParsedLine::Pipeline(pipeline) => match execute_pipeline(pipeline, ctx) {
+            Ok(res) => Ok(res),
+            Err(e) if matches!(e.downcast_ref(), Some(ShellError::NoMatch(_))) => Err(e),
+            // Reported by the wait for the command substitution it stopped.
+            Err(e)
+                if matches!(
+                    e.downcast_ref(),
+                    Some(ShellError::Interrupted | ShellError::Stopped)
+                ) =>
+            {
+                Ok(ExecResult::Status(ctx.last_status()))
+            }
+            Err(e) => {
+                eprintln!("{}", ctx.error_message(&e));
+                // A script, `-c` string or subshell gives up here.
+                if matches!(e.downcast_ref(), Some(ShellError::ReadonlyAssignment(_)))
+                    && !ctx.is_interactive()
+                {
+                    return Ok(ExecResult::Exit(ctx.last_status()));
+                }
+                Ok(ExecResult::Status(ctx.last_status()))
+            }
+        },
+        ParsedLine::And(left, right) => {
+            let res = execute_line(left, ctx)?;
+            if res.leaves_list() || ctx.last_status() != 0 {
+                return Ok(res);
+            }
+            execute_line(right, ctx)
+        }
+        ParsedLine::Or(left, right) => {
+            let res = execute_line(left, ctx)?;
+            if res.leaves_list() || ctx.last_status() == 0 || process::interrupted() {
+                return Ok(res);
+            }
+            execute_line(right, ctx)
+        }
+        ParsedLine::Background(line) => spawn_job(line, ctx),
+        ParsedLine::Sequence(lines) => {
+            let mut res = ExecResult::Continue;
+            for line in lines {
+                res = execute_line(line, ctx)?;
+                if res.leaves_list() || process::interrupted() {
+                    break;
+                }
+            }
+            Ok(res)
+        }
+    }
+}
+
+/// Runs `line` as a background job in a subshell of its own.
+fn spawn_job(line: &ParsedLine, ctx: &mut ShellCtx) -> Result<ExecResult> {
+    let pid = process::fork_subshell(ctx, |ctx| execute_line(line, ctx))?;
+    let id = ctx
+        .jobs_mut()
+        .add(Job::new(pid, vec![pid], line.to_string()));
+
+    // Only an interactive shell reports the jobs it starts.
+    if ctx.job_control().is_some() {
+        eprintln!("[{id}] {pid}");
+    }
+    ctx.set_last_background(pid);
+    ctx.set_last_status(0);
+
+    Ok(ExecResult::Status(0))
+}
+
+/// Runs a single pipeline and records its exit status in `ctx` for `$?`.
+pub fn execute_pipeline(pipeline: &[Command], ctx: &mut ShellCtx) -> Result<ExecResult> {
+    let result = run_pipeline(pipeline, ctx);
+    ctx.set_last_status(result_status(&result));
+
+    result
 }
 
-pub fn execute_pipeline(parsed: ParsedLine, ctx: &mut ShellCtx) -> Result<ExecResult> {
-    let ParsedLine::Pipeline(pipeline) = parsed;
+fn result_status(result: &Result<ExecResult>) -> i32 {
+    match result {
+        Ok(ExecResult::Status(status))
+        | Ok(ExecResult::Exit(status))
+        | Ok(ExecResult::Return(status)) => *status,
+        Ok(ExecResult::Continue) | Ok(ExecResult::Break(_)) | Ok(ExecResult::ContinueLoop(_)) => 0,
+        Err(e) => error_status(e),
+    }
+}
 
-    enum Buf {
-        External(ChildStdout),
-        Builtin(Vec<u8>),
+fn run_pipeline(pipeline: &[Command], ctx: &mut ShellCtx) -> Result<ExecResult> {
+    match pipeline {
+        [Command::Compound(compound, redirects)] => {
+            return run_compound(compound, redirects, ctx);
+        }
+        [Command::Function(function)] => {
+            ctx.define_function(Rc::clone(function));
+            return Ok(ExecResult::Status(0));
+        }
+        _ => {}
     }
 
-    let mut input_buf: Option<Buf> = None;
-    let mut children: Vec<Child> = Vec::new();
+    let mut next_stdin: Option<OwnedFd> = None;
+    let mut children: Vec<i32> = Vec::new();
+    let mut pgid: Option<i32> = None;
+    let mut ends_with_process = false;
+    let mut failed: Option<i32> = None;
 
     for (i, stage) in pipeline.iter().enumerate() {
         let is_last = i + 1 == pipeline.len();
-        let CommandStage {
-            cmd,
-            args,
-            redirects,
-        } = stage;
-
-        match cmd {
-            Cmds::Builtin(cmd) => {
-                if is_last {
-                    let result = cmd.execute(args, ctx);
 
-                    for mut c in children {
-                        let _ = c.wait();
-                    }
-                    return handle_builtin_redirection(redirects, result);
-                } else {
-                    let mut out_buf: Vec<u8> = Vec::new();
-                    let data = cmd.execute(args, ctx);
-                    let result = handle_builtin_redirection(redirects, data)?;
-
-                    if let ExecResult::Res(res) = result {
-                        out_buf.extend_from_slice(res.as_bytes());
+        let aliased;
+        let stage = match stage {
+            Command::Simple(stage) => match alias::expand(stage, ctx)? {
+                None => stage,
+                Some(Aliased::Simple(expanded)) => {
+                    aliased = expanded;
+                    &aliased
+                }
+                Some(Aliased::Line(line, used)) => {
+                    let group = Compound::Group(Box::new(line));
+                    if pipeline.len() == 1 {
+                        return ctx.with_active_aliases(used, |ctx| {
+                            run_compound(&group, &stage.redirects, ctx)
+                        });
                     }
 
-                    input_buf = Some(Buf::Builtin(out_buf));
+                    let pipes = stage_pipes(&mut next_stdin, is_last)?;
+                    let pid = ctx.with_active_aliases(used, |ctx| {
+                        fork_compound(&group, &stage.redirects, pipes, pgid, ctx)
+                    })?;
+                    children.push(pid);
+                    pgid.get_or_insert(pid);
+                    ends_with_process = is_last;
+                    continue;
                 }
+            },
+            Command::Compound(compound, redirects) => {
+                let pipes = stage_pipes(&mut next_stdin, is_last)?;
+                let pid = fork_compound(compound, redirects, pipes, pgid, ctx)?;
+                children.push(pid);
+                pgid.get_or_insert(pid);
+                ends_with_process = is_last;
+                continue;
             }
+            // Defined in a subshell of its own, so it has no effect, but
+            // still takes its place in the pipeline: its reader sees the
+            // end of input and its writer's output goes nowhere.
+            Command::Function(_) => {
+                drop(stage_pipes(&mut next_stdin, is_last)?);
+                continue;
+            }
+        };
 
-            Cmds::External(cmd) => {
-                let mut cmd = cmd.build(args)?;
+        ctx.take_substitution_status();
+        let mut args = expansion::expand_command(&stage.words, ctx)?;
+        // Without a command the assignments are made one by one, so that a
+        // later one sees an earlier one, as in `x=1 y=$x`.
+        let assign_now = args.is_empty() && pipeline.len() == 1;
+        let assignments = stage
+            .assignments
+            .iter()
+            .map(|(name, value)| {
+                let value = expansion::expand_word(value, ctx)?.unwrap_or_default();
+                if assign_now && ctx.set_var(name, value.clone()).is_err() {
+                    return Err(ShellError::ReadonlyAssignment(name.clone()).into());
+                }
+                Ok((name.clone(), value))
+            })
+            .collect::<Result<Vec<(String, String)>>>()?;
+        let redirects = expand_redirects(&stage.redirects, ctx)?;
+
+        let (stdin, stdout) = stage_pipes(&mut next_stdin, is_last)?;
+        let mut fds = FdTable::new(stdin, stdout)?;
+        fds.apply(&redirects)?;
+
+        let cmd = (!args.is_empty()).then(|| Cmds::new(&args.remove(0), ctx));
+        let (args, assignments) = (&args, &assignments);
+
+        let Some(cmd) = cmd else {
+            // A bare assignment takes the status of its last substitution.
+            if is_last {
+                failed = ctx.take_substitution_status();
+            }
+            continue;
+        };
 
-                match input_buf.take() {
-                    Some(buf) => {
-                        cmd.stdin(Stdio::piped());
-                        if is_last {
-                            cmd.stdout(Stdio::inherit());
-                        } else {
-                            cmd.stdout(Stdio::piped());
-                        }
+        match cmd {
+            Cmds::Function(function) => {
+                let call = |ctx: &mut ShellCtx| {
+                    ctx.with_temporary_vars(assignments, |ctx| {
+                        call_function(&function, args.clone(), ctx)
+                    })
+                };
+                if pipeline.len() == 1 {
+                    return fds.install(|| call(ctx))?;
+                }
 
-                        handle_external_redirection(&redirects, &mut cmd)?;
-
-                        match buf {
-                            Buf::External(buf) => {
-                                cmd.stdin(Stdio::from(buf));
-                                let child = cmd.spawn()?;
-                                children.push(child);
-                            }
-                            Buf::Builtin(buf) => {
-                                let mut child = cmd.spawn()?;
-
-                                if let Some(mut child_stdin) = child.stdin.take() {
-                                    thread::spawn(move || {
-                                        let _ = child_stdin.write_all(&buf);
-                                    });
-                                }
-
-                                children.push(child);
-                            }
-                        }
+                let pid = process::fork_stage(ctx, &fds, pgid, call)?;
+                children.push(pid);
+                pgid.get_or_insert(pid);
+                ends_with_process = is_last;
+            }
 
-                        if !is_last {
-                            let last = children.pop().unwrap();
-                            input_buf = if last.stdout.is_some() {
-                                Some(Buf::External(last.stdout.unwrap()))
-                            } else {
-                                None
-                            }
-                        }
-                    }
+            Cmds::Builtin(cmd) => {
+                let run = |ctx: &mut ShellCtx| {
+                    ctx.with_temporary_vars(assignments, |ctx| {
+                        Ok(run_builtin(cmd.as_ref(), args, &fds, ctx))
+                    })
+                };
+                if !is_last {
+                    // Forked like any other stage, so that its output
+                    // streams to the next one as it is written.
+                    let pid = process::fork_stage(ctx, &fds, pgid, |ctx| {
+                        let result = run(ctx);
+                        ctx.set_last_status(result_status(&result));
+                        result
+                    })?;
+                    children.push(pid);
+                    pgid.get_or_insert(pid);
+                    continue;
+                }
 
-                    None => {
-                        cmd.stdin(Stdio::inherit());
+                let result = run(ctx);
+                // The read end of the pipe must close first, or an earlier
+                // stage that is still writing never finishes.
+                drop(fds);
+                if let Some(pgid) = pgid {
+                    wait_children(ctx, pgid, children, pipeline);
+                }
+                return result;
+            }
 
+            Cmds::External(cmd) => {
+                ends_with_process = is_last;
+                let mut envs = ctx.exported_vars();
+                envs.extend(assignments.iter().cloned());
+                // `PATH=dir cmd` looks in `dir`, as the shell's own `PATH`
+                // would be for a builtin.
+                let paths = assignments
+                    .iter()
+                    .rfind(|(name, _)| name == "PATH")
+                    .map(|(_, value)| value.clone())
+                    .or_else(|| ctx.get_var("PATH"));
+
+                let spawned = cmd
+                    .build(args, &envs, paths.as_deref())
+                    .and_then(|mut cmd| {
+                        if ctx.job_control().is_some() {
+                            process::join_process_group(&mut cmd, pgid, true);
+                        }
+                        fds.configure(&mut cmd)?;
+                        Ok(process::spawn_in_group(&mut cmd, pgid, ctx)?)
+                    });
+                // Reported with the command's name, as a missing one is,
+                // while the io error underneath still gives the status.
+                let spawned = spawned.map_err(|e| match e.downcast_ref::<io::Error>() {
+                    Some(io_err) => {
+                        let msg = format!("{}: {}", cmd.name(), io_error_reason(io_err));
+                        e.context(msg)
+                    }
+                    None => e,
+                });
+                match spawned {
+                    Ok(child) => children.push(child.id() as i32),
+                    Err(e) => {
+                        // Reported on the stage's own stderr, as a builtin
+                        // reports its errors, so that it honours `2>` and
+                        // the rest of the pipeline still runs.
+                        let _ = Io::new(&fds).report(ctx, &e);
                         if is_last {
-                            cmd.stdout(Stdio::inherit());
-
-                            handle_external_redirection(&redirects, &mut cmd)?;
-
-                            let child = cmd.spawn()?;
-                            children.push(child);
-                        } else {
-                            cmd.stdout(Stdio::piped());
-
-                            handle_external_redirection(&redirects, &mut cmd)?;
-
-                            let child = cmd.spawn()?;
-                            input_buf = if child.stdout.is_some() {
-                                Some(Buf::External(child.stdout.unwrap()))
-                            } else {
-                                None
-                            }
+                            failed = Some(error_status(&e));
                         }
+                        continue;
                     }
                 }
+
+                let pid = *children.last().expect("stage was just spawned");
+                if ctx.job_control().is_some() {
+                    // Also done in the child, so that the group exists
+                    // whichever process runs first.
+                    unsafe { libc::setpgid(pid, pgid.unwrap_or(pid)) };
+                    process::give_terminal(pgid.unwrap_or(pid));
+                }
+                pgid.get_or_insert(pid);
             }
         }
     }
 
-    for mut c in children {
-        let _ = c.wait();
+    let status = match pgid {
+        Some(pgid) => wait_children(ctx, pgid, children, pipeline),
+        None => 0,
+    };
+
+    match (ends_with_process, failed) {
+        (_, Some(status)) => Ok(ExecResult::Status(status)),
+        (true, None) => Ok(ExecResult::Status(status)),
+        (false, None) => Ok(ExecResult::Status(0)),
     }
-    Ok(ExecResult::Continue)
 }
 
-fn handle_builtin_redirection(
-    redirects: &Vec<Redirection>,
-    data: Result<ExecResult>,
+/// Runs a builtin with the descriptors in `fds` as its streams. An error it
+/// gives up with is reported on its stderr, like those it carries on after.
+fn run_builtin(
+    cmd: &dyn ShellCommand,
+    args: &[String],
+    fds: &FdTable,
+    ctx: &mut ShellCtx,
+) -> ExecResult {
+    let mut io = Io::new(fds);
+    let result = cmd.execute(args, &mut io, ctx).and_then(|res| {
+        io.stdout.flush()?;
+        Ok(res)
+    });
+    let status = result_status(&result);
+
+    let err = match result {
+        Ok(res) if res.leaves_list() => return res,
+        Ok(_) => return ExecResult::Status(status),
+        Err(err) => err,
+    };
+    let _ = match err.downcast_ref::<io::Error>() {
+        // The reader went away, which would have killed a process.
+        Some(e) if e.kind() == io::ErrorKind::BrokenPipe => {
+            return ExecResult::Status(128 + libc::SIGPIPE);
+        }
+        Some(e) if e.raw_os_error() == Some(libc::EBADF) => {
+            let err = format!("{}: write error: {}", cmd.name(), error::io_error_reason(e));
+            io.report(ctx, &err)
+        }
+        _ => io.report(ctx, &err),
+    };
+
+    ExecResult::Status(status)
+}
+
+/// Runs a compound command on its own in the current shell, with its
+/// redirects in place for the duration.
+fn run_compound(
+    compound: &Compound,
+    redirects: &[Redirection],
+    ctx: &mut ShellCtx,
 ) -> Result<ExecResult> {
     if redirects.is_empty() {
-        return data;
+        return compound::execute_compound(compound, ctx);
+    }
+
+    let redirects = expand_redirects(redirects, ctx)?;
+    let mut fds = FdTable::new(None, None)?;
+    fds.apply(&redirects)?;
+
+    fds.install(|| compound::execute_compound(compound, ctx))?
+}
+
+/// Forks a pipeline stage that runs a compound command, reading from and
+/// writing to `pipes` unless its redirects say otherwise.
+fn fork_compound(
+    compound: &Compound,
+    redirects: &[Redirection],
+    (stdin, stdout): (Option<OwnedFd>, Option<OwnedFd>),
+    pgid: Option<i32>,
+    ctx: &mut ShellCtx,
+) -> Result<i32> {
+    let redirects = expand_redirects(redirects, ctx)?;
+    let mut fds = FdTable::new(stdin, stdout)?;
+    fds.apply(&redirects)?;
+
+    process::fork_stage(ctx, &fds, pgid, |ctx| {
+        compound::execute_compound(compound, ctx)
+    })
+}
+
+/// Runs a function with `args` as its positional parameters.
+fn call_function(function: &Function, args: Vec<String>, ctx: &mut ShellCtx) -> Result<ExecResult> {
+    let Command::Compound(compound, redirects) = &function.body else {
+        unreachable!("the parser only accepts compound commands as function bodies");
     };
 
-    let redirect_out = redirects
-        .iter()
-        .any(|r| matches!(r.redirect, Redirect::StdOut(_)));
-    let redirect_err = redirects
-        .iter()
-        .any(|r| matches!(r.redirect, Redirect::StdErr(_)));
-
-    for redirect in redirects {
-        let Redirection { redirect, path } = redirect;
-
-        match redirect {
-            redirection::Redirect::StdErr(append) => {
-                writer::create_file(path, &append)?;
-                match &data {
-                    Ok(res) => {
-                        if let ExecResult::Res(res) = res
-                            && !redirect_out
-                        {
-                            return Ok(ExecResult::Res(res.to_string()));
-                        }
-                    }
-                    Err(e) => writer::write_file(path, e.to_string().as_str(), &append)?,
-                }
-            }
-            redirection::Redirect::StdOut(append) => {
-                writer::create_file(path, &append)?;
-                match &data {
-                    Ok(ExecResult::Res(res)) => {
-                        writer::write_file(path, res.clone().as_ref(), &append)?
-                    }
-                    Ok(_) => writer::write_file(path, "", &append)?,
-                    Err(e) => {
-                        if !redirect_err {
-                            return Err(ShellError::Execution(e.to_string()).into());
-                        }
-                    }
-                }
-            }
-        }
+    let res = ctx.call_function(&function.name, args, |ctx| {
+        run_compound(compound, redirects, ctx)
+    })?;
+    match res {
+        ExecResult::Return(status) => Ok(ExecResult::Status(status)),
+        ExecResult::Break(_) | ExecResult::ContinueLoop(_) => Ok(ExecResult::Status(0)),
+        res => Ok(res),
     }
-    Ok(ExecResult::Continue)
 }
 
-fn handle_external_redirection(redirects: &Vec<Redirection>, cmd: &mut Command) -> Result<()> {
-    if redirects.is_empty() {
-        return Ok(());
-    }
-    for redirect in redirects {
-        match redirect.redirect {
-            Redirect::StdOut(append) => {
-                let file = writer::create_file(redirect.path, &append)?;
-                cmd.stdout(Stdio::from(file));
-            }
-            Redirect::StdErr(append) => {
-                let file = writer::create_file(redirect.path, &append)?;
-                cmd.stderr(Stdio::from(file));
-            }
+fn expand_redirects(
+    redirects: &[Redirection],
+    ctx: &mut ShellCtx,
+) -> Result<Vec<Redirection<String>>> {
+    redirects
+        .iter()
+        .map(|redirect| redirect.expand(ctx))
+        .collect()
+}
+
+/// The pipe ends a stage reads from and writes to, where the last stage
+/// writes to the shell's own stdout.
+fn stage_pipes(
+    next_stdin: &mut Option<OwnedFd>,
+    is_last: bool,
+) -> io::Result<(Option<OwnedFd>, Option<OwnedFd>)> {
+    let stdin = next_stdin.take();
+    let stdout = match is_last {
+        true => None,
+        false => {
+            let (reader, writer) = io::pipe()?;
+            *next_stdin = Some(reader.into());
+            Some(writer.into())
         }
-    }
-    Ok(())
+    };
+
+    Ok((stdin, stdout))
+}
+
+/// Waits for the spawned stages of a pipeline as one foreground job.
+fn wait_children(ctx: &mut ShellCtx, pgid: i32, pids: Vec<i32>, pipeline: &[Command]) -> i32 {
+    let command = pipeline
+        .iter()
+        .map(Command::to_string)
+        .collect::<Vec<_>>()
+        .join(" | ");
+
+    process::wait_foreground(ctx, Job::new(pgid, pids, command))
 }
@@ -1,50 +1,230 @@
-mod lexer;
-mod parser;
 mod shell;
 
-use codecrafters_shell::ExecResult;
+use codecrafters_shell::{ExecResult, ShellCtx, ShellError, error_status, io_error_reason, keymap};
 
 use anyhow::Result;
 use shell::Shell;
-use std::io::{self, Write};
+use std::{
+    env, fs,
+    io::{self, IsTerminal, Read, Write},
+};
 use termion::raw::{IntoRawMode, RawTerminal};
 
+const CONTINUATION_PROMPT: &str = "> ";
+
+/// The startup file an interactive shell reads before its first prompt.
+enum RcFile {
+    /// `$SHELLRC`, or `~/.codecraftersrc`, when it exists.
+    Default,
+    /// Given with `--rcfile`.
+    Path(String),
+    /// Turned off with `--norc`.
+    None,
+}
+
 fn main() {
-    let prompt = "$ ";
+    let mut args = env::args();
+    let program = args
+        .next()
+        .unwrap_or_else(|| "codecrafters-shell".to_string());
+    let mut args: Vec<String> = args.collect();
 
-    let mut stdin = io::stdin().lock();
-    let mut out = Out::Raw(io::stdout().into_raw_mode().unwrap());
+    let mut rc_file = RcFile::Default;
+    while let Some(option) = args.first() {
+        match option.as_str() {
+            "--norc" => rc_file = RcFile::None,
+            "--rcfile" => {
+                let Some(path) = args.get(1) else {
+                    eprintln!("{program}: --rcfile: option requires an argument");
+                    std::process::exit(2);
+                };
+                rc_file = RcFile::Path(path.clone());
+                args.remove(0);
+            }
+            _ => break,
+        }
+        args.remove(0);
+    }
+
+    match args.first().map(String::as_str) {
+        Some("-c") => {
+            let Some(command) = args.get(1) else {
+                eprintln!("{program}: -c: option requires an argument");
+                std::process::exit(2);
+            };
+            // As in other shells, the word after the command becomes `$0`.
+            let name = args.get(2).unwrap_or(&program);
+            let params = args.iter().skip(3).cloned().collect();
+            run_script(command, name, params);
+        }
+        Some(path) => {
+            let text = match fs::read_to_string(path) {
+                Ok(text) => text,
+                Err(e) => {
+                    eprintln!("{program}: {path}: {}", io_error_reason(&e));
+                    std::process::exit(127);
+                }
+            };
+            run_script(&text, path, args[1..].to_vec());
+        }
+        None if !io::stdin().is_terminal() => {
+            let mut text = String::new();
+            if let Err(e) = io::stdin().read_to_string(&mut text) {
+                eprintln!("{program}: {}", io_error_reason(&e));
+                std::process::exit(1);
+            }
+            run_script(&text, &program, Vec::new());
+        }
+        None => run_interactive(&program, rc_file),
+    }
+}
+
+/// Runs commands without the line editor or job control and exits with
+/// the status of the last one. Errors are reported as coming from `name`.
+fn run_script(text: &str, name: &str, params: Vec<String>) -> ! {
+    let mut ctx = ShellCtx::build().expect("Failed to load initialize shell");
+    ctx.set_shell_name(name);
+    ctx.set_positional(params);
+
+    match codecrafters_shell::run_script(text, name, &mut ctx) {
+        ExecResult::Exit(status) | ExecResult::Status(status) => std::process::exit(status),
+        _ => std::process::exit(ctx.last_status()),
+    }
+}
+
+/// Sources the startup file into `ctx`, as if with `source`.
+fn read_rc_file(program: &str, rc_file: RcFile, ctx: &mut ShellCtx) {
+    let path = match rc_file {
+        RcFile::None => return,
+        RcFile::Path(path) => path,
+        RcFile::Default => {
+            let path = env::var("SHELLRC")
+                .ok()
+                .or_else(|| Some(format!("{}/.codecraftersrc", env::var("HOME").ok()?)));
+            match path {
+                Some(path) if fs::exists(&path).unwrap_or(false) => path,
+                _ => return,
+            }
+        }
+    };
+
+    let text = match fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(e) => {
+            eprintln!("{program}: {path}: {}", io_error_reason(&e));
+            return;
+        }
+    };
+    let res = ctx.source(Vec::new(), |ctx| {
+        codecrafters_shell::run_script(&text, &path, ctx)
+    });
+    if let ExecResult::Exit(status) = res {
+        std::process::exit(status);
+    }
+}
+
+/// Reads key bindings from `$INPUTRC`, or else from `~/.codecrafters_inputrc`
+/// or `~/.inputrc`, whichever exists first.
+fn read_init_file(program: &str, ctx: &mut ShellCtx) {
+    let home = env::var("HOME").ok();
+    let candidates = [
+        env::var("INPUTRC").ok(),
+        home.as_ref()
+            .map(|home| format!("{home}/.codecrafters_inputrc")),
+        home.as_ref().map(|home| format!("{home}/.inputrc")),
+    ];
+    let Some(path) = candidates
+        .into_iter()
+        .flatten()
+        .find(|path| fs::exists(path).unwrap_or(false))
+    else {
+        return;
+    };
+
+    match keymap::read_init_file(&path, ctx) {
+        Ok(errors) => {
+            for (line, msg) in errors {
+                eprintln!("{program}: {path}: line {line}: {msg}");
+            }
+        }
+        Err(e) => eprintln!("{program}: {path}: {e}"),
+    }
+}
 
+fn run_interactive(program: &str, rc_file: RcFile) -> ! {
     let mut shell = Shell::build().expect("Failed to load initialize shell");
+    shell.ctx.set_interactive(true);
+    shell.ctx.enable_job_control();
+    read_init_file(program, &mut shell.ctx);
+    read_rc_file(program, rc_file, &mut shell.ctx);
+
+    let mut stdin = io::stdin().lock();
+    let mut out = Out::Raw(io::stdout().into_raw_mode().unwrap());
 
     loop {
+        shell.report_jobs(&mut out);
+        let ps1 = shell.ctx.get_var("PS1");
+        let prompt =
+            &codecrafters_shell::expand_prompt(ps1.as_deref().unwrap_or("$ "), &mut shell.ctx);
         shell.redraw(&mut out, prompt);
 
-        let Ok(input) = shell.run(&mut stdin, &mut out, prompt) else {
-            break;
+        let mut input = match shell.run(&mut stdin, &mut out, prompt) {
+            Ok(input) => input,
+            Err(e) if is_interrupted(&e) => continue,
+            Err(_) => break,
         };
 
-        let Some(tokens) = handle_result(lexer::run_lexer(&input), &mut out, prompt, &shell) else {
+        // Keep reading lines while the input is incomplete, such as a
+        // here-doc that is still missing its delimiter.
+        let mut interrupted = false;
+        while codecrafters_shell::parse(&input).is_err_and(|e| is_incomplete(&e)) {
+            shell.redraw(&mut out, CONTINUATION_PROMPT);
+            match shell.run(&mut stdin, &mut out, CONTINUATION_PROMPT) {
+                Ok(line) => {
+                    input.push('\n');
+                    input.push_str(&line);
+                }
+                Err(e) => {
+                    interrupted = is_interrupted(&e);
+                    break;
+                }
+            }
+        }
+        if interrupted {
             continue;
-        };
+        }
 
-        let Some(parsed) = handle_result(parser::parse(tokens), &mut out, prompt, &shell) else {
+        let parsed = codecrafters_shell::parse(&input);
+        let Some(parsed) = handle_result(parsed, &mut out, prompt, &mut shell) else {
             continue;
         };
 
         let result = with_cooked_terminal(&mut out, || {
-            codecrafters_shell::execute_pipeline(parsed, &mut shell.ctx)
+            codecrafters_shell::execute(&parsed, &mut shell.ctx)
         });
 
         match result {
-            Ok(ExecResult::Res(res)) => {
-                print_and_redraw(&mut out, prompt, &shell, &res.trim());
-            }
             Ok(ExecResult::Exit(code)) => std::process::exit(code),
-            Ok(ExecResult::Continue) => {}
+            Ok(ExecResult::Continue)
+            | Ok(ExecResult::Status(_))
+            | Ok(ExecResult::Break(_))
+            | Ok(ExecResult::ContinueLoop(_))
+            | Ok(ExecResult::Return(_)) => {}
             Err(e) => print_and_redraw(&mut out, prompt, &shell, &e.to_string()),
         }
     }
+
+    // Leave raw mode before exiting, as `exit` skips destructors.
+    drop(out);
+    std::process::exit(shell.ctx.last_status());
+}
+
+fn is_incomplete(err: &anyhow::Error) -> bool {
+    matches!(err.downcast_ref(), Some(ShellError::Incomplete))
+}
+
+fn is_interrupted(err: &anyhow::Error) -> bool {
+    matches!(err.downcast_ref(), Some(ShellError::Interrupted))
 }
 
 fn print_and_redraw(out: &mut Out, prompt: &str, shell: &Shell, msg: &str) {
@@ -74,12 +254,13 @@ impl Write for Out {
     }
 }
 
-fn handle_result<T>(data: Result<T>, err: &mut Out, prompt: &str, shell: &Shell) -> Option<T> {
+fn handle_result<T>(data: Result<T>, err: &mut Out, prompt: &str, shell: &mut Shell) -> Option<T> {
     match data {
-        Ok(data) => return Some(data),
+        Ok(data) => Some(data),
         Err(e) => {
+            shell.ctx.set_last_status(error_status(&e));
             print_and_redraw(err, prompt, shell, &e.to_string());
-            return None;
+            None
         }
     }
 }
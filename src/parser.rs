@@ -1,95 +1,809 @@
 use anyhow::Result;
+use std::{iter::Peekable, rc::Rc, slice::Iter};
 
-use super::lexer::{Token, TokenType};
-use codecrafters_shell::{Cmds, CommandStage, ParsedLine, Redirection, ShellError, redirection};
+use crate::lexer::{Token, TokenType, arithmetic_end, backtick_end, parens_end, scan_variable};
+use crate::{
+    CaseItem, Command, CommandStage, Compound, Function, ParsedLine, Redirect, Redirection,
+    ShellError, Word, WordPart, is_valid_name, redirection,
+};
+
+type Tokens<'a, 'b> = Peekable<Iter<'b, Token<'a>>>;
+
+/// Reserved words that start a compound command.
+const OPENING: [&str; 7] = ["if", "while", "until", "for", "case", "{", "function"];
+/// Reserved words that continue or close a compound command, which end the
+/// list before them.
+const CLOSING: [&str; 8] = ["then", "elif", "else", "fi", "do", "done", "esac", "}"];
 
 pub fn parse(tokens: Vec<Token>) -> Result<ParsedLine> {
-    let pipes: Vec<Vec<Token>> = tokens
-        .split(|token| token.token_type == TokenType::Pipe)
-        .map(|chunk| chunk.to_vec())
-        .collect();
+    let mut tokens = tokens.iter().peekable();
+    let line = parse_list(&mut tokens)?;
 
+    match tokens.peek() {
+        Some(token) => Err(unexpected(Some(token))),
+        None => Ok(line),
+    }
+}
+
+/// Parses commands separated by `;`, `&` or newlines, up to the end of the
+/// input or a token that belongs to an enclosing compound command.
+fn parse_list(tokens: &mut Tokens) -> Result<ParsedLine> {
     let mut lines = Vec::new();
-    for pipe in pipes {
-        let parsed = parse_command(pipe)?;
-        if parsed.is_some() {
-            lines.push(parsed.unwrap());
+
+    loop {
+        skip_newlines(tokens);
+        if at_list_end(tokens) {
+            break;
+        }
+
+        let line = parse_and_or(tokens)?;
+        let separator = tokens.next_if(|token| {
+            matches!(
+                token.token_type,
+                TokenType::Background | TokenType::Semicolon | TokenType::Newline
+            )
+        });
+        match separator {
+            Some(token) if token.token_type == TokenType::Background => {
+                lines.push(ParsedLine::Background(Box::new(line)));
+            }
+            Some(_) => lines.push(line),
+            None => {
+                lines.push(line);
+                break;
+            }
         }
     }
 
-    Ok(ParsedLine::Pipeline(lines))
+    match lines.len() {
+        1 => Ok(lines.remove(0)),
+        _ => Ok(ParsedLine::Sequence(lines)),
+    }
 }
 
-fn parse_command(tokens: Vec<Token>) -> Result<Option<CommandStage>> {
-    let mut tokens = tokens.iter();
-    let Some(cmd) = tokens.next().map(|token| {
-        return Cmds::new(&token.origin);
-    }) else {
-        return Ok(None);
+/// Parses a list that may not be empty, such as the body of a loop.
+fn parse_body(tokens: &mut Tokens) -> Result<ParsedLine> {
+    let line = parse_list(tokens)?;
+    match line.is_empty() {
+        true => Err(missing(tokens)),
+        false => Ok(line),
+    }
+}
+
+fn at_list_end(tokens: &mut Tokens) -> bool {
+    match tokens.peek() {
+        None => true,
+        Some(token) if matches!(token.token_type, TokenType::RightParen | TokenType::CaseEnd) => {
+            true
+        }
+        _ => plain_word(tokens).is_some_and(|word| CLOSING.contains(&word)),
+    }
+}
+
+fn skip_newlines(tokens: &mut Tokens) {
+    while tokens
+        .next_if(|token| token.token_type == TokenType::Newline)
+        .is_some()
+    {}
+}
+
+/// Parses pipelines joined by `&&` and `||`, which bind left to right.
+fn parse_and_or(tokens: &mut Tokens) -> Result<ParsedLine> {
+    let mut line = parse_pipeline(tokens)?;
+
+    while let Some(token) =
+        tokens.next_if(|token| matches!(token.token_type, TokenType::And | TokenType::Or))
+    {
+        skip_newlines(tokens);
+        let right = Box::new(parse_pipeline(tokens)?);
+        line = match token.token_type {
+            TokenType::And => ParsedLine::And(Box::new(line), right),
+            _ => ParsedLine::Or(Box::new(line), right),
+        };
+    }
+
+    Ok(line)
+}
+
+fn parse_pipeline(tokens: &mut Tokens) -> Result<ParsedLine> {
+    let mut stages = vec![parse_command(tokens)?];
+
+    while tokens
+        .next_if(|token| token.token_type == TokenType::Pipe)
+        .is_some()
+    {
+        skip_newlines(tokens);
+        stages.push(parse_command(tokens)?);
+    }
+
+    Ok(ParsedLine::Pipeline(stages))
+}
+
+fn parse_command(tokens: &mut Tokens) -> Result<Command> {
+    if tokens.peek().is_none() {
+        return Err(ShellError::Incomplete.into());
+    }
+
+    if let Some(name) = function_header(tokens) {
+        tokens.nth(2);
+        return parse_function(tokens, name);
+    }
+
+    let compound = if let Some(token) =
+        tokens.next_if(|token| token.token_type == TokenType::ArithmeticCommand)
+    {
+        Compound::Arithmetic(Word(parse_double_quoted(token.origin)))
+    } else if tokens
+        .next_if(|token| token.token_type == TokenType::LeftParen)
+        .is_some()
+    {
+        let body = parse_body(tokens)?;
+        if tokens
+            .next_if(|token| token.token_type == TokenType::RightParen)
+            .is_none()
+        {
+            return Err(missing(tokens));
+        }
+        Compound::Subshell(Box::new(body))
+    } else {
+        match plain_word(tokens) {
+            Some(word) if OPENING.contains(&word) => {
+                tokens.next();
+                match word {
+                    "if" => parse_if(tokens)?,
+                    "while" => parse_loop(tokens, false)?,
+                    "until" => parse_loop(tokens, true)?,
+                    "for" => parse_for(tokens)?,
+                    "case" => parse_case(tokens)?,
+                    "{" => {
+                        let body = parse_body(tokens)?;
+                        expect(tokens, "}")?;
+                        Compound::Group(Box::new(body))
+                    }
+                    _ => {
+                        let Some(name) = plain_word(tokens) else {
+                            return Err(missing(tokens));
+                        };
+                        tokens.next();
+                        if tokens
+                            .next_if(|token| token.token_type == TokenType::LeftParen)
+                            .is_some()
+                            && tokens
+                                .next_if(|token| token.token_type == TokenType::RightParen)
+                                .is_none()
+                        {
+                            return Err(missing(tokens));
+                        }
+                        return parse_function(tokens, name);
+                    }
+                }
+            }
+            Some(word) if CLOSING.contains(&word) => {
+                return Err(unexpected(tokens.peek().copied()));
+            }
+            _ => return parse_simple(tokens).map(Command::Simple),
+        }
     };
 
-    let mut args: Vec<String> = Vec::new();
+    let mut redirects = Vec::new();
+    while let Some(token) = tokens.next_if(|token| token.token_type == TokenType::Redirects) {
+        redirects.push(parse_redirect(token, tokens)?);
+    }
+
+    Ok(Command::Compound(compound, redirects))
+}
+
+/// The name of a function defined as `name() ...`, which is followed by
+/// the parentheses.
+fn function_header<'a>(tokens: &Tokens<'a, '_>) -> Option<&'a str> {
+    let name =
+        plain_word(tokens).filter(|name| !OPENING.contains(name) && !CLOSING.contains(name))?;
+    let mut ahead = tokens.clone().skip(1);
+    let is_header = ahead
+        .next()
+        .is_some_and(|token| token.token_type == TokenType::LeftParen)
+        && ahead
+            .next()
+            .is_some_and(|token| token.token_type == TokenType::RightParen);
+
+    is_header.then_some(name)
+}
+
+/// Parses the body of a function after its name, which has to be a
+/// compound command.
+fn parse_function(tokens: &mut Tokens, name: &str) -> Result<Command> {
+    skip_newlines(tokens);
+    let is_compound = tokens.peek().is_some_and(|token| {
+        matches!(
+            token.token_type,
+            TokenType::ArithmeticCommand | TokenType::LeftParen
+        )
+    }) || plain_word(tokens)
+        .is_some_and(|word| OPENING.contains(&word) && word != "function");
+    if !is_compound {
+        return Err(missing(tokens));
+    }
+
+    Ok(Command::Function(Rc::new(Function {
+        name: name.to_string(),
+        body: parse_command(tokens)?,
+    })))
+}
+
+fn parse_if(tokens: &mut Tokens) -> Result<Compound> {
+    let mut branches = Vec::new();
+
+    loop {
+        let condition = parse_body(tokens)?;
+        expect(tokens, "then")?;
+        branches.push((condition, parse_body(tokens)?));
+
+        match plain_word(tokens) {
+            Some("elif") => {
+                tokens.next();
+            }
+            Some("else") => {
+                tokens.next();
+                let otherwise = parse_body(tokens)?;
+                expect(tokens, "fi")?;
+                return Ok(Compound::If {
+                    branches,
+                    otherwise: Some(Box::new(otherwise)),
+                });
+            }
+            _ => {
+                expect(tokens, "fi")?;
+                return Ok(Compound::If {
+                    branches,
+                    otherwise: None,
+                });
+            }
+        }
+    }
+}
+
+fn parse_loop(tokens: &mut Tokens, until: bool) -> Result<Compound> {
+    let condition = parse_body(tokens)?;
+    let body = parse_do_group(tokens)?;
+
+    Ok(Compound::Loop {
+        until,
+        condition: Box::new(condition),
+        body: Box::new(body),
+    })
+}
+
+fn parse_for(tokens: &mut Tokens) -> Result<Compound> {
+    if let Some(token) = tokens.next_if(|token| token.token_type == TokenType::ArithmeticCommand) {
+        let [init, condition, step] = split_arithmetic_for(token.origin)
+            .ok_or_else(|| ShellError::Syntax(format!("(({}))", token.origin)))?;
+        tokens.next_if(|token| token.token_type == TokenType::Semicolon);
+
+        return Ok(Compound::ArithmeticFor {
+            init,
+            condition,
+            step,
+            body: Box::new(parse_do_group(tokens)?),
+        });
+    }
+
+    let Some(name) = tokens.next_if(|token| token.token_type == TokenType::Word) else {
+        return Err(missing(tokens));
+    };
+    if !is_valid_name(name.origin) {
+        return Err(
+            ShellError::Execution(format!("`{}': not a valid identifier", name.origin)).into(),
+        );
+    }
+
+    skip_newlines(tokens);
+    let words = match plain_word(tokens) {
+        Some("in") => {
+            tokens.next();
+            let mut words = Vec::new();
+            while let Some(token) = tokens.next_if(|token| token.token_type.is_word()) {
+                words.push(next_word(token, tokens));
+            }
+            Some(words)
+        }
+        _ => None,
+    };
+    tokens.next_if(|token| token.token_type == TokenType::Semicolon);
+
+    Ok(Compound::For {
+        name: name.origin.to_string(),
+        words,
+        body: Box::new(parse_do_group(tokens)?),
+    })
+}
+
+/// Splits the header of `for ((init; condition; step))` into its three
+/// expressions.
+fn split_arithmetic_for(header: &str) -> Option<[Word; 3]> {
+    let mut parts = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+
+    for (idx, c) in header.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            ';' if depth == 0 => {
+                parts.push(&header[start..idx]);
+                start = idx + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(&header[start..]);
+
+    let parts: Vec<Word> = parts
+        .into_iter()
+        .map(|part| Word(parse_double_quoted(part.trim())))
+        .collect();
+    parts.try_into().ok()
+}
+
+/// Parses the `do ... done` of a loop.
+fn parse_do_group(tokens: &mut Tokens) -> Result<ParsedLine> {
+    skip_newlines(tokens);
+    expect(tokens, "do")?;
+    let body = parse_body(tokens)?;
+    expect(tokens, "done")?;
+
+    Ok(body)
+}
+
+fn parse_case(tokens: &mut Tokens) -> Result<Compound> {
+    let Some(token) = tokens.next_if(|token| token.token_type.is_word()) else {
+        return Err(missing(tokens));
+    };
+    let word = next_word(token, tokens);
+    skip_newlines(tokens);
+    expect(tokens, "in")?;
+
+    let mut items = Vec::new();
+    loop {
+        skip_newlines(tokens);
+        if plain_word(tokens) == Some("esac") {
+            tokens.next();
+            break;
+        }
+
+        tokens.next_if(|token| token.token_type == TokenType::LeftParen);
+        let mut patterns = Vec::new();
+        loop {
+            let Some(token) = tokens.next_if(|token| token.token_type.is_word()) else {
+                return Err(missing(tokens));
+            };
+            patterns.push(next_word(token, tokens));
+
+            if tokens
+                .next_if(|token| token.token_type == TokenType::Pipe)
+                .is_none()
+            {
+                break;
+            }
+        }
+        if tokens
+            .next_if(|token| token.token_type == TokenType::RightParen)
+            .is_none()
+        {
+            return Err(missing(tokens));
+        }
+
+        let body = parse_list(tokens)?;
+        items.push(CaseItem { patterns, body });
+
+        if tokens
+            .next_if(|token| token.token_type == TokenType::CaseEnd)
+            .is_none()
+        {
+            skip_newlines(tokens);
+            expect(tokens, "esac")?;
+            break;
+        }
+    }
+
+    Ok(Compound::Case { word, items })
+}
+
+fn parse_simple(tokens: &mut Tokens) -> Result<CommandStage> {
+    let mut assignments: Vec<(String, Word)> = Vec::new();
+    let mut words: Vec<Word> = Vec::new();
     let mut redirects: Vec<Redirection> = Vec::new();
-    let mut current_arg = String::new();
 
-    while let Some(token) = tokens.next() {
+    while let Some(token) = tokens
+        .next_if(|token| token.token_type.is_word() || token.token_type == TokenType::Redirects)
+    {
         if token.token_type == TokenType::Redirects {
-            let Some(path_token) = tokens.next() else {
-                return Err(ShellError::MissingArg.into());
-            };
-            redirects.push(Redirection::new(
-                redirection::eval_redirect(token.origin),
-                path_token.origin,
-            ));
+            redirects.push(parse_redirect(token, tokens)?);
             continue;
         }
 
-        let origin = match token.token_type {
-            TokenType::DoubleQuote => &process_escaped(token.origin),
-            _ => token.origin,
+        if words.is_empty()
+            && let Some(name) = assignment_name(token)
+        {
+            let mut value = next_word(token, tokens);
+            value.0[0] = WordPart::Literal(token.origin[name.len() + 1..].to_string());
+            assignments.push((name.to_string(), value));
+            continue;
+        }
+
+        words.push(next_word(token, tokens));
+    }
+
+    if words.is_empty() && assignments.is_empty() && redirects.is_empty() {
+        return Err(unexpected(tokens.peek().copied()));
+    }
+
+    Ok(CommandStage {
+        assignments,
+        words,
+        redirects,
+    })
+}
+
+/// Parses the target of a redirect operator, or the body of a here-doc.
+fn parse_redirect<'a, 'b>(
+    operator: &'b Token<'a>,
+    tokens: &mut Tokens<'a, 'b>,
+) -> Result<Redirection> {
+    if let Some(body) = tokens.next_if(|body| matches!(body.token_type, TokenType::HereDoc { .. }))
+    {
+        return Ok(here_doc(operator, body));
+    }
+
+    let Some(path) = tokens.next_if(|path| path.token_type.is_word()) else {
+        return Err(unexpected(tokens.peek().copied()));
+    };
+    Ok(Redirection::new(
+        redirection::eval_redirect(operator.origin),
+        next_word(path, tokens),
+    ))
+}
+
+/// The next token when it is a plain, unquoted word, which is how reserved
+/// words are recognised.
+fn plain_word<'a>(tokens: &Tokens<'a, '_>) -> Option<&'a str> {
+    let mut ahead = tokens.clone();
+    let token = ahead.next()?;
+    let is_glued = token.is_adjacent && ahead.peek().is_some_and(|next| next.token_type.is_word());
+
+    (token.token_type == TokenType::Word && !is_glued).then_some(token.origin)
+}
+
+/// Consumes the reserved word `keyword`, which has to come next.
+fn expect(tokens: &mut Tokens, keyword: &str) -> Result<()> {
+    if plain_word(tokens) == Some(keyword) {
+        tokens.next();
+        return Ok(());
+    }
+
+    Err(missing(tokens))
+}
+
+/// The error for a token that is not what the grammar needs next, where
+/// running out of input means more lines are needed.
+fn missing(tokens: &mut Tokens) -> anyhow::Error {
+    match tokens.peek() {
+        Some(token) => unexpected(Some(token)),
+        None => ShellError::Incomplete.into(),
+    }
+}
+
+fn unexpected(token: Option<&Token>) -> anyhow::Error {
+    let origin = match token {
+        Some(token) if token.token_type == TokenType::Newline => "newline",
+        Some(token) => token.origin,
+        None => "newline",
+    };
+    ShellError::Syntax(origin.to_string()).into()
+}
+
+/// Glues adjacent tokens into a single word.
+fn next_word<'a, 'b>(mut token: &'b Token<'a>, tokens: &mut Tokens<'a, 'b>) -> Word {
+    let mut parts = Vec::new();
+
+    loop {
+        let part = match token.token_type {
+            TokenType::DoubleQuote => WordPart::DoubleQuoted(parse_double_quoted(token.origin)),
+            TokenType::Variable => WordPart::Variable(token.origin.to_string()),
+            TokenType::Word => WordPart::Literal(token.origin.to_string()),
+            TokenType::CommandSubst => WordPart::Command(token.origin.to_string()),
+            TokenType::Backtick => WordPart::Command(unescape_backtick(token.origin)),
+            TokenType::ArithmeticSubst => WordPart::Arithmetic(parse_double_quoted(token.origin)),
+            _ => WordPart::Quoted(token.origin.to_string()),
         };
-        current_arg.push_str(origin);
+        parts.push(part);
 
         if !token.is_adjacent {
-            args.push(current_arg);
-            current_arg = String::new();
+            break;
+        }
+        match tokens.next_if(|next| next.token_type.is_word()) {
+            Some(next) => token = next,
+            None => break,
         }
     }
 
-    if !current_arg.is_empty() {
-        args.push(current_arg);
+    Word(parts)
+}
+
+/// Builds a here-doc redirect from its operator and body tokens.
+fn here_doc(operator: &Token, body: &Token) -> Redirection {
+    let TokenType::HereDoc { delimiter, expand } = &body.token_type else {
+        unreachable!("only called with here-doc bodies");
+    };
+    let mut redirect = redirection::eval_redirect(operator.origin);
+    let Redirect::HereDoc {
+        delimiter: redirect_delimiter,
+        strip_tabs,
+        ..
+    } = &mut redirect
+    else {
+        unreachable!("here-doc bodies only follow here-doc operators");
+    };
+    *redirect_delimiter = delimiter.clone();
+
+    let text = match strip_tabs {
+        true => body
+            .origin
+            .split_inclusive('\n')
+            .map(|line| line.trim_start_matches('\t'))
+            .collect(),
+        false => body.origin.to_string(),
+    };
+    let parts = match expand {
+        true => parse_escaped(&text, &['\\', '$', '`', '\n']),
+        false => vec![WordPart::Quoted(text)],
+    };
+
+    Redirection::new(redirect, Word(parts))
+}
+
+/// Returns the variable name if the token starts a `NAME=value` assignment.
+fn assignment_name<'a>(token: &Token<'a>) -> Option<&'a str> {
+    if token.token_type != TokenType::Word {
+        return None;
     }
 
-    let parsed = Some(CommandStage {
-        cmd,
-        args,
-        redirects,
-    });
+    let (name, _) = token.origin.split_once('=')?;
+    is_valid_name(name).then_some(name)
+}
 
-    Ok(parsed)
+/// Parses a prompt string, which is expanded as if it were double-quoted
+/// but may hold quotes of its own.
+pub fn parse_prompt(input: &str) -> Word {
+    Word(parse_escaped(input, &['\\', '$', '`']))
 }
 
-fn process_escaped(input: &str) -> String {
+/// Splits the contents of a double-quoted string into literal text and
+/// variables, resolving the escapes that are special inside double quotes.
+fn parse_double_quoted(input: &str) -> Vec<WordPart> {
+    parse_escaped(input, &['\\', '"', '$', '`', '\n'])
+}
+
+/// Resolves the escapes inside backticks, where a backslash only escapes
+/// `\\`, `` ` `` and `$`.
+fn unescape_backtick(input: &str) -> String {
+    let mut command = String::with_capacity(input.len());
+    let mut chars = input.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match chars.next_if(|next| c == '\\' && matches!(next, '\\' | '`' | '$')) {
+            Some(next) => command.push(next),
+            None => command.push(c),
+        }
+    }
+
+    command
+}
+
+/// Splits text into literal text, variables and command substitutions,
+/// where a backslash only escapes the characters in `special`.
+fn parse_escaped(input: &str, special: &[char]) -> Vec<WordPart> {
     let mut chars = input.chars();
-    let mut result = String::new();
+    let mut parts = Vec::new();
+    let mut text = String::new();
 
     while let Some(c) = chars.next() {
+        let rest = chars.as_str();
+        let substitution = match c {
+            '$' if rest.starts_with('(') => {
+                match rest[1..].strip_prefix('(').and_then(arithmetic_end) {
+                    Some(end) => {
+                        let parts = parse_double_quoted(&rest[2..end + 2]);
+                        Some((WordPart::Arithmetic(parts), end + 4))
+                    }
+                    None => parens_end(&rest[1..])
+                        .map(|end| (WordPart::Command(rest[1..end + 1].to_string()), end + 2)),
+                }
+            }
+            '`' => backtick_end(rest)
+                .map(|end| (WordPart::Command(unescape_backtick(&rest[..end])), end + 1)),
+            _ => None,
+        };
+        if let Some((part, len)) = substitution {
+            if !text.is_empty() {
+                parts.push(WordPart::Quoted(std::mem::take(&mut text)));
+            }
+            parts.push(part);
+            chars = rest[len..].chars();
+            continue;
+        }
+
+        if c == '$'
+            && let Some((name, len)) = scan_variable(chars.as_str())
+        {
+            if !text.is_empty() {
+                parts.push(WordPart::Quoted(std::mem::take(&mut text)));
+            }
+            parts.push(WordPart::Variable(name.to_string()));
+            chars = chars.as_str()[len..].chars();
+            continue;
+        }
+
         if c != '\\' {
-            result.push(c);
+            text.push(c);
             continue;
         }
 
         match chars.next() {
-            Some(next) if matches!(next, '\\' | '"' | '$' | '`' | '\n') => {
-                result.push(next);
+            Some('\n') if special.contains(&'\n') => {}
+            Some(next) if special.contains(&next) => {
+                text.push(next);
             }
             Some(next) => {
-                result.push(c);
-                result.push(next);
+                text.push(c);
+                text.push(next);
             }
-            None => result.push(c),
+            None => text.push(c),
+        }
+    }
+
+    if !text.is_empty() {
+        parts.push(WordPart::Quoted(text));
+    }
+
+    parts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::run_lexer;
+
+    /// Parses `input` and writes it back out on one line, which shows how
+    /// its commands were grouped.
+    fn reparse(input: &str) -> String {
+        parse(run_lexer(input).unwrap()).unwrap().to_string()
+    }
+
+    fn parse_error(input: &str) -> ShellError {
+        let err = parse(run_lexer(input).unwrap()).unwrap_err();
+        err.downcast().unwrap()
+    }
+
+    #[test]
+    fn lists() {
+        let line = parse(run_lexer("a && b || c; d &").unwrap()).unwrap();
+        let ParsedLine::Sequence(lines) = &line else {
+            panic!("expected a sequence, got {line:?}");
+        };
+        assert!(
+            matches!(&lines[0], ParsedLine::Or(left, _) if matches!(**left, ParsedLine::And(..)))
+        );
+        assert!(matches!(&lines[1], ParsedLine::Background(_)));
+        assert_eq!(line.to_string(), "a && b || c; d &");
+    }
+
+    #[test]
+    fn if_commands() {
+        let line = parse(run_lexer("if a; then b; elif c; then d; else e; fi").unwrap()).unwrap();
+        let ParsedLine::Pipeline(stages) = &line else {
+            panic!("expected a pipeline, got {line:?}");
+        };
+        let Command::Compound(
+            Compound::If {
+                branches,
+                otherwise,
+            },
+            _,
+        ) = &stages[0]
+        else {
+            panic!("expected an if command, got {:?}", stages[0]);
+        };
+        assert_eq!(branches.len(), 2);
+        assert_eq!(branches[1].0.to_string(), "c");
+        assert_eq!(otherwise.as_ref().unwrap().to_string(), "e");
+
+        assert_eq!(
+            reparse("if a\nthen\n  b\nelse c &\nfi > out"),
+            "if a; then b; else c & fi > out"
+        );
+    }
+
+    #[test]
+    fn loops() {
+        assert_eq!(
+            reparse("while a; do b; done | c"),
+            "while a; do b; done | c"
+        );
+        assert_eq!(reparse("until a\ndo\n  b\ndone"), "until a; do b; done");
+        assert_eq!(
+            reparse("for x in a \"b $y\"\ndo echo $x; done"),
+            "for x in a \"b $y\"; do echo $x; done"
+        );
+        assert_eq!(reparse("for x; do :; done"), "for x; do :; done");
+        assert_eq!(
+            reparse("for ((i = 0; i < 3; i++)) do echo $i; done"),
+            "for ((i = 0; i < 3; i++)); do echo $i; done"
+        );
+    }
+
+    #[test]
+    fn case_commands() {
+        assert_eq!(
+            reparse("case $x in\n  a|'b') echo ab;;\n  *) ;;\nesac"),
+            "case $x in a|'b') echo ab;; *);; esac"
+        );
+        // The last item may leave out its `;;`.
+        assert_eq!(reparse("case x in y) z\nesac"), "case x in y) z;; esac");
+    }
+
+    #[test]
+    fn functions() {
+        assert_eq!(reparse("f() { a; b; }"), "f () { a; b; }");
+        assert_eq!(reparse("function g {\n  a\n}"), "g () { a; }");
+        assert_eq!(reparse("function h() { a; }"), "h () { a; }");
+        assert_eq!(
+            reparse("f()\nif a; then b; fi > out"),
+            "f () if a; then b; fi > out"
+        );
+        assert_eq!(reparse("{ a; b & } > out"), "{ a; b & } > out");
+    }
+
+    #[test]
+    fn incomplete() {
+        for input in [
+            "if a; then b",
+            "if a; then b; else",
+            "while a; do",
+            "for x in a b",
+            "case x in y) z;;",
+            "a &&",
+            "a |",
+            "f() {",
+            "f()",
+        ] {
+            assert!(
+                matches!(parse_error(input), ShellError::Incomplete),
+                "{input}"
+            );
         }
     }
 
-    result
+    #[test]
+    fn syntax_errors() {
+        for (input, token) in [
+            ("if then b; fi", "then"),
+            ("done", "done"),
+            ("while a; do; done", ";"),
+            ("if a; then; fi", ";"),
+            ("case x in y) z; fi", "fi"),
+            ("a && ; b", ";"),
+            ("f() echo", "echo"),
+            ("{ }", "}"),
+        ] {
+            match parse_error(input) {
+                ShellError::Syntax(found) => assert_eq!(found, token, "{input}"),
+                err => panic!("{input}: expected a syntax error, got {err:?}"),
+            }
+        }
+
+        assert_eq!(
+            parse_error("for 1x in a; do b; done").to_string(),
+            "`1x': not a valid identifier"
+        );
+    }
 }
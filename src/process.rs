@@ -0,0 +1,318 @@
+use std::{
+    io::{self, Read, Write},
+    os::{fd::AsRawFd, unix::process::CommandExt},
+    process::{Child, Command},
+    sync::atomic::{AtomicBool, Ordering},
+    thread,
+};
+
+use anyhow::Result;
+
+use super::{ExecResult, FdTable, ShellCtx, ShellError};
+use crate::context::{Job, JobState};
+
+/// Signals the interactive shell handles or ignores but its children must not.
+const JOB_CONTROL_SIGNALS: [libc::c_int; 5] = [
+    libc::SIGINT,
+    libc::SIGQUIT,
+    libc::SIGTSTP,
+    libc::SIGTTIN,
+    libc::SIGTTOU,
+];
+
+/// Set when Ctrl-C reaches the shell itself, e.g. during `wait`.
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn on_interrupt(_: libc::c_int) {
+    INTERRUPTED.store(true, Ordering::SeqCst);
+}
+
+/// Returns whether Ctrl-C was pressed since the last call.
+pub fn take_interrupt() -> bool {
+    INTERRUPTED.swap(false, Ordering::SeqCst)
+}
+
+/// Whether Ctrl-C was pressed, either at the shell or at a foreground job,
+/// since the line started running. Lists and loops stop when it was.
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
+
+/// Puts the shell in its own process group in the foreground of the
+/// terminal, returning that group when stdin is a terminal.
+pub fn init_job_control() -> Option<i32> {
+    if unsafe { libc::isatty(libc::STDIN_FILENO) } == 0 {
+        return None;
+    }
+
+    unsafe {
+        // Wait until we are started in the foreground.
+        loop {
+            let pgid = libc::getpgrp();
+            if libc::tcgetpgrp(libc::STDIN_FILENO) == pgid {
+                break;
+            }
+            libc::kill(-pgid, libc::SIGTTIN);
+        }
+
+        for signal in [libc::SIGQUIT, libc::SIGTSTP, libc::SIGTTIN, libc::SIGTTOU] {
+            libc::signal(signal, libc::SIG_IGN);
+        }
+
+        // No SA_RESTART, so that a blocking wait returns early with EINTR.
+        let mut action: libc::sigaction = std::mem::zeroed();
+        action.sa_sigaction = on_interrupt as *const () as libc::sighandler_t;
+        libc::sigemptyset(&mut action.sa_mask);
+        libc::sigaction(libc::SIGINT, &action, std::ptr::null_mut());
+
+        let pid = libc::getpid();
+        // Fails harmlessly when we already lead a session.
+        libc::setpgid(pid, pid);
+        libc::tcsetpgrp(libc::STDIN_FILENO, libc::getpgrp());
+
+        Some(libc::getpgrp())
+    }
+}
+
+/// Restores the default handling of the signals the shell ignores.
+fn reset_signals() {
+    for signal in JOB_CONTROL_SIGNALS {
+        unsafe { libc::signal(signal, libc::SIG_DFL) };
+    }
+}
+
+pub fn terminal_modes() -> Option<libc::termios> {
+    let mut modes = unsafe { std::mem::zeroed() };
+    (unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut modes) } == 0).then_some(modes)
+}
+
+pub fn set_terminal_modes(modes: &libc::termios) {
+    unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSADRAIN, modes) };
+}
+
+/// Makes `pgid` the foreground process group of the terminal.
+pub fn give_terminal(pgid: i32) {
+    unsafe { libc::tcsetpgrp(libc::STDIN_FILENO, pgid) };
+}
+
+/// Sets up `cmd` to join the process group `pgid`, or to start a new one
+/// when there is none yet, taking the terminal if it runs in the foreground.
+pub fn join_process_group(cmd: &mut Command, pgid: Option<i32>, foreground: bool) {
+    let pgid = pgid.unwrap_or(0);
+
+    // SAFETY: only async-signal-safe calls are made between fork and exec.
+    unsafe {
+        cmd.pre_exec(move || {
+            libc::setpgid(0, pgid);
+            if foreground {
+                // Done before SIGTTOU is reset, as we are not in the
+                // foreground yet.
+                libc::tcsetpgrp(libc::STDIN_FILENO, libc::getpgrp());
+            }
+            reset_signals();
+            Ok(())
+        })
+    };
+}
+
+/// Spawns `cmd` set up by [`join_process_group`]. A first process that
+/// fails to start has already taken the terminal for its group, so the
+/// shell takes it back.
+pub fn spawn_in_group(cmd: &mut Command, pgid: Option<i32>, ctx: &ShellCtx) -> io::Result<Child> {
+    let child = cmd.spawn();
+    if let (Err(_), None, Some(shell_pgid)) = (&child, pgid, ctx.job_control()) {
+        give_terminal(shell_pgid);
+    }
+    child
+}
+
+/// Waits for `job` in the foreground, handing it the terminal until it
+/// finishes or stops. A stopped job moves to the job table. Returns the
+/// status to report in `$?`.
+pub fn wait_foreground(ctx: &mut ShellCtx, mut job: Job) -> i32 {
+    let Some(shell_pgid) = ctx.job_control() else {
+        job.wait(false);
+        return match job.state {
+            JobState::Done(status) => status,
+            _ => 0,
+        };
+    };
+
+    let shell_modes = terminal_modes();
+    if let Some(modes) = job.modes.take() {
+        set_terminal_modes(&modes);
+    }
+    give_terminal(job.pgid);
+
+    if job.state == JobState::Stopped {
+        job.resume();
+    }
+    job.wait(true);
+
+    let status = match job.state {
+        JobState::Done(status) => {
+            if status == 128 + libc::SIGINT {
+                // Move past the ^C the terminal echoed.
+                eprintln!();
+                INTERRUPTED.store(true, Ordering::SeqCst);
+            }
+            status
+        }
+        _ => {
+            job.modes = terminal_modes();
+            128 + libc::SIGTSTP
+        }
+    };
+
+    give_terminal(shell_pgid);
+    if let Some(modes) = shell_modes {
+        set_terminal_modes(&modes);
+    }
+
+    if job.state == JobState::Stopped {
+        let id = ctx.jobs_mut().add(job);
+        let job = ctx.jobs().get(id).expect("job was just added");
+        eprintln!("\n{}", ctx.jobs().format(job, false));
+    }
+
+    status
+}
+
+/// Converts a raw `waitpid` status, reporting death by signal N as 128+N.
+pub fn wait_status_code(wstatus: i32) -> i32 {
+    if libc::WIFSIGNALED(wstatus) {
+        128 + libc::WTERMSIG(wstatus)
+    } else {
+        libc::WEXITSTATUS(wstatus)
+    }
+}
+
+/// Forks a subshell in its own process group that runs `f` on a copy of the
+/// shell state and exits with the resulting status. Returns the child's pid.
+pub fn fork_subshell(
+    ctx: &mut ShellCtx,
+    f: impl FnOnce(&mut ShellCtx) -> Result<ExecResult>,
+) -> Result<i32> {
+    io::stdout().flush()?;
+    io::stderr().flush()?;
+
+    match unsafe { libc::fork() } {
+        -1 => Err(io::Error::last_os_error().into()),
+        0 => {
+            unsafe { libc::setpgid(0, 0) };
+            run_subshell(ctx, f)
+        }
+        pid => {
+            // Also set from the parent so that the group exists before we
+            // signal it, whichever process gets scheduled first.
+            unsafe { libc::setpgid(pid, pid) };
+            Ok(pid)
+        }
+    }
+}
+
+/// Forks a subshell for a pipeline stage that runs `f` with the descriptors
+/// in `fds`, joining the process group `pgid` or starting a new one.
+pub fn fork_stage(
+    ctx: &mut ShellCtx,
+    fds: &FdTable,
+    pgid: Option<i32>,
+    f: impl FnOnce(&mut ShellCtx) -> Result<ExecResult>,
+) -> Result<i32> {
+    io::stdout().flush()?;
+    io::stderr().flush()?;
+    let job_control = ctx.job_control().is_some();
+
+    match unsafe { libc::fork() } {
+        -1 => Err(io::Error::last_os_error().into()),
+        0 => {
+            if job_control {
+                unsafe {
+                    libc::setpgid(0, pgid.unwrap_or(0));
+                    libc::tcsetpgrp(libc::STDIN_FILENO, libc::getpgrp());
+                }
+            }
+            if let Err(e) = fds.move_into_place() {
+                eprintln!("{e}");
+                std::process::exit(1);
+            }
+            run_subshell(ctx, f)
+        }
+        pid => {
+            if job_control {
+                unsafe { libc::setpgid(pid, pgid.unwrap_or(pid)) };
+                give_terminal(pgid.unwrap_or(pid));
+            }
+            Ok(pid)
+        }
+    }
+}
+
+/// Runs `f` in a subshell whose stdout is captured, as for `$(...)`, with
+/// `command` as its text should it stop and become a job. Returns what it
+/// printed and its exit status.
+pub fn capture_output(
+    ctx: &mut ShellCtx,
+    command: &str,
+    f: impl FnOnce(&mut ShellCtx) -> Result<ExecResult>,
+) -> Result<(String, i32)> {
+    io::stdout().flush()?;
+    io::stderr().flush()?;
+    let (mut reader, writer) = io::pipe()?;
+    let job_control = ctx.job_control().is_some();
+
+    let pid = match unsafe { libc::fork() } {
+        -1 => return Err(io::Error::last_os_error().into()),
+        0 => {
+            if job_control {
+                unsafe { libc::setpgid(0, 0) };
+            }
+            drop(reader);
+            unsafe { libc::dup2(writer.as_raw_fd(), libc::STDOUT_FILENO) };
+            drop(writer);
+            run_subshell(ctx, f)
+        }
+        pid => pid,
+    };
+    drop(writer);
+    if job_control {
+        unsafe { libc::setpgid(pid, pid) };
+    }
+
+    // Read on a thread of its own, as a child that stops still holds the
+    // pipe open and would keep the shell waiting for the end of it.
+    let output = thread::spawn(move || {
+        let mut output = Vec::new();
+        reader.read_to_end(&mut output).map(|_| output)
+    });
+    let status = wait_foreground(ctx, Job::new(pid, vec![pid], command.to_string()));
+
+    if ctx.jobs().find_by_pid(pid).is_some() {
+        return Err(ShellError::Stopped.into());
+    }
+    if status == 128 + libc::SIGINT && interrupted() {
+        return Err(ShellError::Interrupted.into());
+    }
+    let output = output.join().expect("the reader thread doesn't panic")?;
+    Ok((String::from_utf8_lossy(&output).into_owned(), status))
+}
+
+/// The child side of a fork: runs `f` and exits with its status.
+fn run_subshell(ctx: &mut ShellCtx, f: impl FnOnce(&mut ShellCtx) -> Result<ExecResult>) -> ! {
+    reset_signals();
+    ctx.jobs_mut().inherit();
+    ctx.set_job_control(None);
+    ctx.set_interactive(false);
+
+    let status = match f(ctx) {
+        Ok(ExecResult::Exit(status)) => status,
+        Ok(_) => ctx.last_status(),
+        Err(e) => {
+            eprintln!("{}", ctx.error_message(&e));
+            crate::error_status(&e)
+        }
+    };
+
+    let _ = io::stdout().flush();
+    std::process::exit(status);
+}
@@ -1,37 +1,423 @@
+use anyhow::Result;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    env,
+    ffi::OsStr,
+    fmt,
+    fs::{self, File},
+    io::{self, Seek, Write},
+    os::{
+        fd::{AsFd, AsRawFd, BorrowedFd, FromRawFd, OwnedFd},
+        unix::{ffi::OsStrExt, process::CommandExt},
+    },
+    process::{Command, Stdio},
+    sync::Arc,
+};
+
+use super::{ShellCtx, ShellError, Word, error::redirect_error, expansion};
+
 #[derive(Debug, PartialEq, Clone)]
 pub enum Redirect {
-    StdOut(bool),
-    StdErr(bool),
+    /// `[n]>` or `[n]>>`
+    Output { fd: i32, append: bool },
+    /// `&>` or `&>>`, sending both stdout and stderr to the file.
+    OutputAll { append: bool },
+    /// `[n]<`
+    Input { fd: i32 },
+    /// `[n]>&m` or `[n]<&m`, where a target of `-` closes `n` instead.
+    Duplicate { fd: i32, output: bool },
+    /// `[n]<<< word`, where the target is the text itself.
+    HereString { fd: i32 },
+    /// `[n]<<EOF` or `[n]<<-EOF`, where the target is the body. Leading tabs
+    /// are already stripped from the body when parsing `<<-`.
+    HereDoc {
+        fd: i32,
+        delimiter: String,
+        strip_tabs: bool,
+    },
+}
+
+impl Redirect {
+    /// The descriptor the redirect applies to when none is given.
+    fn default_fd(&self) -> i32 {
+        match self {
+            Redirect::Output { .. }
+            | Redirect::OutputAll { .. }
+            | Redirect::Duplicate { output: true, .. } => 1,
+            _ => 0,
+        }
+    }
+
+    fn fd(&self) -> Option<i32> {
+        match self {
+            Redirect::Output { fd, .. }
+            | Redirect::Input { fd }
+            | Redirect::Duplicate { fd, .. }
+            | Redirect::HereString { fd }
+            | Redirect::HereDoc { fd, .. } => Some(*fd),
+            Redirect::OutputAll { .. } => None,
+        }
+    }
+}
+
+impl fmt::Display for Redirect {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(fd) = self.fd()
+            && fd != self.default_fd()
+        {
+            write!(f, "{fd}")?;
+        }
+
+        match self {
+            Redirect::Output { append: false, .. } => write!(f, ">"),
+            Redirect::Output { append: true, .. } => write!(f, ">>"),
+            Redirect::OutputAll { append: false } => write!(f, "&>"),
+            Redirect::OutputAll { append: true } => write!(f, "&>>"),
+            Redirect::Input { .. } => write!(f, "<"),
+            Redirect::Duplicate { output: true, .. } => write!(f, ">&"),
+            Redirect::Duplicate { output: false, .. } => write!(f, "<&"),
+            Redirect::HereString { .. } => write!(f, "<<<"),
+            Redirect::HereDoc {
+                delimiter,
+                strip_tabs,
+                ..
+            } => match strip_tabs {
+                true => write!(f, "<<-{delimiter}"),
+                false => write!(f, "<<{delimiter}"),
+            },
+        }
+    }
 }
 
+/// A redirect as parsed, with an unexpanded target word, or as resolved
+/// right before the command runs.
 #[derive(Debug, PartialEq, Clone)]
-pub struct Redirection<'a> {
+pub struct Redirection<T = Word> {
     pub redirect: Redirect,
-    pub path: &'a str,
+    pub target: T,
 }
 
-impl<'a> Redirection<'a> {
-    pub fn new(redirection: Redirect, path: &'a str) -> Redirection<'a> {
+impl<T> Redirection<T> {
+    pub fn new(redirection: Redirect, target: T) -> Redirection<T> {
         Redirection {
             redirect: redirection,
-            path,
+            target,
         }
     }
 }
 
-pub fn eval_redirect(redirect: &str) -> Redirect {
-    let mut redirect = redirect.chars();
-    let first = redirect
-        .next()
-        .expect("Redirects should always have minimum one char");
-    let count = redirect.count();
+impl Redirection {
+    /// Resolves the target. For here-strings and here-docs that is the text
+    /// fed to the command rather than a path.
+    pub fn expand(&self, ctx: &mut ShellCtx) -> Result<Redirection<String>> {
+        let target = match self.redirect {
+            Redirect::HereString { .. } => {
+                let text = expansion::expand_word(&self.target, ctx)?.unwrap_or_default();
+                format!("{text}\n")
+            }
+            Redirect::HereDoc { .. } => {
+                expansion::expand_word(&self.target, ctx)?.unwrap_or_default()
+            }
+            _ => expansion::expand_single(&self.target, ctx)?,
+        };
 
-    let append = (first == '>' && count == 1) || count == 2;
+        Ok(Redirection::new(self.redirect.clone(), target))
+    }
+}
+
+/// The file descriptors a command runs with. Redirects are applied to the
+/// table in order, so `> log 2>&1` and `2>&1 > log` differ as they should.
+///
+/// Every descriptor the table holds is numbered 10 or above, and above any
+/// it sets up such as the 12 of `12>&1`, so that moving them into place in
+/// a child never overwrites one that is still to be moved.
+#[derive(Debug)]
+pub struct FdTable {
+    fds: BTreeMap<i32, Arc<OwnedFd>>,
+}
 
-    if first == '2' {
-        return Redirect::StdErr(append);
+impl FdTable {
+    /// Starts from the shell's own descriptors, with stdin and stdout
+    /// replaced by pipes inside a pipeline.
+    pub fn new(stdin: Option<OwnedFd>, stdout: Option<OwnedFd>) -> Result<FdTable> {
+        let stdin = match stdin {
+            Some(fd) => fd,
+            None => io::stdin().as_fd().try_clone_to_owned()?,
+        };
+        let stdout = match stdout {
+            Some(fd) => fd,
+            None => io::stdout().as_fd().try_clone_to_owned()?,
+        };
+        let stderr = io::stderr().as_fd().try_clone_to_owned()?;
+
+        let mut table = FdTable {
+            fds: BTreeMap::new(),
+        };
+        table.set(0, stdin)?;
+        table.set(1, stdout)?;
+        table.set(2, stderr)?;
+
+        Ok(table)
+    }
+
+    fn set(&mut self, fd: i32, file: impl Into<OwnedFd>) -> Result<()> {
+        self.insert(fd, Arc::new(file.into()))
+    }
+
+    fn insert(&mut self, fd: i32, file: Arc<OwnedFd>) -> Result<()> {
+        self.fds.insert(fd, file);
+        let floor = self.floor();
+        for file in self.fds.values_mut() {
+            if file.as_raw_fd() < floor {
+                *file = Arc::new(dup_above(file.as_fd(), floor)?);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The lowest number the table's own descriptors may have.
+    fn floor(&self) -> i32 {
+        self.fds.keys().max().map_or(10, |fd| (fd + 1).max(10))
+    }
+
+    pub fn apply(&mut self, redirects: &[Redirection<String>]) -> Result<()> {
+        for Redirection { redirect, target } in redirects {
+            match redirect {
+                Redirect::Output { fd, append } => {
+                    self.set(*fd, create_file(target, *append)?)?;
+                }
+                Redirect::OutputAll { append } => {
+                    self.set(1, create_file(target, *append)?)?;
+                    self.fds.insert(2, self.fds[&1].clone());
+                }
+                Redirect::Input { fd } => {
+                    let file = File::open(target).map_err(|e| redirect_error(target, e))?;
+                    self.set(*fd, file)?;
+                }
+                Redirect::Duplicate { fd, output } => {
+                    if target == "-" {
+                        self.fds.remove(fd);
+                        continue;
+                    }
+
+                    match target.parse::<i32>() {
+                        Ok(source) => {
+                            let bad_fd = || {
+                                ShellError::Redirect(
+                                    source.to_string(),
+                                    "Bad file descriptor".to_string(),
+                                )
+                            };
+                            let file = match self.fds.get(&source) {
+                                Some(file) => file.clone(),
+                                // One the shell has open itself, such as the
+                                // 3 of `{ echo hi >&3; } 3>&1` in the group.
+                                None if source >= 0 => {
+                                    let shell_fd = unsafe { BorrowedFd::borrow_raw(source) };
+                                    let file = dup_above(shell_fd, self.floor());
+                                    Arc::new(file.map_err(|_| bad_fd())?)
+                                }
+                                None => return Err(bad_fd().into()),
+                            };
+                            self.insert(*fd, file)?;
+                        }
+                        // `>& file` is an older spelling of `&> file`.
+                        Err(_) if *output && *fd == 1 => {
+                            self.set(1, create_file(target, false)?)?;
+                            self.fds.insert(2, self.fds[&1].clone());
+                        }
+                        Err(_) => {
+                            return Err(ShellError::Redirect(
+                                target.clone(),
+                                "ambiguous redirect".to_string(),
+                            )
+                            .into());
+                        }
+                    }
+                }
+                Redirect::HereString { fd } | Redirect::HereDoc { fd, .. } => {
+                    self.set(*fd, here_doc_file(target)?)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Opens `fd` for reading or writing from within the shell.
+    pub fn file(&self, fd: i32) -> io::Result<File> {
+        match self.fds.get(&fd) {
+            Some(file) => Ok(File::from(file.try_clone()?)),
+            None => Err(io::Error::from_raw_os_error(libc::EBADF)),
+        }
+    }
+
+    /// Moves the descriptors into place in this process, closing the
+    /// standard ones the table leaves out.
+    pub fn move_into_place(&self) -> io::Result<()> {
+        for (fd, file) in &self.fds {
+            if unsafe { libc::dup2(file.as_raw_fd(), *fd) } == -1 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        for fd in (0..=2).filter(|fd| !self.fds.contains_key(fd)) {
+            unsafe { libc::close(fd) };
+        }
+
+        Ok(())
+    }
+
+    /// Runs `f` in the shell itself with the descriptors in place, as for
+    /// `while read line; do ...; done < file`, then puts the shell's own
+    /// ones back.
+    pub fn install<T>(&self, f: impl FnOnce() -> T) -> Result<T> {
+        io::stdout().flush()?;
+        io::stderr().flush()?;
+
+        let fds: BTreeSet<i32> = (0..=2).chain(self.fds.keys().copied()).collect();
+        // A descriptor that was not open has no copy, and is closed again.
+        let saved: Vec<(i32, Option<OwnedFd>)> = fds
+            .into_iter()
+            .map(|fd| {
+                let saved = dup_above(unsafe { BorrowedFd::borrow_raw(fd) }, self.floor());
+                (fd, saved.ok())
+            })
+            .collect();
+
+        self.move_into_place()?;
+        let res = f();
+        let _ = io::stdout().flush();
+        let _ = io::stderr().flush();
+
+        for (fd, saved) in saved {
+            match saved {
+                Some(file) => unsafe { libc::dup2(file.as_raw_fd(), fd) },
+                None => unsafe { libc::close(fd) },
+            };
+        }
+
+        Ok(res)
+    }
+
+    /// Sets up `cmd` to run with the descriptors in the table.
+    pub fn configure(&self, cmd: &mut Command) -> Result<()> {
+        if let Some(file) = self.fds.get(&0) {
+            cmd.stdin(Stdio::from(file.try_clone()?));
+        }
+        if let Some(file) = self.fds.get(&1) {
+            cmd.stdout(Stdio::from(file.try_clone()?));
+        }
+        if let Some(file) = self.fds.get(&2) {
+            cmd.stderr(Stdio::from(file.try_clone()?));
+        }
+
+        let extra: Vec<(i32, Arc<OwnedFd>)> = self
+            .fds
+            .iter()
+            .filter(|(fd, _)| **fd > 2)
+            .map(|(fd, file)| (*fd, file.clone()))
+            .collect();
+        let closed: Vec<i32> = (0..=2).filter(|fd| !self.fds.contains_key(fd)).collect();
+        if extra.is_empty() && closed.is_empty() {
+            return Ok(());
+        }
+
+        // SAFETY: only async-signal-safe calls are made between fork and exec.
+        unsafe {
+            cmd.pre_exec(move || {
+                for (fd, file) in &extra {
+                    if libc::dup2(file.as_raw_fd(), *fd) == -1 {
+                        return Err(io::Error::last_os_error());
+                    }
+                }
+                for fd in &closed {
+                    libc::close(*fd);
+                }
+                Ok(())
+            })
+        };
+
+        Ok(())
+    }
+}
+
+/// Duplicates `fd` to a close-on-exec descriptor numbered `floor` or above.
+fn dup_above(fd: BorrowedFd, floor: i32) -> io::Result<OwnedFd> {
+    match unsafe { libc::fcntl(fd.as_raw_fd(), libc::F_DUPFD_CLOEXEC, floor) } {
+        -1 => Err(io::Error::last_os_error()),
+        fd => Ok(unsafe { OwnedFd::from_raw_fd(fd) }),
+    }
+}
+
+fn create_file(path: &str, append: bool) -> Result<File, ShellError> {
+    File::options()
+        .create(true)
+        .write(true)
+        .append(append)
+        .truncate(!append)
+        .open(path)
+        .map_err(|e| redirect_error(path, e))
+}
+
+/// Holds the text of a here-doc or here-string in an unlinked temporary
+/// file, as bash does. Unlike a pipe, it never needs a writer kept open
+/// for as long as the command reads, which forked stages would inherit.
+fn here_doc_file(text: &str) -> io::Result<File> {
+    let mut template = env::temp_dir()
+        .join("sh-thd-XXXXXX")
+        .into_os_string()
+        .into_encoded_bytes();
+    template.push(0);
+    let fd = unsafe { libc::mkostemp(template.as_mut_ptr().cast(), libc::O_CLOEXEC) };
+    if fd == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    let mut file = unsafe { File::from_raw_fd(fd) };
+    template.pop();
+    fs::remove_file(OsStr::from_bytes(&template))?;
+
+    file.write_all(text.as_bytes())?;
+    file.rewind()?;
+    Ok(file)
+}
+
+/// Parses a redirect operator such as `2>>`, `<&` or `&>`.
+pub fn eval_redirect(redirect: &str) -> Redirect {
+    let digits = redirect
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(redirect.len());
+    let fd = redirect[..digits].parse::<i32>().ok();
+    let output = fd.unwrap_or(1);
+    let input = fd.unwrap_or(0);
+
+    match &redirect[digits..] {
+        ">>" => Redirect::Output {
+            fd: output,
+            append: true,
+        },
+        "&>" => Redirect::OutputAll { append: false },
+        "&>>" => Redirect::OutputAll { append: true },
+        ">&" => Redirect::Duplicate {
+            fd: output,
+            output: true,
+        },
+        "<&" => Redirect::Duplicate {
+            fd: input,
+            output: false,
+        },
+        "<" => Redirect::Input { fd: input },
+        "<<<" => Redirect::HereString { fd: input },
+        op @ ("<<" | "<<-") => Redirect::HereDoc {
+            fd: input,
+            delimiter: String::new(),
+            strip_tabs: op == "<<-",
+        },
+        _ => Redirect::Output {
+            fd: output,
+            append: false,
+        },
     }
-    Redirect::StdOut(append)
 }
 
 #[cfg(test)]
@@ -40,11 +426,41 @@ mod tests {
 
     #[test]
     fn redirection() {
-        assert_eq!(eval_redirect("1>"), Redirect::StdOut(false));
-        assert_eq!(eval_redirect("1>>"), Redirect::StdOut(true));
-        assert_eq!(eval_redirect("2>"), Redirect::StdErr(false));
-        assert_eq!(eval_redirect(">"), Redirect::StdOut(false));
-        assert_eq!(eval_redirect(">>"), Redirect::StdOut(true));
-        assert_eq!(eval_redirect("2>>"), Redirect::StdErr(true));
+        let output = |fd, append| Redirect::Output { fd, append };
+
+        assert_eq!(eval_redirect("1>"), output(1, false));
+        assert_eq!(eval_redirect("1>>"), output(1, true));
+        assert_eq!(eval_redirect("2>"), output(2, false));
+        assert_eq!(eval_redirect(">"), output(1, false));
+        assert_eq!(eval_redirect(">>"), output(1, true));
+        assert_eq!(eval_redirect("2>>"), output(2, true));
+        assert_eq!(eval_redirect("10>"), output(10, false));
+        assert_eq!(eval_redirect("<"), Redirect::Input { fd: 0 });
+        assert_eq!(eval_redirect("3<"), Redirect::Input { fd: 3 });
+        assert_eq!(eval_redirect("<<<"), Redirect::HereString { fd: 0 });
+        assert_eq!(
+            eval_redirect("2>&"),
+            Redirect::Duplicate {
+                fd: 2,
+                output: true
+            }
+        );
+        assert_eq!(
+            eval_redirect("<&"),
+            Redirect::Duplicate {
+                fd: 0,
+                output: false
+            }
+        );
+        assert_eq!(eval_redirect("&>>"), Redirect::OutputAll { append: true });
+    }
+
+    #[test]
+    fn display() {
+        assert_eq!(eval_redirect("1>").to_string(), ">");
+        assert_eq!(eval_redirect("2>>").to_string(), "2>>");
+        assert_eq!(eval_redirect("0<").to_string(), "<");
+        assert_eq!(eval_redirect("2>&").to_string(), "2>&");
+        assert_eq!(eval_redirect("&>").to_string(), "&>");
     }
 }
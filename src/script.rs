@@ -0,0 +1,52 @@
+use super::{ExecResult, ShellCtx, ShellError, error_status};
+
+/// Runs the lines of a script, a `-c` string or piped input, one complete
+/// command at a time so that each sees the effects of the ones before it.
+/// Stops at `exit`, `return` or a syntax error, which has status 2. Errors
+/// are reported with `name` and the line they happened on.
+pub fn run_script(text: &str, name: &str, ctx: &mut ShellCtx) -> ExecResult {
+    ctx.in_script(name, |ctx| run_lines(text, ctx))
+}
+
+fn run_lines(text: &str, ctx: &mut ShellCtx) -> ExecResult {
+    let mut pending = String::new();
+
+    for (i, line) in text.lines().enumerate() {
+        if pending.is_empty() {
+            ctx.set_script_line(i + 1);
+        } else {
+            pending.push('\n');
+        }
+        pending.push_str(line);
+
+        let parsed = match crate::parse(&pending) {
+            Ok(parsed) => parsed,
+            Err(e) if matches!(e.downcast_ref(), Some(ShellError::Incomplete)) => continue,
+            Err(e) => {
+                ctx.set_script_line(i + 1);
+                eprintln!("{}", ctx.error_message(&e));
+                ctx.set_last_status(error_status(&e));
+                return ExecResult::Status(ctx.last_status());
+            }
+        };
+        pending.clear();
+
+        match crate::execute(&parsed, ctx) {
+            Ok(ExecResult::Exit(status)) => return ExecResult::Exit(status),
+            Ok(ExecResult::Return(status)) => return ExecResult::Status(status),
+            Ok(_) => {}
+            Err(e) => {
+                eprintln!("{}", ctx.error_message(&e));
+                ctx.set_last_status(error_status(&e));
+            }
+        }
+    }
+
+    if !pending.is_empty() {
+        ctx.set_script_line(text.lines().count());
+        eprintln!("{}", ctx.error_message(&ShellError::Incomplete));
+        ctx.set_last_status(ShellError::Incomplete.status());
+    }
+
+    ExecResult::Status(ctx.last_status())
+}
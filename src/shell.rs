@@ -1,35 +1,99 @@
-use std::io::{self, Read, Write};
+mod complete;
+mod line;
+mod vi;
+
+use std::{
+    collections::VecDeque,
+    io::{self, Read, Write},
+};
 
 use anyhow::Result;
-use codecrafters_shell::{Builtins, ShellCtx, ShellError};
-use termion::{event::Key, input::TermRead};
+use codecrafters_shell::{
+    EditMode, ShellCtx, ShellError,
+    keymap::{self, Action, Binding, Lookup},
+};
+use line::{Line, alnum_word_end, alnum_word_start};
+use termion::{event::Key, input::TermRead, style};
+use vi::{Vi, ViAction};
+
+/// What the shell should do after an editing action.
+enum Flow {
+    Continue,
+    /// The line is done and should be run.
+    Accept(String),
+    /// End of input, as from Ctrl-D on an empty line.
+    Eof,
+}
+
+/// An incremental history search, started with Ctrl-R or Ctrl-S.
+struct Search {
+    query: String,
+    reverse: bool,
+    /// The matching history entry and where in it the match starts.
+    found: Option<(usize, usize)>,
+    failed: bool,
+    /// Started with `/` in vi mode, where Enter takes the match without
+    /// running it.
+    vi: bool,
+    /// The line and cursor from before the search, put back by Ctrl-G.
+    saved: (Vec<char>, usize),
+}
 
 pub struct Shell {
     pub ctx: ShellCtx,
-    buffer: Vec<char>,
-    cursor: usize,
-    last_event: Option<Key>,
+    line: Line,
+    vi: Vi,
+    last_action: Option<Action>,
+    /// The keys of a bound sequence typed so far.
+    pending: Vec<Key>,
+    /// Keys from a macro, read before any more are typed.
+    queued: VecDeque<Key>,
+    /// Whether the key being handled came from a macro, whose keys run
+    /// actions but not other macros, which could expand forever.
+    from_macro: bool,
     hist_pos: usize,
+    search: Option<Search>,
+    /// What the last search looked for, which Ctrl-R on an empty query
+    /// looks for again.
+    last_search: String,
+    last_search_reverse: bool,
 }
 
 impl Shell {
     pub fn build() -> Result<Shell> {
-        let ctx = ShellCtx::build()?;
+        Ok(Shell::new(ShellCtx::build()?))
+    }
 
-        Ok(Shell {
+    fn new(ctx: ShellCtx) -> Shell {
+        Shell {
             ctx,
-            buffer: Vec::new(),
-            cursor: 0,
-            last_event: None,
+            line: Line::default(),
+            vi: Vi::default(),
+            last_action: None,
+            pending: Vec::new(),
+            queued: VecDeque::new(),
+            from_macro: false,
             hist_pos: 0,
-        })
+            search: None,
+            last_search: String::new(),
+            last_search_reverse: true,
+        }
     }
 
     pub fn redraw<W: io::Write>(&self, out: &mut W, prompt: &str) {
-        let full: String = self.buffer.iter().collect();
+        if let Some(search) = &self.search {
+            self.redraw_search(out, search);
+            return;
+        }
+
+        let full = self.line.text();
+        let prompt = match self.ctx.edit_mode() {
+            EditMode::Vi => &format!("{}{prompt}", self.vi.indicator()),
+            EditMode::Emacs => prompt,
+        };
 
         let prompt_len = prompt.chars().count();
-        let cursor_col = (prompt_len + self.cursor) as u16;
+        let cursor_col = (prompt_len + self.line.cursor()) as u16;
 
         write!(
             out,
@@ -44,194 +108,708 @@ impl Shell {
         out.flush().unwrap();
     }
 
-    fn current_buffer(&self) -> String {
-        self.buffer.iter().collect()
+    /// Shows the search in place of the prompt, followed by the matching
+    /// entry with the match highlighted and the cursor on it.
+    fn redraw_search<W: io::Write>(&self, out: &mut W, search: &Search) {
+        let label = match search.vi {
+            true => format!(
+                "{}{}: ",
+                if search.reverse { '/' } else { '?' },
+                search.query
+            ),
+            false => format!(
+                "({}{}i-search)`{}': ",
+                if search.failed { "failed " } else { "" },
+                if search.reverse { "reverse-" } else { "" },
+                search.query
+            ),
+        };
+        let (line, offset): (Vec<char>, usize) = match search.found {
+            Some((idx, offset)) => (self.ctx.get_history()[idx].chars().collect(), offset),
+            None => (search.saved.0.clone(), search.saved.1),
+        };
+        let end = match search.found {
+            Some(_) => (offset + search.query.chars().count()).min(line.len()),
+            None => offset,
+        };
+
+        let before: String = line[..offset].iter().collect();
+        let matched: String = line[offset..end].iter().collect();
+        let after: String = line[end..].iter().collect();
+        let cursor_col = (label.chars().count() + offset) as u16;
+
+        write!(
+            out,
+            "\r{label}{before}{}{matched}{}{after}{}\r{}",
+            style::Invert,
+            style::Reset,
+            termion::clear::AfterCursor,
+            termion::cursor::Right(cursor_col)
+        )
+        .unwrap();
+
+        out.flush().unwrap();
     }
 
-    pub fn run<W, R>(&mut self, stdin: R, out: &mut W, prompt: &str) -> Result<String>
-    where
-        W: Write,
-        R: Read,
-    {
-        for key in stdin.keys() {
-            let key = key.unwrap();
-
-            match key {
-                Key::Ctrl('c') => {
-                    write!(out, "\r\n").unwrap();
-                    self.ctx.shut_down()?;
-                    break;
-                }
+    /// Prints a line for each background job that finished or stopped since
+    /// the last prompt.
+    pub fn report_jobs<W: io::Write>(&mut self, out: &mut W) {
+        for line in self.ctx.reap_jobs() {
+            write!(out, "\r{line}\r\n").unwrap();
+        }
+    }
 
-                Key::Char('\n') | Key::Char('\r') => {
-                    self.last_event = None;
-                    let line = self.current_buffer();
-                    write!(out, "\r\n").unwrap();
+    /// Handles a key during a history search, returning whether it was
+    /// used up. Any other key ends the search with the match on the line
+    /// and is then handled as usual, so Enter runs it and Left edits it.
+    fn search_key(&mut self, key: &Key) -> bool {
+        let Some(search) = &mut self.search else {
+            return false;
+        };
+
+        match *key {
+            Key::Char('\n') if search.vi => self.end_search(),
+            Key::Esc if search.vi => self.cancel_search(),
+            Key::Char(ch) if !matches!(ch, '\n' | '\r' | '\t') => {
+                search.query.push(ch);
+                self.find_match(false);
+            }
+            Key::Backspace => {
+                search.query.pop();
+                search.found = None;
+                search.failed = false;
+                self.find_match(false);
+            }
+            Key::Ctrl('r') | Key::Ctrl('s') => {
+                search.reverse = *key == Key::Ctrl('r');
+                if search.query.is_empty() {
+                    search.query = self.last_search.clone();
+                    self.find_match(false);
+                } else {
+                    self.find_match(true);
+                }
+            }
+            Key::Ctrl('g') => self.cancel_search(),
+            Key::Esc => self.end_search(),
+            _ => {
+                self.end_search();
+                return false;
+            }
+        }
 
-                    self.buffer.clear();
-                    self.cursor = 0;
+        true
+    }
 
-                    self.ctx.handle_history(&line);
-                    self.hist_pos = 0;
+    /// Looks for the query from the current match on, or from the one
+    /// before or after it when `step` is set, as for a repeated Ctrl-R.
+    fn find_match(&mut self, step: bool) {
+        let Some(search) = &mut self.search else {
+            return;
+        };
+        if search.query.is_empty() {
+            return;
+        }
 
-                    return Ok(line);
+        let len = self.ctx.get_history().len();
+        let from = match (search.found, search.reverse) {
+            (Some((idx, _)), true) if step => idx.checked_sub(1),
+            (Some((idx, _)), false) if step => Some(idx + 1),
+            (Some((idx, _)), _) => Some(idx),
+            // From the entry Up and Down last showed, if any.
+            (None, true) => len.checked_sub(self.hist_pos + 1),
+            (None, false) => Some(len - self.hist_pos.min(len)),
+        };
+
+        let found =
+            from.and_then(|from| self.ctx.search_history(&search.query, from, search.reverse));
+        match found {
+            Some(idx) => {
+                let entry = &self.ctx.get_history()[idx];
+                let start = match search.reverse {
+                    true => entry.rfind(&search.query),
+                    false => entry.find(&search.query),
                 }
+                .expect("the entry was found to contain the query");
+                search.found = Some((idx, entry[..start].chars().count()));
+                search.failed = false;
+            }
+            None => search.failed = true,
+        }
+    }
 
-                Key::Char('\t') if self.last_event == Some(Key::Char('\t')) => {
-                    let partial = self.current_buffer();
+    /// Leaves the search with the match, if any, as the line being edited.
+    fn end_search(&mut self) {
+        let Some(search) = self.search.take() else {
+            return;
+        };
+
+        if let Some((idx, offset)) = search.found {
+            let len = self.ctx.get_history().len();
+            self.line
+                .set(self.ctx.get_history()[idx].chars().collect(), offset);
+            self.hist_pos = len - idx;
+        }
+        if !search.query.is_empty() {
+            self.last_search = search.query;
+            self.last_search_reverse = search.reverse;
+        }
+    }
 
-                    let possibilities = get_possibilities(&partial)?;
+    /// Shows the next older or newer history entry, or the line that was
+    /// being typed after the newest, returning whether there was one.
+    fn history(&mut self, older: bool) -> bool {
+        let pos = match older {
+            true if self.hist_pos < self.ctx.get_history().len() => self.hist_pos + 1,
+            false if self.hist_pos > 0 => self.hist_pos - 1,
+            _ => return false,
+        };
+        self.hist_pos = pos;
+
+        let entry = self.ctx.get_history_entry(pos, self.line.text());
+        self.line.set(entry.chars().collect(), usize::MAX);
+        true
+    }
 
-                    write!(out, "\n\r{}", possibilities.join("  ")).unwrap();
+    fn start_search(&mut self, reverse: bool, vi: bool) {
+        self.search = Some(Search {
+            query: String::new(),
+            reverse,
+            found: None,
+            failed: false,
+            vi,
+            saved: (self.line.chars().to_vec(), self.line.cursor()),
+        });
+    }
 
-                    writeln!(out).unwrap();
-                    self.redraw(out, prompt);
-                    self.last_event = None;
-                }
-                Key::Char('\t') => {
-                    let partial = self.current_buffer();
+    /// Looks for the last search again from the entry on the line, for `n`
+    /// and `N` in vi mode, which go the way the search did when `same` is set.
+    fn search_again(&mut self, same: bool) -> bool {
+        if self.last_search.is_empty() {
+            return false;
+        }
+        let reverse = self.last_search_reverse == same;
+        let len = self.ctx.get_history().len();
+        let from = match reverse {
+            true => len.checked_sub(self.hist_pos + 1),
+            false => (self.hist_pos > 1).then(|| len + 1 - self.hist_pos),
+        };
+        let Some(idx) =
+            from.and_then(|from| self.ctx.search_history(&self.last_search, from, reverse))
+        else {
+            return false;
+        };
+
+        self.hist_pos = len - idx;
+        self.line
+            .set(self.ctx.get_history()[idx].chars().collect(), 0);
+        true
+    }
 
-                    let possibilities = get_possibilities(&partial)?;
+    /// Leaves the search with the line as it was before it.
+    fn cancel_search(&mut self) {
+        let Some(search) = &mut self.search else {
+            return;
+        };
 
-                    if !possibilities.is_empty() {
-                        self.last_event = Some(Key::Char('\t'));
-                    }
+        search.found = None;
+        let (chars, cursor) = search.saved.clone();
+        self.line.set(chars, cursor);
+        self.end_search();
+    }
 
-                    let common_prefix = common_prefix_ascii(&possibilities);
-
-                    if possibilities.len() == 1 {
-                        self.buffer = possibilities.first().unwrap().trim().chars().collect();
-                        self.buffer.push(' ');
-                        self.cursor = self.buffer.len();
-                        self.last_event = None;
-                    } else if !common_prefix.is_empty() && common_prefix != self.current_buffer() {
-                        self.buffer = common_prefix.trim().chars().collect();
-                        self.cursor = self.buffer.len();
-                    } else {
-                        write!(out, "\x07").unwrap();
-                    }
+    /// Finds the next older or newer entry that starts with the text before
+    /// the cursor, leaving the cursor where it is.
+    fn history_prefix_search(&mut self, older: bool) -> bool {
+        let prefix: String = self.line.chars()[..self.line.cursor()].iter().collect();
+        let entries = self.ctx.get_history();
+        let len = entries.len();
+        let found = match older {
+            true => (0..len.saturating_sub(self.hist_pos))
+                .rev()
+                .find(|idx| entries[*idx].starts_with(&prefix)),
+            false => {
+                (len + 1 - self.hist_pos.max(1)..len).find(|idx| entries[*idx].starts_with(&prefix))
+            }
+        };
+        let Some(idx) = found else {
+            return false;
+        };
+
+        let cursor = self.line.cursor();
+        self.hist_pos = len - idx;
+        self.line.set(entries[idx].chars().collect(), cursor);
+        true
+    }
 
-                    self.redraw(out, prompt);
-                }
+    /// Completes the word before the cursor, or lists what it could be
+    /// completed to when `list` is set, returning whether a second press
+    /// should list.
+    fn complete<W: Write>(&mut self, list: bool, out: &mut W) -> Result<bool> {
+        let cursor = self.line.cursor();
+        let prefix: String = self.line.chars()[..cursor].iter().collect();
+        let Some(mut word) = complete::current_word(&prefix) else {
+            write!(out, "\x07").unwrap();
+            return Ok(false);
+        };
+
+        // What replaces the word up to the cursor, which starts out as just
+        // the cursor unless a leading `~` is to be expanded.
+        let (mut start, mut text) = (cursor, String::new());
+        if word.tilde
+            && let Some(home) = self.ctx.get_var("HOME")
+        {
+            start = word.start;
+            text = complete::escape(&home, None, true);
+            text.extend(&self.line.chars()[word.start + 1..cursor]);
+            word.value.replace_range(..1, &home);
+        }
 
-                Key::Char(ch) => {
-                    self.last_event = Some(Key::Char(ch));
-                    self.buffer.insert(self.cursor, ch);
-                    self.cursor += 1;
-                    self.redraw(out, prompt);
-                }
+        if list {
+            let possibilities = complete::candidates(&word, &self.ctx)?;
+            let dir = word.value.rfind('/').map_or(0, |idx| idx + 1);
+            let names: Vec<&str> = possibilities.iter().map(|path| &path[dir..]).collect();
+            write!(out, "\n\r{}", names.join("  ")).unwrap();
+            writeln!(out).unwrap();
+            return Ok(false);
+        }
 
-                Key::Left => {
-                    self.last_event = Some(Key::Left);
-                    self.cursor = self.cursor.saturating_sub(1);
-                    self.redraw(out, prompt);
-                }
-                Key::Right => {
-                    self.last_event = Some(Key::Right);
-                    self.cursor = (self.cursor + 1).min(self.buffer.len());
-                    self.redraw(out, prompt);
-                }
-                Key::Up => {
-                    self.last_event = Some(Key::Up);
-                    self.hist_pos += 1;
+        let possibilities = complete::candidates(&word, &self.ctx)?;
+        let common_prefix = complete::common_prefix(&possibilities);
+        let rest = common_prefix.get(word.value.len()..).unwrap_or_default();
+        text.push_str(&complete::escape(rest, word.quote, word.value.is_empty()));
+
+        let unique = possibilities.len() == 1;
+        if unique && !common_prefix.ends_with('/') {
+            text.extend(word.quote);
+            text.push(' ');
+        }
+        if !unique && rest.is_empty() {
+            write!(out, "\x07").unwrap();
+        }
+        if start < cursor || !text.is_empty() {
+            self.line.replace(start, cursor, &text);
+        }
 
-                    let entry = self
-                        .ctx
-                        .get_history_entry(self.hist_pos, self.current_buffer());
+        Ok(!unique && !possibilities.is_empty())
+    }
 
-                    self.buffer = entry.chars().collect();
-                    self.cursor = self.buffer.len();
+    /// Reads the inputrc file again, showing any problems with it.
+    fn reread_init_file<W: Write>(&mut self, out: &mut W) -> bool {
+        let Some(path) = self.ctx.keymap().init_file().map(str::to_string) else {
+            return false;
+        };
 
-                    self.redraw(out, prompt);
+        match keymap::read_init_file(&path, &mut self.ctx) {
+            Ok(errors) => {
+                for (line, msg) in errors {
+                    write!(out, "\r\n{path}: line {line}: {msg}").unwrap();
                 }
-                Key::Down => {
-                    self.last_event = Some(Key::Down);
-                    self.hist_pos = std::cmp::max(self.hist_pos - 1, 0);
-
-                    let entry = self
-                        .ctx
-                        .get_history_entry(self.hist_pos, self.current_buffer());
+            }
+            Err(e) => write!(out, "\r\n{path}: {e}").unwrap(),
+        }
+        write!(out, "\r\n").unwrap();
+        true
+    }
 
-                    self.buffer = entry.chars().collect();
-                    self.cursor = self.buffer.len();
+    /// Runs an editing action for `key`, ringing the bell when it has
+    /// nothing to act on.
+    fn perform<W: Write>(
+        &mut self,
+        action: Action,
+        key: Key,
+        out: &mut W,
+        prompt: &str,
+    ) -> Result<Flow> {
+        let last_action = self.last_action.replace(action);
+        let line = &mut self.line;
+
+        let ok = match action {
+            Action::AcceptLine => {
+                self.last_action = None;
+                let line = self.line.text();
+                write!(out, "\r\n").unwrap();
+
+                self.line.clear();
+                self.vi.new_line();
+
+                self.ctx.handle_history(&line);
+                self.hist_pos = 0;
+
+                return Ok(Flow::Accept(line));
+            }
+            // The terminal's end-of-file key ends the shell on an empty line.
+            Action::DeleteChar if key == Key::Ctrl('d') && line.is_empty() => {
+                write!(out, "\r\n").unwrap();
+                self.ctx.shut_down()?;
+                return Ok(Flow::Eof);
+            }
+            Action::Complete => {
+                let list = last_action == Some(Action::Complete);
+                if !self.complete(list, out)? {
+                    self.last_action = None;
+                }
+                true
+            }
 
-                    self.redraw(out, prompt);
+            Action::SelfInsert => match key {
+                Key::Char(ch) => {
+                    line.insert(ch);
+                    true
                 }
-                Key::Home => {
-                    self.last_event = Some(Key::Home);
-                    self.cursor = 0;
-                    self.redraw(out, prompt);
+                _ => false,
+            },
+            Action::BeginningOfLine => {
+                line.move_to(0);
+                true
+            }
+            Action::EndOfLine => {
+                line.move_to(line.len());
+                true
+            }
+            Action::BackwardChar => {
+                line.move_to(line.cursor().saturating_sub(1));
+                true
+            }
+            Action::ForwardChar => {
+                line.move_to(line.cursor() + 1);
+                true
+            }
+            Action::BackwardWord => {
+                line.move_to(alnum_word_start(line.chars(), line.cursor()));
+                true
+            }
+            Action::ForwardWord => {
+                line.move_to(alnum_word_end(line.chars(), line.cursor()));
+                true
+            }
+
+            Action::BackwardDeleteChar => line.backspace(),
+            Action::DeleteChar => line.delete_char(),
+            Action::TransposeChars => line.transpose(),
+            Action::KillLine => line.kill_to_end(),
+            Action::UnixLineDiscard => line.kill_to_start(),
+            Action::KillWord => line.kill_word_forward(),
+            Action::BackwardKillWord => line.kill_word_backward(),
+            Action::UnixWordRubout => line.unix_word_rubout(),
+            Action::Yank => line.yank(),
+            Action::YankPop => line.yank_pop(),
+            Action::Undo => line.undo(),
+
+            Action::PreviousHistory => self.history(true),
+            Action::NextHistory => self.history(false),
+            Action::HistorySearchBackward => self.history_prefix_search(true),
+            Action::HistorySearchForward => self.history_prefix_search(false),
+            Action::ReverseSearchHistory | Action::ForwardSearchHistory => {
+                self.start_search(action == Action::ReverseSearchHistory, false);
+                true
+            }
+
+            Action::ClearScreen => {
+                write!(
+                    out,
+                    "{}{}",
+                    termion::clear::All,
+                    termion::cursor::Goto(1, 1)
+                )
+                .unwrap();
+                true
+            }
+            Action::ReReadInitFile => self.reread_init_file(out),
+            Action::ViEditingMode | Action::EmacsEditingMode => {
+                let mode = match action {
+                    Action::ViEditingMode => EditMode::Vi,
+                    _ => EditMode::Emacs,
+                };
+                self.ctx.set_edit_mode(mode);
+                self.vi.new_line();
+                true
+            }
+            Action::Abort => false,
+        };
+
+        if !ok {
+            write!(out, "\x07").unwrap();
+        }
+        self.redraw(out, prompt);
+        Ok(Flow::Continue)
+    }
+
+    /// Runs what the keys typed so far are bound to, once they make up a
+    /// bound sequence.
+    fn dispatch<W: Write>(&mut self, key: Key, out: &mut W, prompt: &str) -> Result<Flow> {
+        self.pending.push(key);
+        let found = match self.ctx.keymap().lookup(&self.pending) {
+            Lookup::Prefix => return Ok(Flow::Continue),
+            Lookup::Found(Binding::Macro(_)) if self.from_macro => None,
+            Lookup::Found(binding) => Some(binding.clone()),
+            Lookup::None => None,
+        };
+        let binding = match found {
+            Some(binding) => binding,
+            // Unbound characters type themselves.
+            None if self.pending.len() == 1 && matches!(key, Key::Char(_)) => {
+                Binding::Action(Action::SelfInsert)
+            }
+            None if self.pending.len() == 1 => {
+                self.pending.clear();
+                return Ok(Flow::Continue);
+            }
+            None => Binding::Action(Action::Abort),
+        };
+        self.pending.clear();
+
+        match binding {
+            Binding::Action(action) => self.perform(action, key, out, prompt),
+            // A macro is typed as if its keys came next.
+            Binding::Macro(text) => {
+                let keys = keymap::parse_keys(text.as_bytes()).unwrap_or_default();
+                for key in keys.into_iter().rev() {
+                    self.queued.push_front(key);
                 }
-                Key::End => {
-                    self.last_event = Some(Key::End);
-                    self.cursor = self.buffer.len();
-                    self.redraw(out, prompt);
+                Ok(Flow::Continue)
+            }
+        }
+    }
+
+    pub fn run<W, R>(&mut self, stdin: R, out: &mut W, prompt: &str) -> Result<String>
+    where
+        W: Write,
+        R: Read,
+    {
+        let mut keys = stdin.keys();
+        loop {
+            self.from_macro = !self.queued.is_empty();
+            let key = match self.queued.pop_front() {
+                Some(key) => key,
+                None => match keys.next() {
+                    Some(key) => key.unwrap(),
+                    None => break,
+                },
+            };
+            self.line.next_key();
+
+            if self.search_key(&key) {
+                if self.search.as_ref().is_some_and(|search| search.failed) {
+                    write!(out, "\x07").unwrap();
                 }
+                self.redraw(out, prompt);
+                continue;
+            }
 
-                Key::Backspace => {
-                    self.last_event = Some(Key::Backspace);
-                    if self.cursor > 0 {
-                        self.cursor -= 1;
-                        self.buffer.remove(self.cursor);
-                        self.redraw(out, prompt);
+            // Ctrl-C abandons whatever is being typed, bound or not, along
+            // with any lines already read for the same command.
+            if key == Key::Ctrl('c') {
+                write!(out, "^C\r\n").unwrap();
+                self.last_action = None;
+                self.pending.clear();
+                self.queued.clear();
+                self.line.clear();
+                self.vi.new_line();
+                self.hist_pos = 0;
+
+                self.ctx.set_last_status(130);
+                return Err(ShellError::Interrupted.into());
+            }
+
+            if self.ctx.edit_mode() == EditMode::Vi && self.pending.is_empty() {
+                let ok = match self.vi.key(key, &mut self.line) {
+                    ViAction::Pass => None,
+                    ViAction::Redraw => Some(true),
+                    ViAction::Bell => Some(false),
+                    ViAction::History(older) => Some(self.history(older)),
+                    ViAction::Search(reverse) => {
+                        self.start_search(reverse, true);
+                        Some(true)
                     }
-                }
-                Key::Delete => {
-                    self.last_event = Some(Key::Delete);
-                    if self.cursor < self.buffer.len() {
-                        self.buffer.remove(self.cursor);
-                        self.redraw(out, prompt);
+                    ViAction::SearchAgain(same) => Some(self.search_again(same)),
+                };
+                if let Some(ok) = ok {
+                    self.last_action = None;
+                    if !ok {
+                        write!(out, "\x07").unwrap();
                     }
+                    self.redraw(out, prompt);
+                    continue;
                 }
+            }
 
-                _ => {}
+            match self.dispatch(key, out, prompt)? {
+                Flow::Continue => {}
+                Flow::Accept(line) => return Ok(line),
+                Flow::Eof => break,
             }
         }
-        return Err(ShellError::Eol.into());
+        Err(ShellError::Eol.into())
     }
 }
 
-fn get_possibilities(partial: &str) -> Result<Vec<String>> {
-    let mut possibilities: Vec<String> = Builtins::all_builtins()
-        .iter()
-        .filter(|cmd| cmd.starts_with(partial))
-        .map(|cmd| cmd.to_string())
-        .collect();
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    let externals = codecrafters_shell::search_executables(partial)?;
+    fn with_history(history: &[&str]) -> Shell {
+        let mut shell = Shell::new(ShellCtx::build_without_history());
+        for line in history {
+            shell.ctx.handle_history(line);
+        }
+        shell
+    }
 
-    possibilities.extend(externals.iter().cloned());
+    /// Sends keys to the search, as `run` does while one is going on.
+    fn send(shell: &mut Shell, keys: &str) {
+        for ch in keys.chars() {
+            let key = match ch {
+                'R' => Key::Ctrl('r'),
+                'S' => Key::Ctrl('s'),
+                'G' => Key::Ctrl('g'),
+                ch => Key::Char(ch),
+            };
+            assert!(shell.search_key(&key));
+        }
+    }
 
-    let mut possibilities: Vec<String> = possibilities
-        .iter()
-        .map(|cmd| cmd.trim().to_string())
-        .collect();
+    fn found(shell: &Shell) -> Option<&str> {
+        let (idx, _) = shell.search.as_ref()?.found?;
+        Some(&shell.ctx.get_history()[idx])
+    }
 
-    possibilities.sort();
-    possibilities.dedup();
+    fn failed(shell: &Shell) -> bool {
+        shell.search.as_ref().is_some_and(|search| search.failed)
+    }
 
-    Ok(possibilities)
-}
+    #[test]
+    fn reverse_search() {
+        let mut shell = with_history(&["echo one", "ls", "echo two"]);
+        shell.start_search(true, false);
+        send(&mut shell, "echo");
+        assert_eq!(found(&shell), Some("echo two"));
+        send(&mut shell, "R");
+        assert_eq!(found(&shell), Some("echo one"));
+        assert!(!failed(&shell));
+
+        // Nothing older matches, so the search fails but keeps its match.
+        send(&mut shell, "R");
+        assert!(failed(&shell));
+        assert_eq!(found(&shell), Some("echo one"));
+
+        assert!(shell.search_key(&Key::Esc));
+        assert!(shell.search.is_none());
+        assert_eq!(shell.line.text(), "echo one");
+    }
+
+    #[test]
+    fn failing_search() {
+        let mut shell = with_history(&["echo one", "ls"]);
+        shell.start_search(true, false);
+        send(&mut shell, "ls");
+        assert_eq!(found(&shell), Some("ls"));
+        send(&mut shell, "q");
+        assert!(failed(&shell));
+
+        // Taking the failing part back finds the match again.
+        assert!(shell.search_key(&Key::Backspace));
+        assert!(!failed(&shell));
+        assert_eq!(found(&shell), Some("ls"));
+    }
+
+    #[test]
+    fn forward_search() {
+        let mut shell = with_history(&["echo one", "ls", "echo two", "echo three"]);
+        shell.start_search(true, false);
+        send(&mut shell, "echoRR");
+        assert_eq!(found(&shell), Some("echo one"));
+        send(&mut shell, "S");
+        assert_eq!(found(&shell), Some("echo two"));
+
+        // Ctrl-S on its own starts from the entry Up went back to.
+        let mut shell = with_history(&["echo one", "ls", "echo two"]);
+        for _ in 0..3 {
+            shell.history(true);
+        }
+        shell.start_search(false, false);
+        send(&mut shell, "echo");
+        assert_eq!(found(&shell), Some("echo one"));
+        send(&mut shell, "S");
+        assert_eq!(found(&shell), Some("echo two"));
+        send(&mut shell, "S");
+        assert!(failed(&shell));
+    }
+
+    #[test]
+    fn complete_home() {
+        let home = std::env::temp_dir().join(format!("complete-home-{}", std::process::id()));
+        std::fs::create_dir_all(home.join("Documents")).unwrap();
+        std::fs::write(home.join("notes"), "").unwrap();
+
+        let mut shell = with_history(&[]);
+        let home_var = home.display().to_string();
+        shell.ctx.set_var("HOME", home_var.clone()).unwrap();
+        let mut completed = |line: &str| {
+            shell.line.set(line.chars().collect(), usize::MAX);
+            shell.complete(false, &mut Vec::new()).unwrap();
+            (shell.line.text(), shell.line.cursor())
+        };
+
+        let line = format!("ls {home_var}/Documents/");
+        assert_eq!(completed("ls ~/Doc"), (line.clone(), line.len()));
+        let line = format!("ls {home_var}/notes ");
+        assert_eq!(completed("ls ~/no"), (line.clone(), line.len()));
+        let line = format!("ls {home_var}/");
+        assert_eq!(completed("ls ~"), (line.clone(), line.len()));
+
+        std::fs::remove_dir_all(home).unwrap();
+    }
 
-fn common_prefix_ascii(strings: &[String]) -> String {
-    if strings.is_empty() {
-        return String::new();
+    #[test]
+    fn cancel_restores_line() {
+        let mut shell = with_history(&["echo one", "ls"]);
+        shell.line.set("draft".chars().collect(), 2);
+        shell.start_search(true, false);
+        send(&mut shell, "echo");
+        assert_eq!(found(&shell), Some("echo one"));
+
+        send(&mut shell, "G");
+        assert!(shell.search.is_none());
+        assert_eq!(shell.line.text(), "draft");
+        assert_eq!(shell.line.cursor(), 2);
     }
 
-    let first = strings[0].as_bytes();
-    let mut end = first.len();
+    #[test]
+    fn word_keys() {
+        let mut shell = with_history(&[]);
+        let mut edited = |keys: &[u8]| shell.run(keys, &mut Vec::new(), "> ").unwrap();
+
+        // Alt-B and Alt-F stop at the `/` and `.` of a path, as does Alt-D.
+        assert_eq!(edited(b"cat foo/bar.txt\x1bb\x1bb\x1bd\r"), "cat foo/.txt");
+        assert_eq!(
+            edited(b"cat foo/bar.txt\x01\x1bf\x1bf\x1bd\r"),
+            "cat foo.txt"
+        );
+        assert_eq!(edited(b"cat foo/bar.txt\x1b\x7f\r"), "cat foo/bar.");
+        // Ctrl-W takes the whole of it.
+        assert_eq!(edited(b"cat foo/bar.txt\x17\r"), "cat ");
+    }
 
-    for s in &strings[1..] {
-        let b = s.as_bytes();
-        end = end.min(b.len());
+    #[test]
+    fn macros() {
+        let mut shell = with_history(&[]);
+        let errors = keymap::read_inputrc("\"x\": \"xy\"\n\"\\C-t\": \"\\C-ax\"", &mut shell.ctx);
+        assert_eq!(errors, []);
+        let mut edited = |keys: &[u8]| shell.run(keys, &mut Vec::new(), "> ").unwrap();
 
-        for i in 0..end {
-            if first[i] != b[i] {
-                end = i;
-                break;
-            }
-        }
+        // The keys a macro types run actions, but not macros again.
+        assert_eq!(edited(b"x\r"), "xy");
+        assert_eq!(edited(b"ab\x14\r"), "xab");
     }
 
-    strings[0][..end].to_string()
+    #[test]
+    fn interrupt() {
+        let mut shell = with_history(&[]);
+        let err = shell
+            .run(&b"echo \x03"[..], &mut Vec::new(), "> ")
+            .unwrap_err();
+        assert!(matches!(err.downcast_ref(), Some(ShellError::Interrupted)));
+        assert_eq!(shell.line.text(), "");
+        assert_eq!(shell.ctx.last_status(), 130);
+    }
 }
@@ -0,0 +1,241 @@
+use std::fs;
+
+use anyhow::Result;
+use codecrafters_shell::{Builtins, ShellCtx, Token, TokenType};
+
+/// Stands in for the next character typed, so that lexing the line up to
+/// the cursor shows which word that character would be part of.
+const SENTINEL: char = 'x';
+
+/// The word being completed, up to the cursor.
+#[derive(Debug, PartialEq)]
+pub struct Word {
+    /// Where the word starts in the line, in characters.
+    pub start: usize,
+    /// The word with its quotes and escapes taken out.
+    pub value: String,
+    /// The quote still open at the cursor, which the completion goes inside.
+    pub quote: Option<char>,
+    /// Whether the word names a command rather than being an argument.
+    pub command: bool,
+    /// Whether the word starts with a `~` that stands for `$HOME`.
+    pub tilde: bool,
+}
+
+/// Finds the word that ends at the cursor of a line whose text up to the
+/// cursor is `prefix`. Words holding expansions, and places such as
+/// comments where nothing can be completed, give `None`.
+pub fn current_word(prefix: &str) -> Option<Word> {
+    let (input, quote) = [None, Some('\''), Some('"')]
+        .into_iter()
+        .find_map(|quote| {
+            let mut input = format!("{prefix}{SENTINEL}");
+            input.extend(quote);
+            codecrafters_shell::run_lexer(&input)
+                .is_ok()
+                .then_some((input, quote))
+        })?;
+    let tokens = codecrafters_shell::run_lexer(&input).ok()?;
+
+    let offset = |token: &Token| token.origin.as_ptr() as usize - input.as_ptr() as usize;
+    let last = tokens.last()?;
+    if !last.token_type.is_word() || offset(last) + last.origin.len() != prefix.len() + 1 {
+        return None;
+    }
+
+    let mut first = tokens.len() - 1;
+    while first > 0 && tokens[first - 1].is_adjacent && tokens[first - 1].token_type.is_word() {
+        first -= 1;
+    }
+
+    let mut value = String::new();
+    for token in &tokens[first..] {
+        match token.token_type {
+            TokenType::Word | TokenType::SingleQuote | TokenType::Escaped => {
+                value.push_str(token.origin);
+            }
+            TokenType::DoubleQuote if !token.origin.contains(['$', '`']) => {
+                let mut chars = token.origin.chars();
+                while let Some(c) = chars.next() {
+                    match c {
+                        '\\' => value.extend(chars.next()),
+                        c => value.push(c),
+                    }
+                }
+            }
+            _ => return None,
+        }
+    }
+    value.pop();
+
+    let start = match tokens[first].token_type {
+        TokenType::Word => offset(&tokens[first]),
+        _ => offset(&tokens[first]) - 1,
+    };
+    let command = first == 0
+        || matches!(
+            tokens[first - 1].token_type,
+            TokenType::Pipe
+                | TokenType::And
+                | TokenType::Or
+                | TokenType::Semicolon
+                | TokenType::Background
+                | TokenType::Newline
+                | TokenType::LeftParen
+        );
+    let tilde = tokens[first].token_type == TokenType::Word
+        && tokens[first].origin.starts_with('~')
+        && (value == "~" || value.starts_with("~/"));
+
+    Some(Word {
+        start: prefix[..start].chars().count(),
+        value,
+        quote,
+        command,
+        tilde,
+    })
+}
+
+/// Everything `word` could be completed to, sorted. Commands are looked up
+/// among aliases, functions, builtins and `PATH`; anything else, or a
+/// command with a `/` in it, is a path and directories end with `/`.
+pub fn candidates(word: &Word, ctx: &ShellCtx) -> Result<Vec<String>> {
+    let partial = word.value.as_str();
+    let mut candidates: Vec<String> = match word.command && !partial.contains('/') {
+        true => {
+            let mut names: Vec<String> = Builtins::all_builtins()
+                .iter()
+                .map(|name| name.to_string())
+                .chain(ctx.aliases().map(|(name, _)| name.clone()))
+                .chain(ctx.functions().cloned())
+                .filter(|name| name.starts_with(partial))
+                .collect();
+            let paths = ctx.get_var("PATH").unwrap_or_default();
+            let externals = codecrafters_shell::search_executables(partial, &paths)?;
+            names.extend(externals.iter().map(|name| name.trim().to_string()));
+            names
+        }
+        false => paths(partial, word.command),
+    };
+
+    candidates.sort();
+    candidates.dedup();
+    Ok(candidates)
+}
+
+/// The files in the directory part of `partial` whose names start with the
+/// rest of it. Hidden files are left out unless asked for with a `.`.
+fn paths(partial: &str, executables: bool) -> Vec<String> {
+    let (dir, base) = partial.split_at(partial.rfind('/').map_or(0, |idx| idx + 1));
+    let Ok(entries) = fs::read_dir(if dir.is_empty() { "." } else { dir }) else {
+        return Vec::new();
+    };
+
+    entries
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            let name = entry.file_name().into_string().ok()?;
+            if !name.starts_with(base) || (name.starts_with('.') && !base.starts_with('.')) {
+                return None;
+            }
+
+            let path = entry.path();
+            let is_dir = path.is_dir();
+            if executables && !is_dir && !codecrafters_shell::is_executable(&path) {
+                return None;
+            }
+            Some(format!("{dir}{name}{}", if is_dir { "/" } else { "" }))
+        })
+        .collect()
+}
+
+/// Quotes `text` so that it reads back as itself, whether inside the quote
+/// that is open or as part of a bare word. A `~` or `#` only needs it at
+/// the start of a word.
+pub fn escape(text: &str, quote: Option<char>, word_start: bool) -> String {
+    let mut escaped = String::new();
+    for (idx, c) in text.chars().enumerate() {
+        let special = match quote {
+            Some('\'') if c == '\'' => {
+                escaped.push_str("'\\''");
+                continue;
+            }
+            Some('\'') => false,
+            Some(_) => matches!(c, '"' | '\\' | '$' | '`'),
+            None => {
+                c.is_whitespace()
+                    || "\\'\"$`|&;<>()*?[]!{}".contains(c)
+                    || (word_start && idx == 0 && matches!(c, '~' | '#'))
+            }
+        };
+        if special {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// The longest start all of `strings` share.
+pub fn common_prefix(strings: &[String]) -> &str {
+    let Some((first, rest)) = strings.split_first() else {
+        return "";
+    };
+
+    let end = rest.iter().fold(first.len(), |end, s| {
+        first[..end]
+            .char_indices()
+            .zip(s.chars())
+            .find(|((_, a), b)| a != b)
+            .map_or(end.min(s.len()), |((idx, _), _)| idx)
+    });
+    &first[..end]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn word(prefix: &str) -> (usize, String, Option<char>, bool) {
+        let word = current_word(prefix).unwrap();
+        (word.start, word.value, word.quote, word.command)
+    }
+
+    #[test]
+    fn words() {
+        assert_eq!(word(""), (0, "".into(), None, true));
+        assert_eq!(word("ec"), (0, "ec".into(), None, true));
+        assert_eq!(word("cat src/le"), (4, "src/le".into(), None, false));
+        assert_eq!(word("cat "), (4, "".into(), None, false));
+        assert_eq!(word("ls | gr"), (5, "gr".into(), None, true));
+        assert_eq!(word("true && ec"), (8, "ec".into(), None, true));
+        assert_eq!(word("a;b"), (2, "b".into(), None, true));
+        assert_eq!(word("cat >out"), (5, "out".into(), None, false));
+        assert_eq!(word("cat my\\ fi"), (4, "my fi".into(), None, false));
+        assert_eq!(word("cat 'my fi"), (4, "my fi".into(), Some('\''), false));
+        assert_eq!(word("cat a\"b c"), (4, "ab c".into(), Some('"'), false));
+        assert_eq!(word("cat a\\"), (4, "a".into(), None, false));
+        assert_eq!(word("cat \\ü\\ b"), (4, "ü b".into(), None, false));
+        assert!(current_word("echo $HO").is_none());
+        assert!(current_word("echo # ab").is_none());
+        assert!(current_word("cat ~/a").unwrap().tilde);
+        assert!(!current_word("cat '~'/a").unwrap().tilde);
+    }
+
+    #[test]
+    fn escaping() {
+        assert_eq!(escape("my file(1)", None, false), "my\\ file\\(1\\)");
+        assert_eq!(escape("~a~", None, true), "\\~a~");
+        assert_eq!(escape("it's $x", Some('\''), false), "it'\\''s $x");
+        assert_eq!(escape("it's $x", Some('"'), false), "it's \\$x");
+    }
+
+    #[test]
+    fn prefixes() {
+        let strings = ["src/lexer.rs", "src/lib.rs"].map(String::from);
+        assert_eq!(common_prefix(&strings), "src/l");
+        let strings = ["é1", "éa", "é"].map(String::from);
+        assert_eq!(common_prefix(&strings), "é");
+        assert_eq!(common_prefix(&[]), "");
+    }
+}
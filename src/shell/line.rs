@@ -0,0 +1,424 @@
+use std::collections::VecDeque;
+
+/// How many kills the kill ring keeps before dropping the oldest.
+const KILL_RING_SIZE: usize = 16;
+
+/// What the previous key did, which decides whether a kill adds to the last
+/// one, a yank can be cycled with yank-pop and typing shares an undo step.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+enum Last {
+    #[default]
+    Other,
+    Insert,
+    Kill,
+    /// Text from the kill ring now between these positions.
+    Yank(usize, usize),
+}
+
+/// The line being edited, with the kill ring and undo list the editing
+/// commands share.
+#[derive(Debug, Default)]
+pub struct Line {
+    chars: Vec<char>,
+    cursor: usize,
+    kill_ring: VecDeque<String>,
+    undo: Vec<(Vec<char>, usize)>,
+    /// Set while a vi change is being made, which is undone as one step.
+    changing: bool,
+    last: Last,
+    this: Last,
+}
+
+impl Line {
+    pub fn chars(&self) -> &[char] {
+        &self.chars
+    }
+
+    pub fn text(&self) -> String {
+        self.chars.iter().collect()
+    }
+
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.chars.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.chars.len()
+    }
+
+    /// Starts a new key, so the previous one can be told apart from those
+    /// before it.
+    pub fn next_key(&mut self) {
+        self.last = std::mem::take(&mut self.this);
+    }
+
+    /// Replaces the whole line, such as with a history entry, which starts
+    /// over what undo can go back to.
+    pub fn set(&mut self, chars: Vec<char>, cursor: usize) {
+        self.cursor = cursor.min(chars.len());
+        self.chars = chars;
+        self.undo.clear();
+        self.changing = false;
+    }
+
+    pub fn clear(&mut self) {
+        self.set(Vec::new(), 0);
+    }
+
+    pub fn move_to(&mut self, pos: usize) {
+        self.cursor = pos.min(self.chars.len());
+    }
+
+    /// Inserts a typed character. A run of them is undone in one go.
+    pub fn insert(&mut self, ch: char) {
+        if self.last != Last::Insert {
+            self.save();
+        }
+        self.chars.insert(self.cursor, ch);
+        self.cursor += 1;
+        self.this = Last::Insert;
+    }
+
+    /// Inserts text as a single undo step, leaving the cursor after it.
+    pub fn insert_str(&mut self, text: &str) {
+        self.save();
+        let end = self.cursor;
+        self.chars.splice(end..end, text.chars());
+        self.cursor += text.chars().count();
+    }
+
+    /// Deletes the character under the cursor, returning whether there was
+    /// one.
+    pub fn delete_char(&mut self) -> bool {
+        if self.cursor >= self.chars.len() {
+            return false;
+        }
+        self.save();
+        self.chars.remove(self.cursor);
+        true
+    }
+
+    /// Deletes the character before the cursor, returning whether there
+    /// was one.
+    pub fn backspace(&mut self) -> bool {
+        if self.cursor == 0 {
+            return false;
+        }
+        self.save();
+        self.cursor -= 1;
+        self.chars.remove(self.cursor);
+        true
+    }
+
+    /// Swaps the characters either side of the cursor and moves past them,
+    /// or the last two when the cursor is at the end of the line.
+    pub fn transpose(&mut self) -> bool {
+        if self.cursor == 0 || self.chars.len() < 2 {
+            return false;
+        }
+        self.save();
+        if self.cursor == self.chars.len() {
+            self.cursor -= 1;
+        }
+        self.chars.swap(self.cursor - 1, self.cursor);
+        self.cursor += 1;
+        true
+    }
+
+    pub fn kill_to_end(&mut self) -> bool {
+        self.kill(self.cursor, self.chars.len())
+    }
+
+    pub fn kill_to_start(&mut self) -> bool {
+        self.kill(0, self.cursor)
+    }
+
+    pub fn kill_word_forward(&mut self) -> bool {
+        self.kill(self.cursor, alnum_word_end(&self.chars, self.cursor))
+    }
+
+    pub fn kill_word_backward(&mut self) -> bool {
+        self.kill(alnum_word_start(&self.chars, self.cursor), self.cursor)
+    }
+
+    /// Kills back to the whitespace before the cursor, as Ctrl-W does.
+    pub fn unix_word_rubout(&mut self) -> bool {
+        self.kill(word_start(&self.chars, self.cursor), self.cursor)
+    }
+
+    /// Removes `start..end`, one end of which is the cursor, onto the kill
+    /// ring. Kills straight after one another make up a single entry, so
+    /// yanking brings them all back.
+    fn kill(&mut self, start: usize, end: usize) -> bool {
+        if start == end {
+            return false;
+        }
+        self.save();
+
+        let text: String = self.chars.drain(start..end).collect();
+        match self.kill_ring.front_mut() {
+            Some(front) if self.last == Last::Kill && start < self.cursor => {
+                front.insert_str(0, &text)
+            }
+            Some(front) if self.last == Last::Kill => front.push_str(&text),
+            _ => {
+                self.kill_ring.push_front(text);
+                self.kill_ring.truncate(KILL_RING_SIZE);
+            }
+        }
+
+        self.cursor = start;
+        self.this = Last::Kill;
+        true
+    }
+
+    /// Inserts the most recent kill, returning whether there was one.
+    pub fn yank(&mut self) -> bool {
+        let Some(text) = self.kill_ring.front().cloned() else {
+            return false;
+        };
+        let start = self.cursor;
+        self.insert_str(&text);
+        self.this = Last::Yank(start, self.cursor);
+        true
+    }
+
+    /// Swaps what the previous key yanked for the kill before it, which is
+    /// only possible straight after a yank.
+    pub fn yank_pop(&mut self) -> bool {
+        let Last::Yank(start, end) = self.last else {
+            return false;
+        };
+        if self.kill_ring.len() < 2 {
+            return false;
+        }
+
+        self.kill_ring.rotate_left(1);
+        let text = &self.kill_ring[0];
+        self.chars.splice(start..end, text.chars());
+        self.cursor = start + text.chars().count();
+        self.this = Last::Yank(start, self.cursor);
+        true
+    }
+
+    /// Replaces `start..end` with `text` as a single undo step, leaving the
+    /// cursor after it.
+    pub fn replace(&mut self, start: usize, end: usize, text: &str) {
+        self.save();
+        self.chars.splice(start..end, text.chars());
+        self.cursor = start + text.chars().count();
+    }
+
+    /// Removes `start..end` and returns it, leaving the cursor at `start`.
+    pub fn delete_range(&mut self, start: usize, end: usize) -> String {
+        self.save();
+        self.cursor = start;
+        self.chars.drain(start..end).collect()
+    }
+
+    /// Starts a change that undo takes back in one step, however many
+    /// edits it is made of.
+    pub fn begin_change(&mut self) {
+        self.save();
+        self.changing = true;
+    }
+
+    pub fn end_change(&mut self) {
+        if !self.changing {
+            return;
+        }
+        self.changing = false;
+        // A change that changed nothing leaves nothing to undo.
+        if self
+            .undo
+            .last()
+            .is_some_and(|(chars, _)| *chars == self.chars)
+        {
+            self.undo.pop();
+        }
+    }
+
+    /// Puts back the line as it was before the last change.
+    pub fn undo(&mut self) -> bool {
+        let Some((chars, cursor)) = self.undo.pop() else {
+            return false;
+        };
+        self.chars = chars;
+        self.cursor = cursor;
+        true
+    }
+
+    fn save(&mut self) {
+        if !self.changing {
+            self.undo.push((self.chars.clone(), self.cursor));
+        }
+    }
+}
+
+/// Words as Ctrl-W and vi's `W`, `B` and `E` see them are split on
+/// whitespace alone, so quotes do not hold one together and an operator
+/// such as `|` is part of the word it touches.
+fn is_blank(ch: &char) -> bool {
+    ch.is_whitespace()
+}
+
+/// Words as Alt-B, Alt-F and Alt-D see them are made of letters and digits
+/// alone, as in readline, so `foo/bar.txt` is three of them.
+fn is_delimiter(ch: &char) -> bool {
+    !ch.is_alphanumeric()
+}
+
+/// Where the word at or after `pos` ends.
+pub fn word_end(chars: &[char], pos: usize) -> usize {
+    end_of_word(chars, pos, is_blank)
+}
+
+/// Where the word before `pos` starts.
+pub fn word_start(chars: &[char], pos: usize) -> usize {
+    start_of_word(chars, pos, is_blank)
+}
+
+/// Where the run of letters and digits at or after `pos` ends.
+pub fn alnum_word_end(chars: &[char], pos: usize) -> usize {
+    end_of_word(chars, pos, is_delimiter)
+}
+
+/// Where the run of letters and digits before `pos` starts.
+pub fn alnum_word_start(chars: &[char], pos: usize) -> usize {
+    start_of_word(chars, pos, is_delimiter)
+}
+
+fn end_of_word(chars: &[char], pos: usize, is_delimiter: fn(&char) -> bool) -> usize {
+    let rest = &chars[pos..];
+    let start = rest
+        .iter()
+        .position(|ch| !is_delimiter(ch))
+        .unwrap_or(rest.len());
+    let len = rest[start..]
+        .iter()
+        .position(is_delimiter)
+        .unwrap_or(rest.len() - start);
+    pos + start + len
+}
+
+fn start_of_word(chars: &[char], pos: usize, is_delimiter: fn(&char) -> bool) -> usize {
+    let before = &chars[..pos];
+    let end = before
+        .iter()
+        .rposition(|ch| !is_delimiter(ch))
+        .map_or(0, |i| i + 1);
+    before[..end]
+        .iter()
+        .rposition(is_delimiter)
+        .map_or(0, |i| i + 1)
+}
+
+/// Where the word after the one at `pos` starts.
+pub fn next_word(chars: &[char], pos: usize) -> usize {
+    let rest = &chars[pos..];
+    let end = rest.iter().position(is_blank).unwrap_or(rest.len());
+    let start = rest[end..]
+        .iter()
+        .position(|ch| !is_blank(ch))
+        .unwrap_or(rest.len() - end);
+    pos + end + start
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(text: &str, cursor: usize) -> Line {
+        let mut line = Line::default();
+        line.set(text.chars().collect(), cursor);
+        line
+    }
+
+    #[test]
+    fn words() {
+        // Only whitespace counts, even inside quotes or next to a `|`.
+        let chars: Vec<char> = "echo  'a b'|wc".chars().collect();
+        assert_eq!(word_end(&chars, 0), 4);
+        assert_eq!(word_end(&chars, 4), 8);
+        assert_eq!(word_end(&chars, 9), chars.len());
+        assert_eq!(word_start(&chars, chars.len()), 9);
+        assert_eq!(word_start(&chars, 9), 6);
+        assert_eq!(word_start(&chars, 6), 0);
+
+        // Readline's words stop at anything but letters and digits.
+        let chars: Vec<char> = "cat foo/bar.txt".chars().collect();
+        assert_eq!(alnum_word_end(&chars, 3), 7);
+        assert_eq!(alnum_word_end(&chars, 7), 11);
+        assert_eq!(alnum_word_start(&chars, chars.len()), 12);
+        assert_eq!(alnum_word_start(&chars, 11), 8);
+        assert_eq!(alnum_word_start(&chars, 8), 4);
+    }
+
+    #[test]
+    fn kills_add_up() {
+        let mut line = line("one two three", 13);
+        line.kill_word_backward();
+        line.next_key();
+        line.kill_word_backward();
+        line.next_key();
+        assert_eq!(line.text(), "one ");
+        line.yank();
+        assert_eq!(line.text(), "one two three");
+
+        let mut line = self::line("a b c", 0);
+        line.kill_word_forward();
+        line.next_key();
+        line.kill_word_forward();
+        assert_eq!(line.text(), " c");
+        line.next_key();
+        line.move_to(2);
+        line.yank();
+        assert_eq!(line.text(), " ca b");
+    }
+
+    #[test]
+    fn yank_pop() {
+        let mut line = line("a b", 3);
+        line.kill_word_backward();
+        line.next_key();
+        line.backspace();
+        line.next_key();
+        line.kill_word_backward();
+        line.next_key();
+        line.yank();
+        line.next_key();
+        assert_eq!(line.text(), "a");
+        assert!(line.yank_pop());
+        line.next_key();
+        assert_eq!(line.text(), "b");
+        assert!(line.yank_pop());
+        line.next_key();
+        assert_eq!(line.text(), "a");
+        line.insert('x');
+        line.next_key();
+        assert!(!line.yank_pop());
+    }
+
+    #[test]
+    fn transpose_and_undo() {
+        let mut line = line("ab", 2);
+        line.transpose();
+        line.next_key();
+        assert_eq!(line.text(), "ba");
+        line.move_to(1);
+        line.insert('x');
+        line.next_key();
+        line.insert('y');
+        line.next_key();
+        assert_eq!(line.text(), "bxya");
+        line.undo();
+        assert_eq!(line.text(), "ba");
+        line.undo();
+        assert_eq!(line.text(), "ab");
+        assert!(!line.undo());
+    }
+}
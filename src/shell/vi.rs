@@ -0,0 +1,549 @@
+use termion::event::Key;
+
+use super::line::{Line, next_word, word_end, word_start};
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+enum Mode {
+    #[default]
+    Insert,
+    Normal,
+}
+
+/// What the shell should do after a key in vi mode.
+#[derive(Debug, PartialEq)]
+pub enum ViAction {
+    Redraw,
+    Bell,
+    /// Not a vi key, so it is handled as in emacs mode, like Enter or Tab.
+    Pass,
+    /// `k` or `j`, moving to an older entry when set.
+    History(bool),
+    /// `/` or `?`, searching older entries when set.
+    Search(bool),
+    /// `n` or `N`, searching again in the same direction when set.
+    SearchAgain(bool),
+}
+
+/// The state of the vi editor between keys.
+#[derive(Debug, Default)]
+pub struct Vi {
+    mode: Mode,
+    count: Option<usize>,
+    /// An operator waiting for its motion, with the count typed before it.
+    operator: Option<(char, Option<usize>)>,
+    /// `f`, `t`, `F`, `T` or `r` waiting for its character.
+    pending: Option<char>,
+    /// The last `f`, `t`, `F` or `T` and its character, for `;` and `,`.
+    last_find: Option<(char, char)>,
+    /// What the last delete or yank took, which `p` puts back.
+    register: String,
+    /// The keys of the change being made, and of the last one made, which
+    /// `.` repeats.
+    keys: Vec<Key>,
+    last_change: Vec<Key>,
+}
+
+impl Vi {
+    /// Shown before the prompt so the mode can be told at a glance.
+    pub fn indicator(&self) -> &'static str {
+        match self.mode {
+            Mode::Insert => "(ins) ",
+            Mode::Normal => "(cmd) ",
+        }
+    }
+
+    /// Starts a new line, which is edited in insert mode.
+    pub fn new_line(&mut self) {
+        self.mode = Mode::Insert;
+        self.reset();
+    }
+
+    fn reset(&mut self) {
+        self.count = None;
+        self.operator = None;
+        self.pending = None;
+        self.keys.clear();
+    }
+
+    pub fn key(&mut self, key: Key, line: &mut Line) -> ViAction {
+        match self.mode {
+            Mode::Insert => self.insert_key(key, line),
+            Mode::Normal => {
+                let action = self.normal_key(key, line);
+                if self.mode == Mode::Normal {
+                    line.move_to(line.cursor().min(line.len().saturating_sub(1)));
+                }
+                action
+            }
+        }
+    }
+
+    fn insert_key(&mut self, key: Key, line: &mut Line) -> ViAction {
+        let recording = !self.keys.is_empty();
+        match key {
+            Key::Esc => {
+                line.move_to(line.cursor().saturating_sub(1));
+                line.end_change();
+                self.mode = Mode::Normal;
+                if recording {
+                    self.keys.push(key);
+                    self.last_change = std::mem::take(&mut self.keys);
+                }
+                ViAction::Redraw
+            }
+            // Esc typed quickly before a key arrives as one Alt key.
+            Key::Alt(ch) => {
+                self.insert_key(Key::Esc, line);
+                self.key(Key::Char(ch), line)
+            }
+            Key::Char(ch) if !matches!(ch, '\n' | '\t') => {
+                line.insert(ch);
+                if recording {
+                    self.keys.push(key);
+                }
+                ViAction::Redraw
+            }
+            Key::Backspace if line.cursor() > 0 => {
+                line.backspace();
+                if recording {
+                    self.keys.push(key);
+                }
+                ViAction::Redraw
+            }
+            _ => ViAction::Pass,
+        }
+    }
+
+    fn normal_key(&mut self, key: Key, line: &mut Line) -> ViAction {
+        let Key::Char(ch) = key else {
+            if key == Key::Esc {
+                self.reset();
+                return ViAction::Redraw;
+            }
+            return ViAction::Pass;
+        };
+        self.keys.push(key);
+
+        if let Some(kind) = self.pending.take() {
+            return match kind {
+                'r' => self.replace(ch, line),
+                _ => {
+                    self.last_find = Some((kind, ch));
+                    self.motion(kind, Some(ch), false, line)
+                }
+            };
+        }
+
+        match ch {
+            '1'..='9' | '0' if ch != '0' || self.count.is_some() => {
+                let digit = ch.to_digit(10).unwrap() as usize;
+                self.count = Some(self.count.unwrap_or(0).saturating_mul(10) + digit);
+                ViAction::Redraw
+            }
+            'f' | 't' | 'F' | 'T' | 'r' => {
+                self.pending = Some(ch);
+                ViAction::Redraw
+            }
+            'h' | 'l' | 'w' | 'b' | 'e' | 'W' | 'B' | 'E' | '0' | '$' | ' ' => {
+                self.motion(ch, None, false, line)
+            }
+            ';' | ',' => match self.last_find {
+                Some((kind, target)) if ch == ';' => self.motion(kind, Some(target), true, line),
+                Some((kind, target)) => self.motion(reverse_find(kind), Some(target), true, line),
+                None => self.fail(),
+            },
+
+            'd' | 'c' | 'y' => match self.operator {
+                // `dd`, `cc` and `yy` act on the whole line.
+                Some((op, _)) if op == ch => {
+                    self.operator = None;
+                    self.operate(ch, 0, line.len(), line)
+                }
+                Some(_) => self.fail(),
+                None => {
+                    self.operator = Some((ch, self.count.take()));
+                    ViAction::Redraw
+                }
+            },
+            'D' | 'C' if self.operator.is_none() => {
+                let op = ch.to_ascii_lowercase();
+                self.operate(op, line.cursor(), line.len(), line)
+            }
+            'x' | 'X' | 's' if self.operator.is_none() => {
+                let count = self.count.unwrap_or(1);
+                let (start, end) = match ch {
+                    'X' => (line.cursor().saturating_sub(count), line.cursor()),
+                    _ => (line.cursor(), (line.cursor() + count).min(line.len())),
+                };
+                if start == end && ch != 's' {
+                    return self.fail();
+                }
+                let op = if ch == 's' { 'c' } else { 'd' };
+                self.operate(op, start, end, line)
+            }
+
+            'i' | 'a' | 'I' | 'A' if self.operator.is_none() => {
+                let pos = match ch {
+                    'i' => line.cursor(),
+                    'a' => (line.cursor() + 1).min(line.len()),
+                    'I' => 0,
+                    _ => line.len(),
+                };
+                line.move_to(pos);
+                line.begin_change();
+                self.count = None;
+                self.mode = Mode::Insert;
+                ViAction::Redraw
+            }
+            'p' | 'P' if self.operator.is_none() => self.paste(ch == 'p', line),
+            'u' if self.operator.is_none() => {
+                self.reset();
+                if line.undo() {
+                    ViAction::Redraw
+                } else {
+                    ViAction::Bell
+                }
+            }
+            '.' if self.operator.is_none() => self.repeat(line),
+
+            'k' | 'j' if self.operator.is_none() => {
+                self.reset();
+                ViAction::History(ch == 'k')
+            }
+            '/' | '?' if self.operator.is_none() => {
+                self.reset();
+                ViAction::Search(ch == '/')
+            }
+            'n' | 'N' if self.operator.is_none() => {
+                self.reset();
+                ViAction::SearchAgain(ch == 'n')
+            }
+            '\n' | '\t' => {
+                self.reset();
+                ViAction::Pass
+            }
+            _ => self.fail(),
+        }
+    }
+
+    fn fail(&mut self) -> ViAction {
+        self.reset();
+        ViAction::Bell
+    }
+
+    /// Moves the cursor, or hands the text it moves over to the waiting
+    /// operator. `again` is set for `;` and `,`, where `t` and `T` skip a
+    /// match next to the cursor rather than stay put.
+    fn motion(
+        &mut self,
+        motion: char,
+        arg: Option<char>,
+        again: bool,
+        line: &mut Line,
+    ) -> ViAction {
+        let count = match self.operator {
+            Some((_, Some(before))) => before * self.count.unwrap_or(1),
+            _ => self.count.unwrap_or(1),
+        };
+        let chars = line.chars();
+        let cursor = line.cursor();
+
+        // `cw` changes to the end of the word, as `ce` does.
+        let motion = match (self.operator, motion) {
+            (Some(('c', _)), 'w' | 'W')
+                if chars.get(cursor).is_some_and(|c| !c.is_whitespace()) =>
+            {
+                if motion == 'w' {
+                    'e'
+                } else {
+                    'E'
+                }
+            }
+            _ => motion,
+        };
+
+        let target = match motion {
+            'h' => cursor.checked_sub(1).map(|_| cursor.saturating_sub(count)),
+            'l' | ' ' => (cursor < chars.len()).then(|| (cursor + count).min(chars.len())),
+            'w' => Some((0..count).fold(cursor, |pos, _| next_vi_word(chars, pos))),
+            'b' => Some((0..count).fold(cursor, |pos, _| vi_word_start(chars, pos))),
+            'e' => Some((0..count).fold(cursor, |pos, _| {
+                vi_word_end(chars, (pos + 1).min(chars.len())).saturating_sub(1)
+            })),
+            'W' => Some((0..count).fold(cursor, |pos, _| next_word(chars, pos))),
+            'B' => Some((0..count).fold(cursor, |pos, _| word_start(chars, pos))),
+            'E' => Some((0..count).fold(cursor, |pos, _| {
+                word_end(chars, (pos + 1).min(chars.len())).saturating_sub(1)
+            })),
+            '0' => Some(0),
+            '$' => Some(chars.len()),
+            'f' | 't' => {
+                let target = arg.unwrap();
+                let skip = usize::from(motion == 't');
+                let from = cursor + 1 + usize::from(again) * skip;
+                chars
+                    .iter()
+                    .enumerate()
+                    .skip(from)
+                    .filter(|(_, ch)| **ch == target)
+                    .nth(count - 1)
+                    .map(|(pos, _)| pos - skip)
+            }
+            'F' | 'T' => {
+                let target = arg.unwrap();
+                let skip = usize::from(motion == 'T');
+                chars[..cursor.saturating_sub(usize::from(again) * skip)]
+                    .iter()
+                    .enumerate()
+                    .rev()
+                    .filter(|(_, ch)| **ch == target)
+                    .nth(count - 1)
+                    .map(|(pos, _)| pos + skip)
+            }
+            _ => None,
+        };
+        let Some(target) = target else {
+            return self.fail();
+        };
+
+        let Some((op, _)) = self.operator.take() else {
+            self.reset();
+            line.move_to(target);
+            return ViAction::Redraw;
+        };
+
+        // These motions take in the character they land on.
+        let inclusive = matches!(motion, 'e' | 'E' | 'f' | 't');
+        let (start, end) = match target < cursor {
+            true => (target, cursor),
+            false => (cursor, (target + usize::from(inclusive)).min(chars.len())),
+        };
+        self.operate(op, start, end, line)
+    }
+
+    /// Deletes, changes or yanks `start..end`.
+    fn operate(&mut self, op: char, start: usize, end: usize, line: &mut Line) -> ViAction {
+        self.count = None;
+        self.register = line.chars()[start..end].iter().collect();
+        if op == 'y' {
+            self.reset();
+            line.move_to(start);
+            return ViAction::Redraw;
+        }
+
+        line.begin_change();
+        line.delete_range(start, end);
+        match op {
+            'c' => self.mode = Mode::Insert,
+            _ => self.finish_change(line),
+        }
+        ViAction::Redraw
+    }
+
+    fn paste(&mut self, after: bool, line: &mut Line) -> ViAction {
+        if self.register.is_empty() {
+            return self.fail();
+        }
+        let text = self.register.repeat(self.count.unwrap_or(1));
+
+        line.begin_change();
+        if after && !line.is_empty() {
+            line.move_to(line.cursor() + 1);
+        }
+        line.insert_str(&text);
+        line.move_to(line.cursor() - 1);
+        self.finish_change(line);
+        ViAction::Redraw
+    }
+
+    fn replace(&mut self, ch: char, line: &mut Line) -> ViAction {
+        let count = self.count.unwrap_or(1);
+        let start = line.cursor();
+        if start + count > line.len() {
+            return self.fail();
+        }
+
+        line.begin_change();
+        line.delete_range(start, start + count);
+        line.insert_str(&ch.to_string().repeat(count));
+        line.move_to(start + count - 1);
+        self.finish_change(line);
+        ViAction::Redraw
+    }
+
+    fn finish_change(&mut self, line: &mut Line) {
+        line.end_change();
+        self.last_change = std::mem::take(&mut self.keys);
+        self.reset();
+    }
+
+    /// Makes the last change again, with a new count if one was typed.
+    fn repeat(&mut self, line: &mut Line) -> ViAction {
+        let count = self.count;
+        self.reset();
+        if self.last_change.is_empty() {
+            return ViAction::Bell;
+        }
+
+        let mut keys = self.last_change.clone();
+        if let Some(count) = count {
+            let digits = keys
+                .iter()
+                .take_while(|key| matches!(key, Key::Char('0'..='9')))
+                .count();
+            keys.splice(..digits, count.to_string().chars().map(Key::Char));
+        }
+
+        for key in keys {
+            if self.key(key, line) == ViAction::Bell {
+                return ViAction::Bell;
+            }
+        }
+        ViAction::Redraw
+    }
+}
+
+fn reverse_find(kind: char) -> char {
+    match kind {
+        'f' => 'F',
+        'F' => 'f',
+        't' => 'T',
+        _ => 't',
+    }
+}
+
+/// Sorts characters for `w`, `b` and `e`, where a word is a run of letters,
+/// digits and underscores or a run of other non-blank characters. `W`, `B`
+/// and `E` use the line's own words, which only blanks split.
+fn class(ch: char) -> u8 {
+    match ch {
+        ch if ch.is_whitespace() => 0,
+        ch if ch.is_alphanumeric() || ch == '_' => 1,
+        _ => 2,
+    }
+}
+
+/// Where the vi word after the one at `pos` starts.
+fn next_vi_word(chars: &[char], mut pos: usize) -> usize {
+    if let Some(&ch) = chars.get(pos) {
+        let kind = class(ch);
+        while pos < chars.len() && kind != 0 && class(chars[pos]) == kind {
+            pos += 1;
+        }
+    }
+    while pos < chars.len() && class(chars[pos]) == 0 {
+        pos += 1;
+    }
+    pos
+}
+
+/// Where the vi word before `pos` starts.
+fn vi_word_start(chars: &[char], mut pos: usize) -> usize {
+    while pos > 0 && class(chars[pos - 1]) == 0 {
+        pos -= 1;
+    }
+    let Some(&ch) = pos.checked_sub(1).and_then(|prev| chars.get(prev)) else {
+        return 0;
+    };
+    let kind = class(ch);
+    while pos > 0 && class(chars[pos - 1]) == kind {
+        pos -= 1;
+    }
+    pos
+}
+
+/// Where the vi word at or after `pos` ends.
+fn vi_word_end(chars: &[char], mut pos: usize) -> usize {
+    while pos < chars.len() && class(chars[pos]) == 0 {
+        pos += 1;
+    }
+    let Some(&ch) = chars.get(pos) else {
+        return pos;
+    };
+    let kind = class(ch);
+    while pos < chars.len() && class(chars[pos]) == kind {
+        pos += 1;
+    }
+    pos
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Types `keys` in normal mode on `text` with the cursor at `cursor`,
+    /// where `\x1b` stands for Esc.
+    fn run(text: &str, cursor: usize, keys: &str) -> (String, usize) {
+        let mut line = Line::default();
+        line.set(text.chars().collect(), cursor);
+        let mut vi = Vi {
+            mode: Mode::Normal,
+            ..Vi::default()
+        };
+        for ch in keys.chars() {
+            line.next_key();
+            let key = if ch == '\x1b' {
+                Key::Esc
+            } else {
+                Key::Char(ch)
+            };
+            vi.key(key, &mut line);
+        }
+        (line.text(), line.cursor())
+    }
+
+    #[test]
+    fn motions() {
+        assert_eq!(run("echo one two", 0, "w"), ("echo one two".into(), 5));
+        assert_eq!(run("echo one two", 0, "2w"), ("echo one two".into(), 9));
+        assert_eq!(run("echo one two", 11, "b"), ("echo one two".into(), 9));
+        assert_eq!(run("echo one two", 0, "e"), ("echo one two".into(), 3));
+        assert_eq!(run("echo one two", 0, "$"), ("echo one two".into(), 11));
+        assert_eq!(run("echo one two", 5, "0"), ("echo one two".into(), 0));
+        assert_eq!(run("a,b,c", 0, "2f,"), ("a,b,c".into(), 3));
+        assert_eq!(run("a,b,c", 0, "t,"), ("a,b,c".into(), 0));
+        assert_eq!(run("a,b,c", 0, "t,;"), ("a,b,c".into(), 2));
+        assert_eq!(run("a,b,c", 4, "F,"), ("a,b,c".into(), 3));
+
+        // Punctuation splits `w`, `b` and `e` words but not `W`, `B` and `E`.
+        assert_eq!(run("cat foo.bar x", 4, "w"), ("cat foo.bar x".into(), 7));
+        assert_eq!(run("cat foo.bar x", 4, "2w"), ("cat foo.bar x".into(), 8));
+        assert_eq!(run("cat foo.bar x", 4, "W"), ("cat foo.bar x".into(), 12));
+        assert_eq!(run("cat foo.bar x", 4, "e"), ("cat foo.bar x".into(), 6));
+        assert_eq!(run("cat foo.bar x", 4, "E"), ("cat foo.bar x".into(), 10));
+        assert_eq!(run("cat foo.bar x", 12, "b"), ("cat foo.bar x".into(), 8));
+        assert_eq!(run("cat foo.bar x", 12, "2b"), ("cat foo.bar x".into(), 7));
+        assert_eq!(run("cat foo.bar x", 12, "B"), ("cat foo.bar x".into(), 4));
+        assert_eq!(run("a..b", 0, "w"), ("a..b".into(), 1));
+        assert_eq!(run("a..b", 1, "w"), ("a..b".into(), 3));
+    }
+
+    #[test]
+    fn operators() {
+        assert_eq!(run("echo one two", 5, "dw"), ("echo two".into(), 5));
+        assert_eq!(run("echo one two", 0, "d2w"), ("two".into(), 0));
+        assert_eq!(
+            run("echo one two", 5, "cwthree\x1b"),
+            ("echo three two".into(), 9)
+        );
+        assert_eq!(run("echo one two", 5, "dt "), ("echo  two".into(), 5));
+        assert_eq!(run("echo one two", 5, "D"), ("echo ".into(), 4));
+        assert_eq!(run("echo one two", 5, "dd"), ("".into(), 0));
+        assert_eq!(run("abc", 0, "3x"), ("".into(), 0));
+        assert_eq!(run("echo one", 5, "yw$p"), ("echo oneone".into(), 10));
+        assert_eq!(run("abc", 0, "rx"), ("xbc".into(), 0));
+        assert_eq!(run("cat foo.bar", 4, "dw"), ("cat .bar".into(), 4));
+        assert_eq!(run("cat foo.bar", 4, "dW"), ("cat ".into(), 3));
+        assert_eq!(run("cat foo.bar", 4, "cwx\x1b"), ("cat x.bar".into(), 4));
+        assert_eq!(run("cat foo.bar", 4, "cEx\x1b"), ("cat x".into(), 4));
+    }
+
+    #[test]
+    fn repeat_and_undo() {
+        assert_eq!(run("a b c d", 0, "dw."), ("c d".into(), 0));
+        assert_eq!(run("a b c d", 0, "dw2."), ("d".into(), 0));
+        assert_eq!(run("x", 0, "aa\x1b.."), ("xaaa".into(), 3));
+        assert_eq!(run("a b c", 0, "dwdwu"), ("b c".into(), 0));
+        assert_eq!(run("a b", 0, "cwxyz\x1bu"), ("a b".into(), 0));
+    }
+}
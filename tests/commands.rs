@@ -0,0 +1,945 @@
+use std::{
+    fs::{self, File},
+    io::{Read, Write},
+    os::{
+        fd::{AsRawFd, FromRawFd, OwnedFd},
+        unix::process::CommandExt,
+    },
+    path::{Path, PathBuf},
+    process::{Child, Command, ExitStatus, Output, Stdio},
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+    time::Duration,
+};
+
+/// Runs `script` in a fresh shell with `-c`.
+fn run(script: &str) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_codecrafters-shell"))
+        .args(["-c", script])
+        .output()
+        .expect("the shell should start")
+}
+
+fn stdout(script: &str) -> String {
+    String::from_utf8_lossy(&run(script).stdout).into_owned()
+}
+
+fn stderr(script: &str) -> String {
+    String::from_utf8_lossy(&run(script).stderr).into_owned()
+}
+
+/// What the shell draws when it waits for a new line.
+const PROMPT: &[u8] = b"$ \x1b[J\r\x1b[2C";
+
+static TERMINALS: AtomicUsize = AtomicUsize::new(0);
+
+/// An interactive shell on a terminal of its own, typed at as a user would.
+struct Terminal {
+    master: File,
+    child: Child,
+    home: PathBuf,
+}
+
+impl Terminal {
+    /// Starts a shell with an empty directory as `$HOME` and waits for its
+    /// first prompt.
+    fn start() -> Terminal {
+        Terminal::start_with(&[], &[], |_| {}).0
+    }
+
+    /// Starts a shell with `args` and `envs`, once `setup` has filled in its
+    /// `$HOME`, and returns it with what it printed before its first prompt.
+    fn start_with(
+        args: &[&str],
+        envs: &[(&str, &str)],
+        setup: impl FnOnce(&Path),
+    ) -> (Terminal, String) {
+        let home = std::env::temp_dir().join(format!(
+            "terminal-{}-{}",
+            std::process::id(),
+            TERMINALS.fetch_add(1, Ordering::Relaxed)
+        ));
+        fs::create_dir_all(&home).unwrap();
+        setup(&home);
+
+        let (master, slave) = unsafe {
+            let (mut master, mut slave) = (0, 0);
+            let (name, termios, size) = (std::ptr::null_mut(), std::ptr::null(), std::ptr::null());
+            assert_eq!(
+                libc::openpty(&mut master, &mut slave, name, termios, size),
+                0
+            );
+            (File::from_raw_fd(master), OwnedFd::from_raw_fd(slave))
+        };
+
+        let mut cmd = Command::new(env!("CARGO_BIN_EXE_codecrafters-shell"));
+        cmd.args(args)
+            .env("HOME", &home)
+            .env("HISTFILE", home.join("history"))
+            .env_remove("SHELLRC")
+            .env_remove("PS1")
+            .env_remove("INPUTRC")
+            .envs(envs.iter().copied())
+            .stdin(Stdio::from(slave.try_clone().unwrap()))
+            .stdout(Stdio::from(slave.try_clone().unwrap()))
+            .stderr(Stdio::from(slave));
+        // The terminal becomes the shell's controlling one, as a login would
+        // make it.
+        unsafe {
+            cmd.pre_exec(|| {
+                libc::setsid();
+                libc::ioctl(0, libc::TIOCSCTTY, 0);
+                Ok(())
+            });
+        }
+        let child = cmd.spawn().expect("the shell should start");
+        drop(cmd);
+
+        let mut terminal = Terminal {
+            master,
+            child,
+            home,
+        };
+        let mut output = Vec::new();
+        while !output.ends_with(PROMPT) {
+            output.push(terminal.read_byte().expect("the shell should prompt"));
+        }
+        (terminal, clean(&output))
+    }
+
+    /// Types `line` and returns what it printed before the next prompt.
+    fn run(&mut self, line: &str) -> String {
+        self.send(&format!("{line}\r"));
+        self.read_prompt()
+    }
+
+    fn send(&mut self, keys: &str) {
+        self.master.write_all(keys.as_bytes()).unwrap();
+    }
+
+    /// Reads up to a prompt drawn after the line sent last was done with.
+    fn read_prompt(&mut self) -> String {
+        let mut output = Vec::new();
+        while !(output.contains(&b'\n') && output.ends_with(PROMPT)) {
+            output.push(self.read_byte().expect("the shell should prompt"));
+        }
+        clean(&output)
+    }
+
+    /// Waits until a command the shell started has the terminal.
+    fn wait_for_job(&self) {
+        let shell = self.child.id() as i32;
+        while unsafe { libc::tcgetpgrp(self.master.as_raw_fd()) } == shell {
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    /// Types `keys` that end the shell and returns what it printed until it
+    /// exited, and how it did.
+    fn finish(mut self, keys: &str) -> (String, ExitStatus) {
+        self.send(keys);
+        let mut output = Vec::new();
+        while let Some(byte) = self.read_byte() {
+            output.push(byte);
+        }
+        (clean(&output), self.child.wait().unwrap())
+    }
+
+    /// Reads the next byte the shell printed, or nothing once it and every
+    /// command it started are gone.
+    fn read_byte(&mut self) -> Option<u8> {
+        let mut poll = libc::pollfd {
+            fd: self.master.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        assert_eq!(
+            unsafe { libc::poll(&mut poll, 1, 10_000) },
+            1,
+            "the shell should answer within ten seconds"
+        );
+        let mut byte = [0];
+        match self.master.read(&mut byte) {
+            Ok(1) => Some(byte[0]),
+            _ => None,
+        }
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+        let _ = fs::remove_dir_all(&self.home);
+    }
+}
+
+/// Leaves out the line being edited, which the shell draws again after every
+/// key, the newline that ends it and the carriage returns of the terminal.
+fn clean(output: &[u8]) -> String {
+    let mut output = String::from_utf8_lossy(output).into_owned();
+    while let Some(clear) = output.find("\x1b[J\r") {
+        let start = output[..clear].rfind('\r').unwrap_or(0);
+        let mut end = clear + "\x1b[J\r".len();
+        if let Some(move_right) = output[end..].strip_prefix("\x1b[") {
+            let digits = move_right.find(|c: char| !c.is_ascii_digit());
+            if let Some(digits) = digits.filter(|&d| move_right[d..].starts_with('C')) {
+                end += "\x1b[".len() + digits + 1;
+            }
+        }
+        output.replace_range(start..end, "");
+    }
+    let output = output.replace('\r', "");
+    output.strip_prefix('\n').unwrap_or(&output).to_string()
+}
+
+/// Types each of `lines` into a new shell and returns what they printed.
+fn session(lines: &[&str]) -> String {
+    let mut terminal = Terminal::start();
+    let output = lines.iter().map(|line| terminal.run(line)).collect();
+    terminal.finish("exit\r");
+    output
+}
+
+#[test]
+fn variables() {
+    assert_eq!(
+        session(&["x=1", "y=$x$x", "echo $x$y", "unset x", "echo \"[$x]\""]),
+        "111\n[]\n"
+    );
+    assert_eq!(
+        session(&[
+            "x=1",
+            "sh -c 'echo [$x]'",
+            "export x",
+            "sh -c 'echo [$x]'",
+            "export -n x",
+            "sh -c 'echo [$x]'",
+        ]),
+        "[]\n[1]\n[]\n"
+    );
+    assert_eq!(
+        session(&["export y='a b'", "env | grep ^y=", "export -p | grep ' y='"]),
+        "y=a b\nexport y='a b'\n"
+    );
+    assert_eq!(session(&["z=3", "set | grep ^z="]), "z=3\n");
+    assert_eq!(
+        session(&["export 1x=2"]),
+        "export: `1x=2': not a valid identifier\n"
+    );
+    assert_eq!(
+        stdout("set -- a 'b c'; echo $# $2; shift; echo $# $1; set --; echo $# \"[$1]\""),
+        "2 b c\n1 b c\n0 []\n"
+    );
+    assert_eq!(
+        stdout("f(){ set -- x; echo $1; }; set -- a; f; echo $1"),
+        "x\na\n"
+    );
+}
+
+#[test]
+fn readonly_variables() {
+    assert_eq!(
+        session(&[
+            "readonly r=5",
+            "r=6",
+            "export r=6",
+            "unset r",
+            "echo $r",
+            "readonly | grep ' r='",
+            "(r=7; echo no); echo $?",
+            "r=8; echo yes",
+        ]),
+        "r: readonly variable\n\
+         r: readonly variable\n\
+         unset: r: cannot unset: readonly variable\n\
+         5\n\
+         readonly r=5\n\
+         r: readonly variable\n\
+         1\n\
+         r: readonly variable\n\
+         yes\n"
+    );
+
+    // Assigning one is fatal to a script, and to a subshell, where `export`
+    // and `unset` only fail.
+    let script = "readonly r=5; export r=6; echo $? $r; unset r; echo $? $r";
+    assert_eq!(stdout(script), "1 5\n1 5\n");
+    let output = run("readonly r=5; (r=6; echo no); echo $?; r=7; echo no");
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "1\n");
+    assert_eq!(output.status.code(), Some(1));
+}
+
+#[test]
+fn command_scoped_variables() {
+    assert_eq!(
+        session(&[
+            "FOO=bar sh -c 'echo $FOO'",
+            "echo \"[$FOO]\"",
+            "FOO=bar env | grep ^FOO=",
+        ]),
+        "bar\n[]\nFOO=bar\n"
+    );
+    assert_eq!(
+        session(&[
+            "env -i A=1 /usr/bin/env",
+            "export B=2",
+            "env -u B sh -c 'echo [$B]'",
+        ]),
+        "A=1\n[]\n"
+    );
+    assert_eq!(
+        session(&["env -u", "env -x"]),
+        "env: option requires an argument -- 'u'\nenv: invalid option -- '-x'\n"
+    );
+
+    // Commands are found through the shell's `PATH`, exported or not, and
+    // through a default one once it is unset.
+    assert_eq!(stdout("env -i FOO=1 env"), "FOO=1\n");
+    assert_eq!(
+        stdout("export -n PATH; ls -d /; unset PATH; ls -d /; type ls"),
+        "/\n/\nls is /usr/bin/ls\n"
+    );
+    assert_eq!(run("PATH=/nonexistent ls").status.code(), Some(127));
+}
+
+#[test]
+fn exit_status() {
+    assert_eq!(
+        session(&[
+            "true",
+            "echo $?",
+            "false",
+            "echo $?",
+            "sh -c 'exit 3'",
+            "echo $?"
+        ]),
+        "0\n1\n3\n"
+    );
+    assert_eq!(session(&["sh -c 'kill -TERM $$'", "echo $?"]), "143\n");
+    assert!(session(&["nosuchcmd", "echo $?"]).ends_with("\n127\n"));
+    assert!(session(&["/etc/passwd", "echo $?"]).ends_with("\n126\n"));
+    assert!(session(&["cd /nonexistent", "echo $?"]).ends_with("\n1\n"));
+    assert!(session(&["export 1x", "echo $?"]).ends_with("\n1\n"));
+    assert!(session(&["echo >", "echo $?"]).ends_with("\n2\n"));
+    assert_eq!(session(&["false | true", "echo $?"]), "0\n");
+
+    let mut terminal = Terminal::start();
+    terminal.run("sh -c 'exit 4'");
+    assert_eq!(terminal.finish("exit\r").1.code(), Some(4));
+}
+
+#[test]
+fn env_status() {
+    assert_eq!(
+        session(&[
+            "env false",
+            "echo $?",
+            "env sh -c 'exit 3'",
+            "echo $?",
+            "env sh -c 'kill -TERM $$'",
+            "echo $?",
+        ]),
+        "1\n3\n143\n"
+    );
+    assert_eq!(
+        session(&["env -u", "echo $?", "env -x true", "echo $?"]),
+        "env: option requires an argument -- 'u'\n125\n\
+         env: invalid option -- '-x'\n125\n"
+    );
+
+    // The command runs in the foreground job, where ^C reaches it.
+    let mut terminal = Terminal::start();
+    terminal.send("env sleep 10\r");
+    terminal.wait_for_job();
+    terminal.send("\x03");
+    assert_eq!(terminal.read_prompt(), "^C\n");
+    assert_eq!(terminal.run("echo $?"), "130\n");
+    terminal.finish("exit\r");
+}
+
+#[test]
+fn lists() {
+    assert_eq!(session(&["false && echo x || echo y; echo $?"]), "y\n0\n");
+    assert_eq!(session(&["true || echo no; echo $?"]), "0\n");
+    assert_eq!(session(&["true && false && echo no; echo $?"]), "1\n");
+    assert_eq!(session(&["false || false; echo $?"]), "1\n");
+    assert_eq!(session(&["echo a; false; echo $?"]), "a\n1\n");
+    assert_eq!(session(&["echo a;echo b&&echo c"]), "a\nb\nc\n");
+}
+
+#[test]
+fn failglob() {
+    // A glob with no match abandons the rest of its line, even from
+    // within a function or loop, and the next line sees status 1.
+    let script = "shopt -s failglob\nf(){ echo /nonexistent*; echo no; }; for i in 1; do f; done; echo no\necho $?";
+    let output = run(script);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "1\n");
+    assert!(String::from_utf8_lossy(&output.stderr).contains("no match: /nonexistent*"));
+    assert_eq!(
+        stdout("shopt -s failglob; (echo /nonexistent*; echo no); echo $?"),
+        "1\n"
+    );
+}
+
+#[test]
+fn jobs() {
+    assert_eq!(stdout("sleep 0.1 & sleep 0.2 & wait; echo $?; jobs"), "0\n");
+    assert_eq!(stdout("wait 99999; echo $?"), "127\n");
+    assert!(
+        stderr("wait 99999").ends_with(": line 1: wait: pid 99999 is not a child of this shell\n")
+    );
+
+    let mut terminal = Terminal::start();
+    assert!(terminal.run("sh -c 'exit 4' &").starts_with("[1] "));
+    assert_eq!(terminal.run("wait $!; echo $?"), "4\n");
+    terminal.run("sh -c 'exit 5' &");
+    assert_eq!(terminal.run("wait %1; echo $?"), "5\n");
+
+    // Jobs that would outlive the test are killed through their process
+    // groups.
+    let pid = |started: String| started.trim().split_once(' ').unwrap().1.to_string();
+    let first = pid(terminal.run("sleep 10 &"));
+    let second = pid(terminal.run("sleep 10 &"));
+    assert_eq!(
+        terminal.run("jobs"),
+        "[1]-  Running                 sleep 10 &\n\
+         [2]+  Running                 sleep 10 &\n"
+    );
+    assert_eq!(
+        terminal.run("jobs %-"),
+        "[1]-  Running                 sleep 10 &\n"
+    );
+    assert_eq!(
+        terminal.run(&format!("kill -- -{second}; wait %+; echo $?")),
+        "143\n"
+    );
+    assert_eq!(
+        terminal.run("jobs %+"),
+        "[1]+  Running                 sleep 10 &\n"
+    );
+    assert_eq!(terminal.run("disown %1; jobs; echo $?"), "0\n");
+    terminal.run(&format!("kill -- -{first}"));
+    assert_eq!(
+        terminal.run("wait %1; echo $?"),
+        "wait: %1: no such job\n127\n"
+    );
+
+    assert!(
+        terminal
+            .run("sh -c 'exit 3' & fg; echo $?")
+            .ends_with("\nsh -c 'exit 3'\n3\n")
+    );
+    assert_eq!(terminal.run("bg"), "bg: current: no such job\n");
+    terminal.finish("exit\r");
+}
+
+#[test]
+fn stop_foreground_job() {
+    let mut terminal = Terminal::start();
+    terminal.send("sleep 10\r");
+    terminal.wait_for_job();
+    terminal.send("\x1a");
+    assert_eq!(
+        terminal.read_prompt(),
+        "^Z\n[1]+  Stopped                 sleep 10\n"
+    );
+    assert_eq!(terminal.run("echo $?"), "148\n");
+    assert_eq!(
+        terminal.run("jobs"),
+        "[1]+  Stopped                 sleep 10\n"
+    );
+
+    // Brought back, it has the terminal again and gets its Ctrl-C.
+    terminal.send("fg\r");
+    terminal.wait_for_job();
+    terminal.send("\x03");
+    assert!(terminal.read_prompt().starts_with("sleep 10\n"));
+    assert_eq!(terminal.run("echo $?; jobs"), "130\n");
+    terminal.finish("exit\r");
+}
+
+#[test]
+fn interrupt() {
+    let mut terminal = Terminal::start();
+    // At the prompt, the line typed so far is dropped.
+    terminal.send("echo half\x03");
+    assert_eq!(terminal.read_prompt(), "^C\n");
+    assert_eq!(terminal.run("echo $?"), "130\n");
+
+    terminal.send("sleep 10\r");
+    terminal.wait_for_job();
+    terminal.send("\x03");
+    assert_eq!(terminal.read_prompt(), "^C\n");
+    assert_eq!(terminal.run("echo $?; jobs"), "130\n");
+    terminal.finish("exit\r");
+}
+
+#[test]
+fn end_of_input() {
+    let mut terminal = Terminal::start();
+    // On a non-empty line it deletes the character under the cursor.
+    terminal.send("echo xy\x02\x04\r");
+    assert_eq!(terminal.read_prompt(), "x\n");
+    terminal.run("sh -c 'exit 3'");
+    let (output, status) = terminal.finish("\x04");
+    assert_eq!(output, "");
+    assert_eq!(status.code(), Some(3));
+}
+
+#[test]
+fn command_substitution() {
+    assert_eq!(
+        session(&[r#"printf "[%s]" $(printf "a  b") "$(printf "a  b\n\n")""#]),
+        "[a][b][a  b]"
+    );
+    assert_eq!(
+        session(&[r#"printf "[%s]" $(printf "  lead\ttab\n trail  ")"#]),
+        "[lead][tab][trail]"
+    );
+    assert_eq!(
+        session(&["IFS=:", "printf '[%s]' $(echo a:b::c)"]),
+        "[a][b][][c]"
+    );
+    assert_eq!(
+        session(&[r#"printf "[%s]" "$(echo "$(printf "a\n\n")b")" `echo back`"#]),
+        "[ab][back]"
+    );
+    assert_eq!(session(&["x=$(exit 3)", "echo $? $x"]), "3\n");
+}
+
+#[test]
+fn control_flow() {
+    assert_eq!(
+        session(&[
+            "for i in 1 2 3; do for j in a b c; do if [ $j = b ]; then continue; fi; if [ $i = 2 ]; then break 2; fi; echo $i$j; done; done; echo end"
+        ]),
+        "1a\n1c\nend\n"
+    );
+    assert_eq!(
+        session(&[
+            "for i in 1 2; do for j in 1 2; do continue 2; echo no; done; echo no; done; echo ok"
+        ]),
+        "ok\n"
+    );
+    assert_eq!(
+        session(&["if false; then echo if; elif true; then echo elif; else echo else; fi"]),
+        "elif\n"
+    );
+    assert_eq!(
+        session(&[
+            "i=0; until [ $i -ge 2 ]; do i=$((i+1)); done",
+            "for ((j=0; j<3; j++)); do printf $j; done; echo $i",
+        ]),
+        "0122\n"
+    );
+
+    // Quoting a pattern, or part of one, makes it match literally.
+    assert_eq!(
+        session(&[
+            r#"for x in "*" a.c b; do case $x in "*") echo star;; *.c|b) echo glob $x;; esac; done"#
+        ]),
+        "star\nglob a.c\nglob b\n"
+    );
+    assert_eq!(
+        session(&[
+            r#"p="*"; case abc in $p) echo unquoted;; esac; case abc in "$p") echo quoted;; *) echo none;; esac"#
+        ]),
+        "unquoted\nnone\n"
+    );
+
+    // Redirects and pipes apply to the whole compound command.
+    assert_eq!(
+        session(&[
+            "for x in a b; do echo $x; done > /dev/null; if true; then echo err >&2; fi 2>&1; for x in a b; do echo $x; done | wc -l"
+        ]),
+        "err\n2\n"
+    );
+}
+
+#[test]
+fn functions() {
+    assert_eq!(
+        session(&[
+            r#"f(){ local x=in; shift; echo $# "$@"; return 3; }"#,
+            "x=out",
+            "f a b; echo $? $x",
+        ]),
+        "1 b\n3 out\n"
+    );
+    assert_eq!(
+        session(&[
+            r#"function g { echo "$#|$*|$1|$2"; }"#,
+            r#"g "a b" c; echo $#"#
+        ]),
+        "2|a b c|a b|c\n0\n"
+    );
+
+    // The loop around a call is not the function's to leave.
+    let output = run("for i in 1 2 3; do f(){ break; }; f; echo $i; done");
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "1\n2\n3\n");
+    assert!(String::from_utf8_lossy(&output.stderr).contains("break: only meaningful in a"));
+    assert_eq!(
+        stdout("g(){ for j in a b; do continue 2; done; echo in; }; for i in 1 2; do g; done"),
+        "in\nin\n"
+    );
+
+    // `local` is seen by the functions called while it is in scope.
+    assert_eq!(
+        session(&["h(){ echo $x; }; f(){ local x=dyn; h; }; x=top; f; h"]),
+        "dyn\ntop\n"
+    );
+    assert_eq!(
+        session(&[r#"f(){ echo "$*"; }; type f"#]),
+        "f is a function\nf () { echo \"$*\"; }\n"
+    );
+    assert_eq!(
+        session(&["f(){ shift 3; echo $?; return; }; f a b; echo $?"]),
+        "1\n0\n"
+    );
+    assert_eq!(
+        session(&["local x=1; echo $?", "return; echo $?"]),
+        "local: can only be used in a function\n1\n\
+         return: can only `return' from a function or sourced script\n1\n"
+    );
+
+    // Defined in a pipeline, it reads and writes nothing and is forgotten.
+    assert_eq!(
+        stdout("echo hi | g() { :; } | cat; g() { :; } | cat; type g; echo $?"),
+        "1\n"
+    );
+
+    let output = session(&["f(){ echo f; }; x=1; unset -f f x; echo $x; f"]);
+    assert!(output.starts_with("1\n"));
+    assert!(output.contains("f: command not found"));
+}
+
+#[test]
+fn scripts() {
+    let dir = std::env::temp_dir().join(format!("scripts-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let script = dir.join("script.sh");
+    fs::write(
+        &script,
+        "echo \"$0|$#|$1|$2\"\nfalse\nexit\necho not reached\n",
+    )
+    .unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_codecrafters-shell"))
+        .arg(&script)
+        .args(["a", "b c"])
+        .output()
+        .unwrap();
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        format!("{}|2|a|b c\n", script.display())
+    );
+    assert_eq!(output.status.code(), Some(1));
+
+    let output = Command::new(env!("CARGO_BIN_EXE_codecrafters-shell"))
+        .arg(dir.join("missing.sh"))
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(127));
+    assert!(
+        String::from_utf8_lossy(&output.stderr)
+            .ends_with("missing.sh: No such file or directory\n")
+    );
+
+    // After `-c` and its command come `$0` and the positional parameters.
+    let output = Command::new(env!("CARGO_BIN_EXE_codecrafters-shell"))
+        .args(["-c", "echo \"$0|$#|$1\"; exit 4", "name", "x"])
+        .output()
+        .unwrap();
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "name|1|x\n");
+    assert_eq!(output.status.code(), Some(4));
+    assert_eq!(stdout("x=1\necho $x"), "1\n");
+    assert_eq!(run("false").status.code(), Some(1));
+    assert!(stderr("if").contains("syntax error"));
+    assert_eq!(run("if").status.code(), Some(2));
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn piped_input() {
+    let mut shell = Command::new(env!("CARGO_BIN_EXE_codecrafters-shell"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    shell
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b"echo hi\nx=1\nif true; then\n  echo $x\nfi\nsh -c 'exit 5'\n")
+        .unwrap();
+    let output = shell.wait_with_output().unwrap();
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "hi\n1\n");
+    assert_eq!(output.status.code(), Some(5));
+}
+
+#[test]
+fn source() {
+    let dir = std::env::temp_dir().join(format!("source-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let script = dir.join("script.sh");
+    std::fs::write(&script, "echo \"$# $1\"\nreturn 4\necho not reached\n").unwrap();
+    let script = script.display();
+
+    // Arguments stand in for `$1…` while the file runs and no longer.
+    let output = Command::new(env!("CARGO_BIN_EXE_codecrafters-shell"))
+        .args([
+            "-c",
+            &format!(". {script}; echo $? $1; source {script} a b; echo $1"),
+        ])
+        .args(["sh", "x"])
+        .output()
+        .unwrap();
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "1 x\n4 x\n2 a\nx\n"
+    );
+
+    let bad = dir.join("bad.sh");
+    std::fs::write(&bad, "echo ok\n\nnosuchcmd\nif then\n").unwrap();
+    let bad = bad.display();
+    let output = run(&format!("source {bad}; echo $?"));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "ok\n2\n");
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        format!(
+            "{bad}: line 3: nosuchcmd: command not found\n\
+             {bad}: line 4: syntax error near unexpected token `then'\n"
+        )
+    );
+    let output = Command::new(env!("CARGO_BIN_EXE_codecrafters-shell"))
+        .arg(bad.to_string())
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).starts_with(&format!("{bad}: line 3: ")));
+
+    assert!(stderr("source").contains("source: filename argument required"));
+    assert!(stderr(". /nonexistent").contains("/nonexistent: No such file or directory"));
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn startup_file() {
+    let dir = std::env::temp_dir().join(format!("startup-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let rc = dir.join("rc");
+    std::fs::write(&rc, "from=rcfile\n\nnosuchcmd\n").unwrap();
+    let rc = rc.to_str().unwrap();
+    let home_rc = |home: &Path| fs::write(home.join(".codecraftersrc"), "from=home\n").unwrap();
+
+    let show = "echo \"[$from]\"";
+    // What the shell printed on starting up, and then for `show`.
+    let start = |args: &[&str], envs: &[(&str, &str)]| {
+        let (mut terminal, output) = Terminal::start_with(args, envs, home_rc);
+        (output, terminal.run(show))
+    };
+    assert_eq!(start(&[], &[]).1, "[home]\n");
+    assert_eq!(
+        start(&[], &[("SHELLRC", rc)]),
+        (
+            format!("{rc}: line 3: nosuchcmd: command not found\n"),
+            "[rcfile]\n".to_string()
+        )
+    );
+    assert_eq!(start(&["--rcfile", rc], &[]).1, "[rcfile]\n");
+    assert_eq!(start(&["--norc"], &[("SHELLRC", rc)]).1, "[]\n");
+
+    // `-c` and scripts never read it.
+    home_rc(&dir);
+    let output = Command::new(env!("CARGO_BIN_EXE_codecrafters-shell"))
+        .args(["-c", show])
+        .env("HOME", &dir)
+        .env("SHELLRC", rc)
+        .output()
+        .unwrap();
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "[]\n");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_codecrafters-shell"))
+        .arg("--rcfile")
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(2));
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn subshells() {
+    assert_eq!(
+        stdout("cd /tmp; x=0; (cd /; x=1); pwd; echo $x"),
+        "/tmp\n0\n"
+    );
+    assert_eq!(stdout("(exit 3); echo $?"), "3\n");
+    assert_eq!(stdout("(echo a; echo b) | wc -l"), "2\n");
+}
+
+#[test]
+fn groups() {
+    let dir = std::env::temp_dir().join(format!("groups-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+
+    // Unlike a subshell, a group runs in the shell itself.
+    assert_eq!(
+        stdout("cd /tmp; x=0; { cd /; x=1; }; pwd; echo $x"),
+        "/\n1\n"
+    );
+    assert_eq!(
+        stdout(&format!(
+            "cd {}; {{ echo a; echo b >&2; }} > out 2>&1; cat out",
+            dir.display()
+        )),
+        "a\nb\n"
+    );
+    assert_eq!(stdout("{ echo a; echo b; } | wc -l"), "2\n");
+    assert_eq!(
+        stdout("{ false; }; echo $?; { true; } | { false; }; echo $?"),
+        "1\n1\n"
+    );
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn pipelines() {
+    // The builtin at the end closes its pipe, so `yes` stops.
+    assert_eq!(stdout("yes | echo hi; echo done"), "hi\ndone\n");
+    assert_eq!(stdout("echo a b | wc -w"), "2\n");
+    assert_eq!(
+        stdout("false | true; echo $?; true | false; echo $?"),
+        "0\n1\n"
+    );
+}
+
+#[test]
+fn redirections() {
+    let dir = std::env::temp_dir().join(format!("redirections-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let both = "sh -c 'echo out; echo err >&2'";
+
+    // Applied in order: stderr follows stdout to the file only when it is
+    // duplicated after stdout was redirected.
+    let output = run(&format!(
+        "cd {}; {both} > f 2>&1; cat f; {both} 2>&1 > g; cat g",
+        dir.display()
+    ));
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "out\nerr\nerr\nout\n"
+    );
+    assert_eq!(String::from_utf8_lossy(&output.stderr), "");
+
+    let output = run("echo hi >&-; echo $?");
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "1\n");
+    assert!(
+        String::from_utf8_lossy(&output.stderr).contains("echo: write error: Bad file descriptor")
+    );
+
+    assert_eq!(
+        stdout("{ echo a >&12; } 12>&1; f(){ echo b >&3; }; f 3>&1 | cat"),
+        "a\nb\n"
+    );
+    // Descriptors 10 and 11 swap what the shell would otherwise hold there.
+    assert_eq!(stdout("echo hi | cat /dev/fd/11 11<&0 10>&1"), "hi\n");
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn here_docs() {
+    assert_eq!(stdout("{ cat; } <<< hi | cat"), "hi\n");
+    assert_eq!(stdout("(cat) <<< hi"), "hi\n");
+    assert_eq!(stdout("f(){ cat; }; f <<< hi | cat"), "hi\n");
+    assert_eq!(stdout("{ cat; } <<EOF | cat\none\nEOF"), "one\n");
+    assert_eq!(stdout("(cat) <<EOF\ntwo\nEOF"), "two\n");
+    assert_eq!(stdout("f(){ cat; }; f <<EOF | cat\nthree\nEOF"), "three\n");
+    // More than a pipe holds.
+    assert_eq!(
+        stdout("x=$(printf %070000d 0); cat <<< $x | wc -c"),
+        "70001\n"
+    );
+}
+
+#[test]
+fn spawn_errors() {
+    let output = run("./nonexistent");
+    assert_eq!(output.status.code(), Some(127));
+    assert!(
+        String::from_utf8_lossy(&output.stderr)
+            .ends_with(": line 1: ./nonexistent: No such file or directory\n")
+    );
+    let output = run("/etc/passwd");
+    assert_eq!(output.status.code(), Some(126));
+    assert!(
+        String::from_utf8_lossy(&output.stderr).ends_with(": /etc/passwd: Permission denied\n")
+    );
+}
+
+#[test]
+fn bind() {
+    let (mut terminal, _) = Terminal::start_with(&[], &[], |home| {
+        fs::write(home.join("keys"), "\"\\C-xk\": kill-line\nbogus line\n").unwrap();
+    });
+    assert_eq!(
+        terminal.run("bind -q beginning-of-line; echo $?"),
+        "beginning-of-line can be invoked via \"\\C-a\", \"\\e[H\".\n0\n"
+    );
+    assert_eq!(
+        terminal.run("bind -u beginning-of-line; bind -q beginning-of-line; echo $?"),
+        "beginning-of-line is not bound to any keys.\n1\n"
+    );
+    assert_eq!(
+        terminal.run("bind -p | grep beginning-of-line"),
+        "# beginning-of-line (not bound)\n"
+    );
+    assert_eq!(
+        terminal.run("bind -P | grep kill-line"),
+        "kill-line can be found on \"\\C-k\".\n"
+    );
+    assert_eq!(
+        terminal.run("bind -f $HOME/keys; echo $?; bind -q kill-line"),
+        format!(
+            "bind: {}/keys: line 2: bogus line: unknown key name\n1\n\
+             kill-line can be invoked via \"\\C-k\", \"\\C-xk\".\n",
+            terminal.home.display()
+        )
+    );
+
+    // A macro types its text as if it came from the keyboard.
+    terminal.run("bind '\"\\C-xe\": \"echo macro\"'");
+    assert_eq!(
+        terminal.run("bind -s; bind -S"),
+        "\"\\C-xe\": \"echo macro\"\n\\C-xe outputs echo macro\n"
+    );
+    terminal.send("\x18e\r");
+    assert_eq!(terminal.read_prompt(), "macro\n");
+    assert_eq!(terminal.run("bind -r '\\C-xe'; bind -s"), "");
+
+    assert_eq!(
+        terminal.run("bind -q nosuch; echo $?"),
+        "bind: nosuch: unknown function name\n1\n"
+    );
+    assert_eq!(
+        terminal.run("bind -f $HOME/missing; echo $?"),
+        format!(
+            "bind: {}/missing: No such file or directory\n1\n",
+            terminal.home.display()
+        )
+    );
+    assert_eq!(
+        terminal.run("bind -z; echo $?"),
+        "bind: -z: invalid option\n1\n"
+    );
+    assert_eq!(
+        terminal.run("bind -q; echo $?"),
+        "bind: -q: option requires an argument\n1\n"
+    );
+    terminal.finish("exit\r");
+}